@@ -0,0 +1,200 @@
+use std::{io::Read, path::Path};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    hash::Hash,
+    ident::{self, Role},
+    index::Index,
+    object::{Commit, Object, Tree},
+    refs::{self, Expect, Transaction},
+    repository::Repository,
+    HashObject,
+};
+
+const TEMPLATE: &str = "
+# Please enter the commit message for your changes. Lines starting
+# with '#' will be ignored, and an empty message aborts the commit.
+#
+";
+
+/// how a commit message is tidied up before it is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cleanup {
+    /// trailing whitespace and surplus blank lines are removed
+    Whitespace,
+    /// like `Whitespace`, and `#` comment lines are removed
+    Strip,
+}
+
+/// tidies up a message like `git stripspace`, returning an empty string if nothing is left
+fn cleanup(message: &str, mode: Cleanup) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+    for line in message.lines() {
+        if mode == Cleanup::Strip && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && !out.is_empty() {
+            out.push('\n');
+        }
+        blank = false;
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// the editor from `GIT_EDITOR`, `VISUAL` or `EDITOR`
+fn editor() -> String {
+    ["GIT_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|e| !e.is_empty()))
+        .unwrap_or_else(|| "vi".to_owned())
+}
+
+/// lets the user edit `path`, the editor is run by the shell like git does
+fn edit(path: &Path) -> anyhow::Result<()> {
+    let editor = editor();
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status()
+        .with_context(|| format!("unable to start editor '{editor}'"))?;
+    if !status.success() {
+        bail!("There was a problem with the editor '{editor}'.");
+    }
+    Ok(())
+}
+
+/// commits the index on top of `HEAD` and moves the branch `HEAD` points at, or `HEAD` itself
+pub fn commit(
+    repo: &Repository,
+    messages: &[String],
+    file: Option<&Path>,
+    amend: bool,
+    allow_empty: bool,
+    author: Option<&str>,
+) -> anyhow::Result<()> {
+    let (branch, head) = refs::follow(repo, "HEAD")?;
+    let head = match head {
+        Some(head) => {
+            let Object::Commit(commit) = repo.read_object(&head)? else {
+                bail!("HEAD is not a commit");
+            };
+            Some((head, commit))
+        }
+        None if amend => bail!("You have nothing to amend."),
+        None => None,
+    };
+
+    let index = Index::load(repo)?;
+    let tree = Tree::write_index(repo, &index, b"", false)?;
+    let parents = match &head {
+        Some((_, commit)) if amend => commit.parents().to_vec(),
+        Some((hash, _)) => vec![hash.clone()],
+        None => vec![],
+    };
+    if !amend && !allow_empty {
+        let unchanged = match &head {
+            Some((_, commit)) => *commit.tree() == tree,
+            None => index.entries().is_empty(),
+        };
+        if unchanged {
+            bail!("nothing to commit, working tree clean");
+        }
+    }
+
+    let edit_path = repo.git_dir().join("COMMIT_EDITMSG");
+    let message = match (file, messages) {
+        (Some(_), [_, ..]) => bail!("Option -m cannot be combined with -F"),
+        (Some(file), []) if file == Path::new("-") => {
+            let mut message = String::new();
+            std::io::stdin().read_to_string(&mut message)?;
+            cleanup(&message, Cleanup::Whitespace)
+        }
+        (Some(file), []) => {
+            let message = std::fs::read_to_string(file)
+                .with_context(|| format!("could not read log file '{}'", file.display()))?;
+            cleanup(&message, Cleanup::Whitespace)
+        }
+        (None, [_, ..]) => cleanup(&messages.join("\n\n"), Cleanup::Whitespace),
+        (None, []) => {
+            let previous = match &head {
+                Some((_, commit)) if amend => String::from_utf8_lossy(commit.message()).into(),
+                _ => String::new(),
+            };
+            std::fs::write(&edit_path, format!("{previous}{TEMPLATE}"))?;
+            edit(&edit_path)?;
+            cleanup(&std::fs::read_to_string(&edit_path)?, Cleanup::Strip)
+        }
+    };
+    if message.is_empty() {
+        bail!("Aborting commit due to empty commit message.");
+    }
+    std::fs::write(&edit_path, &message)?;
+
+    let author = match (&head, author) {
+        (Some((_, commit)), None) if amend => commit.author().clone(),
+        (_, author) => ident::event(repo, Role::Author, author)?,
+    };
+    let committer = ident::event(repo, Role::Committer, None)?;
+    let commit = Commit::new(repo, tree, &message, author, committer, parents)?;
+    let root_commit = commit.parents().is_empty();
+    let cmd = HashObject::new(Object::Commit(commit));
+    cmd.write(repo)?;
+    let hash: Hash = cmd.hash();
+
+    let mut transaction = Transaction::default();
+    let kind = match (amend, root_commit) {
+        (true, _) => "commit (amend)",
+        (false, true) => "commit (initial)",
+        (false, false) => "commit",
+    };
+    let subject = message.lines().next().unwrap_or_default();
+    transaction.message = format!("{kind}: {subject}");
+    let old = match &head {
+        Some((head, _)) => Expect::Value(head.clone()),
+        None => Expect::Missing,
+    };
+    transaction.update("HEAD", Some(hash.clone()), old);
+    transaction.commit(repo)?;
+
+    let branch = match branch.as_str() {
+        "HEAD" => "detached HEAD",
+        branch => refs::shorten(branch),
+    };
+    let root_commit = match root_commit {
+        true => " (root-commit)",
+        false => "",
+    };
+    let subject = message.lines().next().unwrap_or_default();
+    println!(
+        "[{branch}{root_commit} {}] {subject}",
+        &hash.to_string()[..7]
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleanup_modes() {
+        let message = "\n\nsubject  \n\n\n# comment\nbody\t\n\n";
+        assert_eq!(
+            cleanup(message, Cleanup::Whitespace),
+            "subject\n\n# comment\nbody\n"
+        );
+        assert_eq!(cleanup(message, Cleanup::Strip), "subject\n\nbody\n");
+        assert_eq!(cleanup("# only a comment\n", Cleanup::Strip), "");
+    }
+}
@@ -0,0 +1,130 @@
+use std::{io::ErrorKind, path::Path};
+
+use crate::hash::Hash;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const HASH_LEN: usize = 20;
+const CHUNK_OID_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_OID_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_COMMIT_DATA: u32 = u32::from_be_bytes(*b"CDAT");
+/// the tree, two parent positions and the generation and date of each commit
+const COMMIT_DATA_LEN: usize = HASH_LEN + 16;
+
+#[derive(Debug, derive_more::Display, Clone, thiserror::Error)]
+pub enum CommitGraphError {
+    #[display(fmt = "commit-graph file is corrupt")]
+    FormatError,
+    #[display(fmt = "unsupported commit-graph version {_0}")]
+    UnsupportedVersion(u8),
+    #[display(fmt = "commit-graph checksum mismatch")]
+    ChecksumMismatch,
+}
+
+/// the generation numbers of `objects/info/commit-graph`, which git writes to speed up walks.
+///
+/// a commit's generation is one more than the largest of its parents', so a commit can only
+/// be an ancestor of commits with a larger generation
+#[derive(Debug)]
+pub struct CommitGraph {
+    hashes: Vec<Hash>,
+    /// the topological levels of the commits, which are generation numbers
+    generations: Vec<u32>,
+}
+
+fn be_u32(s: &[u8], at: usize) -> Result<u32, CommitGraphError> {
+    let bytes = s.get(at..at + 4).ok_or(CommitGraphError::FormatError)?;
+    Ok(u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+}
+
+fn be_u64(s: &[u8], at: usize) -> Result<u64, CommitGraphError> {
+    let bytes = s.get(at..at + 8).ok_or(CommitGraphError::FormatError)?;
+    Ok(u64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+}
+
+impl TryFrom<&[u8]> for CommitGraph {
+    type Error = CommitGraphError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        if s.len() < 8 + HASH_LEN {
+            return Err(CommitGraphError::FormatError);
+        }
+        let (content, checksum) = s.split_at(s.len() - HASH_LEN);
+        if Hash::from_bytes(content).as_bytes() != checksum {
+            return Err(CommitGraphError::ChecksumMismatch);
+        }
+        let Some(header) = content.strip_prefix(SIGNATURE) else {
+            return Err(CommitGraphError::FormatError);
+        };
+        match header[..4] {
+            // version 1 for SHA-1 hashes, without base graphs
+            [1, 1, _, 0] => {}
+            [1, ..] => return Err(CommitGraphError::FormatError),
+            [version, ..] => return Err(CommitGraphError::UnsupportedVersion(version)),
+            [] => return Err(CommitGraphError::FormatError),
+        }
+
+        // a table of chunk ids and offsets, ending with id 0 at the end of the last chunk
+        let chunks = header[2] as usize;
+        let mut table = vec![];
+        for i in 0..=chunks {
+            let at = 8 + 12 * i;
+            table.push((be_u32(content, at)?, be_u64(content, at + 4)? as usize));
+        }
+        let chunk = |id: u32| -> Result<&[u8], CommitGraphError> {
+            let i = table
+                .iter()
+                .take(chunks)
+                .position(|&(chunk, _)| chunk == id)
+                .ok_or(CommitGraphError::FormatError)?;
+            content
+                .get(table[i].1..table[i + 1].1)
+                .ok_or(CommitGraphError::FormatError)
+        };
+
+        let fanout = chunk(CHUNK_OID_FANOUT)?;
+        let count = be_u32(fanout, 255 * 4)? as usize;
+        let lookup = chunk(CHUNK_OID_LOOKUP)?;
+        let data = chunk(CHUNK_COMMIT_DATA)?;
+        if lookup.len() != count * HASH_LEN || data.len() != count * COMMIT_DATA_LEN {
+            return Err(CommitGraphError::FormatError);
+        }
+        let hashes: Vec<Hash> = lookup
+            .chunks(HASH_LEN)
+            .map(|hash| Hash::from_raw(hash).expect("has the length of a hash"))
+            .collect();
+        if !hashes.windows(2).all(|w| w[0] < w[1]) {
+            return Err(CommitGraphError::FormatError);
+        }
+        // the generation is the top 30 bits after the tree and parents
+        let generations = (0..count)
+            .map(|i| be_u32(data, i * COMMIT_DATA_LEN + HASH_LEN + 8).map(|n| n >> 2))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            hashes,
+            generations,
+        })
+    }
+}
+
+impl CommitGraph {
+    /// reads the commit-graph of a git directory, `None` if there is none.
+    ///
+    /// the graph only speeds up walks, so one that cannot be read is warned about and ignored
+    pub fn open(git_dir: &Path) -> Option<Self> {
+        let path = git_dir.join("objects/info/commit-graph");
+        let graph = match std::fs::read(path) {
+            Ok(data) => Self::try_from(data.as_slice()).map_err(anyhow::Error::from),
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => Err(e.into()),
+        };
+        graph
+            .map_err(|e| eprintln!("warning: ignoring the commit-graph: {e}"))
+            .ok()
+    }
+
+    /// the generation of a commit, if the graph has it
+    pub fn generation(&self, hash: &Hash) -> Option<u32> {
+        let i = self.hashes.binary_search(hash).ok()?;
+        Some(self.generations[i])
+    }
+}
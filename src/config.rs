@@ -0,0 +1,933 @@
+use std::{
+    io::{ErrorKind, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+
+use crate::{pathspec::wildmatch, IoErrorExt, PathBufExt};
+
+/// includes are followed this deep, to stop include loops
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// where a config file is, from the least to the most specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+    Worktree,
+}
+
+/// a value of a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// normalized like `section.subsection.name`, with only the subsection keeping its case
+    pub key: String,
+    /// `None` for a key without `=`, which is true as a boolean
+    pub value: Option<String>,
+    /// the file the entry is from
+    pub origin: PathBuf,
+    pub scope: Scope,
+}
+
+/// how `config --type` interprets and prints values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Type {
+    Bool,
+    Int,
+    Path,
+}
+
+/// the settings of all config files, later entries override earlier ones
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+    /// the git directory of the repository, for `includeIf.gitdir:`
+    git_dir: Option<PathBuf>,
+}
+
+/// lowercases the section and the name of a key, the subsection is case sensitive
+fn normalize_key(key: &str) -> String {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    match rest.rsplit_once('.') {
+        Some((subsection, name)) => format!(
+            "{}.{subsection}.{}",
+            section.to_lowercase(),
+            name.to_lowercase()
+        ),
+        None => format!("{}.{}", section.to_lowercase(), rest.to_lowercase()),
+    }
+}
+
+/// splits a key given on the command line into its section and name, checking both
+fn split_key(key: &str) -> anyhow::Result<(&str, &str)> {
+    let Some((section, name)) = key.rsplit_once('.') else {
+        bail!("key does not contain a section: {key}");
+    };
+    let (base, _) = section.split_once('.').unwrap_or((section, ""));
+    let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    let valid_base =
+        !base.is_empty() && base.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid_name || !valid_base {
+        bail!("invalid key: {key}");
+    }
+    Ok((section, name))
+}
+
+/// expands a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// parses a boolean like git, where a key without a value is true
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let Some(value) = value else {
+        return Some(true);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        _ => parse_int(value).map(|i| i != 0),
+    }
+}
+
+/// parses an integer with an optional `k`, `m` or `g` suffix for powers of 1024
+pub fn parse_int(value: &str) -> Option<i64> {
+    let (digits, factor) = match value.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&value[..value.len() - 1], 1 << 10),
+        Some('m') => (&value[..value.len() - 1], 1 << 20),
+        Some('g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.trim().parse::<i64>().ok()?.checked_mul(factor)
+}
+
+impl Config {
+    /// the files of a scope, in the order they are read. outside of a repository, there are
+    /// no local and worktree files
+    fn paths(scope: Scope, git_dir: Option<&Path>) -> Vec<PathBuf> {
+        let var = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+        match scope {
+            Scope::System if var("GIT_CONFIG_NOSYSTEM").is_some() => vec![],
+            Scope::System => vec![var("GIT_CONFIG_SYSTEM")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| "/etc/gitconfig".into())],
+            Scope::Global => {
+                if let Some(global) = var("GIT_CONFIG_GLOBAL") {
+                    return vec![global.into()];
+                }
+                let home = var("HOME").map(PathBuf::from);
+                let xdg = var("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home.as_ref().map(|home| home.join(".config")));
+                xdg.map(|xdg| xdg.push_dir("git").push_dir("config"))
+                    .into_iter()
+                    .chain(home.map(|home| home.push_dir(".gitconfig")))
+                    .collect()
+            }
+            Scope::Local => git_dir.map(|dir| dir.join("config")).into_iter().collect(),
+            Scope::Worktree => git_dir
+                .map(|dir| dir.join("config.worktree"))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// the file `config` writes to for a scope
+    pub fn write_path(scope: Scope, git_dir: Option<&Path>) -> anyhow::Result<PathBuf> {
+        if matches!(scope, Scope::Local | Scope::Worktree) && git_dir.is_none() {
+            bail!("not in a git directory");
+        }
+        let paths = Self::paths(scope, git_dir);
+        // the XDG file is only written to if it is the only one there is
+        let path = match paths.as_slice() {
+            [xdg, home] if scope == Scope::Global && xdg.exists() && !home.exists() => xdg,
+            [.., last] => last,
+            [] => bail!("no config file for the {scope:?} scope"),
+        };
+        Ok(path.clone())
+    }
+
+    fn new(git_dir: Option<&Path>) -> Self {
+        Self {
+            git_dir: git_dir.map(Path::to_owned),
+            ..Self::default()
+        }
+    }
+
+    /// reads the config files of all scopes of the repository at `git_dir`, missing files are
+    /// skipped
+    pub fn load(git_dir: &Path) -> anyhow::Result<Self> {
+        let mut config = Self::new(Some(git_dir));
+        for scope in [Scope::System, Scope::Global, Scope::Local] {
+            config.read_scope(scope)?;
+        }
+        if config.get_bool("extensions.worktreeConfig")? == Some(true) {
+            config.read_scope(Scope::Worktree)?;
+        }
+        Ok(config)
+    }
+
+    /// reads the system and global files, for commands that run outside of a repository
+    pub fn load_global() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        config.read_scope(Scope::System)?;
+        config.read_scope(Scope::Global)?;
+        Ok(config)
+    }
+
+    /// reads only the files of one scope
+    pub fn load_scope(scope: Scope, git_dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut config = Self::new(git_dir);
+        config.read_scope(scope)?;
+        Ok(config)
+    }
+
+    /// reads a single file, as for `config --file`
+    pub fn load_file(path: &Path, git_dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut config = Self::new(git_dir);
+        config.read_file(path, Scope::Local, 0)?;
+        Ok(config)
+    }
+
+    fn read_scope(&mut self, scope: Scope) -> anyhow::Result<()> {
+        for path in Self::paths(scope, self.git_dir.as_deref()) {
+            self.read_file(&path, scope, 0)?;
+        }
+        Ok(())
+    }
+
+    fn read_file(&mut self, path: &Path, scope: Scope, depth: usize) -> anyhow::Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            bail!(
+                "exceeded maximum include depth ({MAX_INCLUDE_DEPTH}) while including {}",
+                path.display()
+            );
+        }
+        let Some(content) = std::fs::read(path)
+            .map(Some)
+            .ignore(ErrorKind::NotFound, None)?
+        else {
+            return Ok(());
+        };
+        let items = parse(&content).with_context(|| format!("in file {}", path.display()))?;
+        for item in items {
+            let Item::Entry { key, value, .. } = item else {
+                continue;
+            };
+            let include = match (key.split_once('.'), &value) {
+                (Some(("include", "path")), Some(include)) => Some(include.clone()),
+                (Some(("includeif", rest)), Some(include)) => {
+                    let condition = rest.strip_suffix(".path");
+                    match condition
+                        .is_some_and(|c| condition_holds(c, path, self.git_dir.as_deref()))
+                    {
+                        true => Some(include.clone()),
+                        false => None,
+                    }
+                }
+                _ => None,
+            };
+            self.entries.push(Entry {
+                key,
+                value,
+                origin: path.to_owned(),
+                scope,
+            });
+            if let Some(include) = include {
+                let mut included = expand_home(&include);
+                if included.is_relative() {
+                    let dir = path.parent().unwrap_or(Path::new(""));
+                    included = dir.join(included);
+                }
+                self.read_file(&included, scope, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// all entries of `key`, in the order they were read
+    pub fn get_all<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a Entry> + 'a {
+        let key = normalize_key(key);
+        self.entries.iter().filter(move |e| e.key == key)
+    }
+
+    fn last(&self, key: &str) -> Option<&Entry> {
+        self.get_all(key).last()
+    }
+
+    /// the last value of `key`, where a key without a value is empty
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.last(key)
+            .map(|entry| entry.value.as_deref().unwrap_or_default())
+    }
+
+    pub fn get_bool(&self, key: &str) -> anyhow::Result<Option<bool>> {
+        let Some(entry) = self.last(key) else {
+            return Ok(None);
+        };
+        let value = entry.value.as_deref();
+        match parse_bool(value) {
+            Some(b) => Ok(Some(b)),
+            None => bail!(
+                "bad boolean config value '{}' for '{key}'",
+                value.unwrap_or_default()
+            ),
+        }
+    }
+
+    /// a path, with a leading `~/` expanded to the home directory
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).map(expand_home)
+    }
+}
+
+/// whether the condition of an `includeIf` section holds, for the file at `origin` of the
+/// repository at `git_dir`
+fn condition_holds(condition: &str, origin: &Path, git_dir: Option<&Path>) -> bool {
+    let (pattern, icase) = if let Some(pattern) = condition.strip_prefix("gitdir:") {
+        (pattern, false)
+    } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+        (pattern, true)
+    } else {
+        return false;
+    };
+    let Some(Ok(gitdir)) = git_dir.map(Path::canonicalize) else {
+        return false;
+    };
+
+    let mut pattern = if let Some(rest) = pattern.strip_prefix("./") {
+        let dir = origin.parent().unwrap_or(Path::new(""));
+        let dir = dir.canonicalize().unwrap_or(dir.to_owned());
+        dir.join(rest).to_string_lossy().into_owned()
+    } else if pattern.starts_with("~/") {
+        expand_home(pattern).to_string_lossy().into_owned()
+    } else if !pattern.starts_with('/') {
+        format!("**/{pattern}")
+    } else {
+        pattern.to_owned()
+    };
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+
+    let gitdir = gitdir.to_string_lossy();
+    match icase {
+        true => wildmatch(
+            pattern.to_lowercase().as_bytes(),
+            gitdir.to_lowercase().as_bytes(),
+            true,
+        ),
+        false => wildmatch(pattern.as_bytes(), gitdir.as_bytes(), true),
+    }
+}
+
+/// a piece of a config file, with the lines it spans
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    /// a section header like `[section "subsection"]`, named `section.subsection`
+    Section { name: String, line: usize },
+    Entry {
+        key: String,
+        value: Option<String>,
+        lines: Range<usize>,
+    },
+}
+
+/// reads a config file in git's INI dialect
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error<T>(&self) -> anyhow::Result<T> {
+        bail!("bad config line {}", self.line + 1)
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == b'\n' {
+                break;
+            }
+        }
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\r')) {
+            self.next();
+        }
+    }
+
+    fn string(&self, bytes: Vec<u8>) -> anyhow::Result<String> {
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => self.error(),
+        }
+    }
+
+    /// `[section]`, `[section "subsection"]` or the legacy `[section.subsection]`
+    fn section(&mut self) -> anyhow::Result<String> {
+        self.next();
+        let mut name = vec![];
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == b'-' || c == b'.') {
+                break;
+            }
+            name.push(c.to_ascii_lowercase());
+            self.next();
+        }
+        if name.is_empty() {
+            return self.error();
+        }
+        if matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.skip_blanks();
+            if self.next() != Some(b'"') {
+                return self.error();
+            }
+            name.push(b'.');
+            loop {
+                match self.next() {
+                    Some(b'"') => break,
+                    Some(b'\\') => match self.next() {
+                        Some(b'\n') | None => return self.error(),
+                        Some(c) => name.push(c),
+                    },
+                    Some(b'\n') | None => return self.error(),
+                    Some(c) => name.push(c),
+                }
+            }
+        }
+        if self.next() != Some(b']') {
+            return self.error();
+        }
+        self.string(name)
+    }
+
+    /// the value after `=`, up to the end of the line, including continuation lines
+    fn value(&mut self) -> anyhow::Result<String> {
+        let mut value = vec![];
+        let mut quoted = false;
+        let mut spaces = 0;
+        loop {
+            let c = match self.next() {
+                None | Some(b'\n') if quoted => return self.error(),
+                None | Some(b'\n') => break,
+                Some(c) => c,
+            };
+            if !quoted && (c == b'#' || c == b';') {
+                self.skip_line();
+                break;
+            }
+            if !quoted && c.is_ascii_whitespace() {
+                // whitespace is only kept between words
+                if !value.is_empty() {
+                    spaces += 1;
+                }
+                continue;
+            }
+            value.extend(std::iter::repeat_n(b' ', spaces));
+            spaces = 0;
+            match c {
+                b'"' => quoted = !quoted,
+                b'\\' => match self.next() {
+                    Some(b'\n') => {}
+                    Some(b'n') => value.push(b'\n'),
+                    Some(b't') => value.push(b'\t'),
+                    Some(b'b') => value.push(0x08),
+                    Some(c @ (b'"' | b'\\')) => value.push(c),
+                    _ => return self.error(),
+                },
+                c => value.push(c),
+            }
+        }
+        self.string(value)
+    }
+
+    fn items(mut self) -> anyhow::Result<Vec<Item>> {
+        let mut items = vec![];
+        let mut section: Option<String> = None;
+        while let Some(c) = self.peek() {
+            match c {
+                c if c.is_ascii_whitespace() => {
+                    self.next();
+                }
+                b'#' | b';' => self.skip_line(),
+                b'[' => {
+                    let line = self.line;
+                    let name = self.section()?;
+                    items.push(Item::Section {
+                        name: name.clone(),
+                        line,
+                    });
+                    section = Some(name);
+                }
+                c if c.is_ascii_alphabetic() => {
+                    let Some(section) = &section else {
+                        return self.error();
+                    };
+                    let start = self.line;
+                    let mut name = vec![];
+                    while let Some(c) = self.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == b'-') {
+                            break;
+                        }
+                        name.push(c.to_ascii_lowercase());
+                        self.next();
+                    }
+                    let name = self.string(name)?;
+                    self.skip_blanks();
+                    let value = match self.peek() {
+                        Some(b'=') => {
+                            self.next();
+                            Some(self.value()?)
+                        }
+                        None | Some(b'\n') | Some(b'#') | Some(b';') => {
+                            self.skip_line();
+                            None
+                        }
+                        Some(_) => return self.error(),
+                    };
+                    // a last line without a newline still counts
+                    let end = match self.s[..self.pos].ends_with(b"\n") {
+                        true => self.line,
+                        false => self.line + 1,
+                    };
+                    items.push(Item::Entry {
+                        key: format!("{section}.{name}"),
+                        value,
+                        lines: start..end,
+                    });
+                }
+                _ => return self.error(),
+            }
+        }
+        Ok(items)
+    }
+}
+
+fn parse(content: &[u8]) -> anyhow::Result<Vec<Item>> {
+    Parser {
+        s: content,
+        pos: 0,
+        line: 0,
+    }
+    .items()
+}
+
+/// quotes a value so that it reads back the same
+fn quote(value: &str) -> String {
+    let mut quoted = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    let needs_quotes = value.starts_with([' ', '\t'])
+        || value.ends_with([' ', '\t'])
+        || value.contains(['#', ';']);
+    match needs_quotes {
+        true => format!("\"{quoted}\""),
+        false => quoted,
+    }
+}
+
+/// a section header for the section part of a key
+fn header(section: &str) -> String {
+    match section.split_once('.') {
+        Some((name, subsection)) => {
+            let subsection = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("[{name} \"{subsection}\"]\n")
+        }
+        None => format!("[{section}]\n"),
+    }
+}
+
+/// the lines of a config file, which are edited in place to keep comments and layout
+struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<String>,
+    items: Vec<Item>,
+}
+
+impl ConfigFile {
+    fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let content = std::fs::read(&path).ignore(ErrorKind::NotFound, vec![])?;
+        let items = parse(&content).with_context(|| format!("in file {}", path.display()))?;
+        let content = String::from_utf8(content).context("config file is not UTF-8")?;
+        let lines = content.split_inclusive('\n').map(str::to_owned).collect();
+        Ok(Self { path, lines, items })
+    }
+
+    /// the lines of the entries of `key`
+    fn find(&self, key: &str) -> Vec<Range<usize>> {
+        let key = normalize_key(key);
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Entry { key: k, lines, .. } if *k == key => Some(lines.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// sets `key`, returning false without changing anything if it has multiple values
+    fn set(&mut self, key: &str, value: &str) -> anyhow::Result<bool> {
+        let (section, name) = split_key(key)?;
+        let line = format!("\t{name} = {}\n", quote(value));
+        let existing = self.find(key);
+        match existing.as_slice() {
+            [lines] => {
+                self.lines.splice(lines.clone(), [line]);
+            }
+            [] => {
+                let section_name = normalize_key(&format!("{section}.x"));
+                let section_name = section_name.strip_suffix(".x").unwrap_or_default();
+                // after the last entry of the last matching section
+                let mut at = None;
+                for item in &self.items {
+                    match item {
+                        Item::Section { name, line } => {
+                            at = (name == section_name).then_some(line + 1)
+                        }
+                        Item::Entry { lines, .. } => at = at.map(|_| lines.end),
+                    }
+                }
+                if let Some(last) = self.lines.last_mut() {
+                    if !last.ends_with('\n') {
+                        last.push('\n');
+                    }
+                }
+                match at {
+                    Some(at) => self.lines.insert(at, line),
+                    None => self.lines.extend([header(section), line]),
+                }
+            }
+            _ => return Ok(false),
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn unset(&mut self, key: &str, all: bool) -> anyhow::Result<bool> {
+        split_key(key)?;
+        let existing = self.find(key);
+        if existing.is_empty() {
+            return Ok(false);
+        }
+        if existing.len() > 1 && !all {
+            bail!("{key} has multiple values");
+        }
+        for lines in existing.into_iter().rev() {
+            self.lines.drain(lines);
+        }
+        self.remove_empty_section(key)?;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// removes the header of the section of `key` if nothing but blank lines are left in it
+    fn remove_empty_section(&mut self, key: &str) -> anyhow::Result<()> {
+        let section = normalize_key(key);
+        let section = section.rsplit_once('.').map_or("", |(section, _)| section);
+        let items = parse(self.lines.concat().as_bytes())?;
+        let empty = items.iter().enumerate().rev().find_map(|(i, item)| {
+            let Item::Section { name, line } = item else {
+                return None;
+            };
+            let end = match items.get(i + 1) {
+                Some(Item::Section { line, .. }) => *line,
+                Some(Item::Entry { .. }) => return None,
+                None => self.lines.len(),
+            };
+            let blank = self.lines[line + 1..end]
+                .iter()
+                .all(|l| l.trim().is_empty());
+            (name == section && blank && self.lines[*line].trim().ends_with(']')).then_some(*line)
+        });
+        if let Some(line) = empty {
+            self.lines.remove(line);
+        }
+        Ok(())
+    }
+
+    /// writes the file through a `.lock` file
+    fn save(&self) -> anyhow::Result<()> {
+        let mut lock = self.path.clone().into_os_string();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        let mut f = std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+            .with_context(|| format!("could not lock config file {}", lock.display()))?;
+        let written = f
+            .write_all(self.lines.concat().as_bytes())
+            .and_then(|_| f.sync_all());
+        if let Err(e) = written {
+            std::fs::remove_file(&lock)?;
+            Err(e)?;
+        }
+        std::fs::rename(&lock, &self.path)?;
+        Ok(())
+    }
+}
+
+/// sets `key` in the config file at `path`, which is created if it does not exist
+pub fn set(path: &Path, key: &str, value: &str) -> anyhow::Result<()> {
+    if !ConfigFile::open(path.to_owned())?.set(key, value)? {
+        bail!("cannot overwrite multiple values of {key} with a single value");
+    }
+    Ok(())
+}
+
+/// what `config` does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Get,
+    GetAll,
+    Set,
+    Unset,
+    UnsetAll,
+    List,
+}
+
+/// the `config` command
+#[derive(Debug)]
+pub struct ConfigCommand {
+    pub action: Action,
+    /// restricts reading and writing to one scope
+    pub scope: Option<Scope>,
+    /// reads and writes only this file
+    pub file: Option<PathBuf>,
+    pub show_origin: bool,
+    pub typ: Option<Type>,
+    /// the git directory of the repository, `None` outside of one
+    pub git_dir: Option<PathBuf>,
+}
+
+impl ConfigCommand {
+    /// interprets a value as `--type` says
+    fn canonical(&self, key: &str, value: Option<&str>) -> anyhow::Result<String> {
+        let text = value.unwrap_or_default();
+        Ok(match self.typ {
+            None => text.to_owned(),
+            Some(Type::Bool) => match parse_bool(value) {
+                Some(b) => b.to_string(),
+                None => bail!("bad boolean config value '{text}' for '{key}'"),
+            },
+            Some(Type::Int) => match parse_int(text) {
+                Some(i) => i.to_string(),
+                None => bail!("bad numeric config value '{text}' for '{key}': invalid unit"),
+            },
+            Some(Type::Path) => expand_home(text).to_string_lossy().into_owned(),
+        })
+    }
+
+    fn origin(&self, entry: &Entry) -> String {
+        match self.show_origin {
+            true => format!("file:{}\t", entry.origin.display()),
+            false => String::new(),
+        }
+    }
+
+    pub fn run(&self, name: Option<&str>, value: Option<&str>) -> anyhow::Result<ExitCode> {
+        if let Some(name) = name {
+            if let Err(e) = split_key(name) {
+                eprintln!("error: {e}");
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        let name = || name.context("wrong number of arguments, should be 1");
+
+        if matches!(self.action, Action::Set | Action::Unset | Action::UnsetAll) {
+            let path = match (&self.file, self.scope) {
+                (Some(file), _) => file.clone(),
+                (None, scope) => {
+                    Config::write_path(scope.unwrap_or(Scope::Local), self.git_dir.as_deref())?
+                }
+            };
+            let mut file = ConfigFile::open(path)?;
+            return match self.action {
+                Action::Set => {
+                    let value = value.context("wrong number of arguments, should be 2")?;
+                    let value = match self.typ {
+                        Some(_) => self.canonical(name()?, Some(value))?,
+                        None => value.to_owned(),
+                    };
+                    let name = name()?;
+                    if !file.set(name, &value)? {
+                        eprintln!("warning: {name} has multiple values");
+                        eprintln!("error: cannot overwrite multiple values with a single value");
+                        eprintln!("       Use a regexp, --add or --replace-all to change {name}.");
+                        return Ok(ExitCode::from(5));
+                    }
+                    Ok(ExitCode::SUCCESS)
+                }
+                _ => match file.unset(name()?, self.action == Action::UnsetAll) {
+                    Ok(true) => Ok(ExitCode::SUCCESS),
+                    Ok(false) => Ok(ExitCode::from(5)),
+                    Err(e) => {
+                        eprintln!("warning: {e}");
+                        Ok(ExitCode::from(5))
+                    }
+                },
+            };
+        }
+
+        let config = match (&self.file, self.scope) {
+            (Some(file), _) => Config::load_file(file, self.git_dir.as_deref())?,
+            (None, Some(scope)) => Config::load_scope(scope, self.git_dir.as_deref())?,
+            (None, None) => match &self.git_dir {
+                Some(git_dir) => Config::load(git_dir)?,
+                None => Config::load_global()?,
+            },
+        };
+        let mut out = std::io::stdout().lock();
+        match self.action {
+            Action::List => {
+                for entry in config.entries() {
+                    let origin = self.origin(entry);
+                    match &entry.value {
+                        Some(value) => writeln!(out, "{origin}{}={value}", entry.key)?,
+                        None => writeln!(out, "{origin}{}", entry.key)?,
+                    }
+                }
+            }
+            Action::Get | Action::GetAll => {
+                let name = name()?;
+                let entries: Vec<_> = config.get_all(name).collect();
+                let entries = match (self.action, entries.split_last()) {
+                    (_, None) => return Ok(ExitCode::FAILURE),
+                    (Action::Get, Some((last, _))) => vec![*last],
+                    _ => entries,
+                };
+                for entry in entries {
+                    let value = self.canonical(name, entry.value.as_deref())?;
+                    writeln!(out, "{}{value}", self.origin(entry))?;
+                }
+            }
+            Action::Set | Action::Unset | Action::UnsetAll => unreachable!("handled above"),
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(content: &str) -> Vec<(String, Option<String>)> {
+        parse(content.as_bytes())
+            .unwrap()
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Entry { key, value, .. } => Some((key, value)),
+                Item::Section { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn syntax() {
+        let content = "# comment\n[user]\n\tname = A  U Thor ; trailing\n\temail = \"a@b\"\n[Remote \"Or\\\"igin\"]\n\tURL = x\n[core]\n\tbare\n[Legacy.Sub] key = \"  spaced\\t\" \\\n continued\n[a] b=\\n\n";
+        let some = |s: &str| Some(s.to_owned());
+        assert_eq!(
+            entries(content),
+            [
+                ("user.name".to_owned(), some("A  U Thor")),
+                ("user.email".to_owned(), some("a@b")),
+                ("remote.Or\"igin.url".to_owned(), some("x")),
+                ("core.bare".to_owned(), None),
+                ("legacy.sub.key".to_owned(), some("  spaced\t  continued")),
+                ("a.b".to_owned(), some("\n")),
+            ]
+        );
+        assert!(parse(b"key = outside\n").is_err());
+        assert!(parse(b"[a]\nb = \"unterminated\n").is_err());
+        assert!(parse(b"[a]\nb = \\x\n").is_err());
+        assert!(parse(b"[a \"b]\n").is_err());
+    }
+
+    #[test]
+    fn lines() {
+        let items = parse(b"[a]\n\tb = 1 \\\n 2\n\tc\n[d]").unwrap();
+        assert_eq!(
+            items,
+            [
+                Item::Section {
+                    name: "a".to_owned(),
+                    line: 0
+                },
+                Item::Entry {
+                    key: "a.b".to_owned(),
+                    value: Some("1  2".to_owned()),
+                    lines: 1..3
+                },
+                Item::Entry {
+                    key: "a.c".to_owned(),
+                    value: None,
+                    lines: 3..4
+                },
+                Item::Section {
+                    name: "d".to_owned(),
+                    line: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(parse_bool(None), Some(true));
+        assert_eq!(parse_bool(Some("Yes")), Some(true));
+        assert_eq!(parse_bool(Some("off")), Some(false));
+        assert_eq!(parse_bool(Some("")), Some(false));
+        assert_eq!(parse_bool(Some("2")), Some(true));
+        assert_eq!(parse_bool(Some("maybe")), None);
+        assert_eq!(parse_int("1k"), Some(1024));
+        assert_eq!(parse_int("3M"), Some(3 << 20));
+        assert_eq!(parse_int("-2g"), Some(-2 << 30));
+        assert_eq!(parse_int("1x"), None);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(normalize_key("Remote.Origin.URL"), "remote.Origin.url");
+        assert_eq!(split_key("a.b.c").unwrap(), ("a.b", "c"));
+        assert!(split_key("nosection").is_err());
+        assert!(split_key("a.1x").is_err());
+        assert_eq!(quote(" a;b\"c"), "\" a;b\\\"c\"");
+        assert_eq!(header("remote.or\"igin"), "[remote \"or\\\"igin\"]\n");
+    }
+}
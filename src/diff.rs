@@ -0,0 +1,330 @@
+use std::{
+    cmp::Ordering,
+    io::{stdin, stdout, BufRead, BufWriter, Write},
+};
+
+use anyhow::bail;
+
+use crate::{
+    hash::Hash,
+    object::{Commit, Object, DIRECTORY},
+    repository::Repository,
+    revision::{self, RevisionError},
+    BlobType,
+};
+
+/// what happened to a path between two trees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum Status {
+    #[display(fmt = "A")]
+    Added,
+    #[display(fmt = "D")]
+    Deleted,
+    #[display(fmt = "M")]
+    Modified,
+    /// a file became a symlink or a submodule, or the other way around
+    #[display(fmt = "T")]
+    TypeChanged,
+}
+
+/// a path that differs between two trees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: Vec<u8>,
+    pub status: Status,
+    /// the mode and object before, `None` if the path was added
+    pub old: Option<(u32, Hash)>,
+    /// the mode and object after, `None` if the path was deleted
+    pub new: Option<(u32, Hash)>,
+}
+
+type Entry = (Vec<u8>, u32, Hash);
+
+fn entries(repo: &Repository, tree: Option<&Hash>) -> anyhow::Result<Vec<Entry>> {
+    let Some(hash) = tree else {
+        return Ok(vec![]);
+    };
+    let Object::Tree(tree) = repo.read_object(hash)? else {
+        bail!("{hash} is not a tree");
+    };
+    Ok(tree
+        .entries()
+        .map(|(name, mode, hash)| (name.to_vec(), mode, hash.clone()))
+        .collect())
+}
+
+/// compares entries in the order of trees, where directories sort as if their name ended in `/`.
+///
+/// so a file and a directory of the same name are never the same entry
+fn compare((a, a_mode, _): &Entry, (b, b_mode, _): &Entry) -> Ordering {
+    let key = |name: &[u8], mode: u32| {
+        let suffix: &[u8] = if mode == DIRECTORY { b"/" } else { b"" };
+        [name, suffix].concat()
+    };
+    key(a, *a_mode).cmp(&key(b, *b_mode))
+}
+
+/// the paths that differ between two trees, in the order of trees.
+///
+/// a missing tree is empty. changed subtrees are entered if `recursive`, and reported as a
+/// single change otherwise
+pub fn diff_trees(
+    repo: &Repository,
+    old: Option<&Hash>,
+    new: Option<&Hash>,
+    recursive: bool,
+) -> anyhow::Result<Vec<Change>> {
+    let mut changes = vec![];
+    diff_into(repo, &[], old, new, recursive, &mut changes)?;
+    Ok(changes)
+}
+
+fn diff_into(
+    repo: &Repository,
+    prefix: &[u8],
+    old: Option<&Hash>,
+    new: Option<&Hash>,
+    recursive: bool,
+    changes: &mut Vec<Change>,
+) -> anyhow::Result<()> {
+    let old = entries(repo, old)?;
+    let new = entries(repo, new)?;
+    let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+
+    loop {
+        let (a, b) = match (old.peek(), new.peek()) {
+            (None, None) => return Ok(()),
+            (Some(_), None) => (old.next(), None),
+            (None, Some(_)) => (None, new.next()),
+            (Some(a), Some(b)) => match compare(a, b) {
+                Ordering::Less => (old.next(), None),
+                Ordering::Greater => (None, new.next()),
+                Ordering::Equal => (old.next(), new.next()),
+            },
+        };
+        let name = match (&a, &b) {
+            (Some((name, ..)), _) | (_, Some((name, ..))) => name,
+            (None, None) => unreachable!("one of the entries is there"),
+        };
+        let path = match prefix.is_empty() {
+            true => name.clone(),
+            false => [prefix, name].join(&b'/'),
+        };
+
+        let status = match (&a, &b) {
+            (Some((_, a_mode, a_hash)), Some((_, b_mode, b_hash))) => {
+                if a_mode == b_mode && a_hash == b_hash {
+                    continue;
+                }
+                // file types are the top bits of the mode
+                match a_mode >> 12 == b_mode >> 12 {
+                    true => Status::Modified,
+                    false => Status::TypeChanged,
+                }
+            }
+            (Some(_), None) => Status::Deleted,
+            (None, Some(_)) => Status::Added,
+            (None, None) => unreachable!("one of the entries is there"),
+        };
+
+        let is_tree = |entry: &Option<Entry>| matches!(entry, Some((_, DIRECTORY, _)));
+        if recursive && (is_tree(&a) || is_tree(&b)) {
+            let old = a.as_ref().map(|(_, _, hash)| hash);
+            let new = b.as_ref().map(|(_, _, hash)| hash);
+            diff_into(repo, &path, old, new, recursive, changes)?;
+            continue;
+        }
+        changes.push(Change {
+            path,
+            status,
+            old: a.map(|(_, mode, hash)| (mode, hash)),
+            new: b.map(|(_, mode, hash)| (mode, hash)),
+        });
+    }
+}
+
+/// how `diff-tree` prints changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// the modes, objects, status and path
+    Raw,
+    NameOnly,
+    NameStatus,
+}
+
+pub struct DiffTree {
+    /// enters changed subtrees
+    pub recursive: bool,
+    pub format: Format,
+    /// ends paths and lines with NUL instead of newlines
+    pub nul: bool,
+    /// compares commits without parents with the empty tree instead of skipping them
+    pub root: bool,
+}
+
+impl DiffTree {
+    pub fn run(&self, repo: &Repository, args: &[String], from_stdin: bool) -> anyhow::Result<()> {
+        let mut out = BufWriter::new(stdout().lock());
+
+        if from_stdin {
+            if !args.is_empty() {
+                bail!("--stdin takes no arguments");
+            }
+            for line in stdin().lock().lines() {
+                self.stdin_line(repo, &line?, &mut out)?;
+            }
+            return Ok(out.flush()?);
+        }
+
+        match args {
+            [commit] => {
+                let hash = match revision::resolve_typed(repo, commit, BlobType::Commit) {
+                    Err(err)
+                        if matches!(err.downcast_ref(), Some(RevisionError::WrongType(..))) =>
+                    {
+                        bail!("{commit} is not a commit")
+                    }
+                    hash => hash?,
+                };
+                let read = repo.read_commit(&hash)?;
+                self.commit(repo, &hash, &read, read.parents(), &mut out)?;
+            }
+            [old, new] => {
+                let old = revision::resolve_typed(repo, old, BlobType::Tree)?;
+                let new = revision::resolve_typed(repo, new, BlobType::Tree)?;
+                let changes = diff_trees(repo, Some(&old), Some(&new), self.recursive)?;
+                self.write(&changes, &mut out)?;
+            }
+            _ => bail!("diff-tree takes one commit or two trees"),
+        }
+        Ok(out.flush()?)
+    }
+
+    /// diffs a commit against the parents that follow it on the line, or its own, or diffs the
+    /// two trees on the line. lines that do not start with an object are copied
+    fn stdin_line(
+        &self,
+        repo: &Repository,
+        line: &str,
+        out: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let mut words = line.split(' ');
+        let Some(Ok(hash)) = words.next().map(str::parse::<Hash>) else {
+            writeln!(out, "{line}")?;
+            return Ok(());
+        };
+        let others: Vec<Hash> = words.map_while(|word| word.parse().ok()).collect();
+        match repo.read_object(&hash)? {
+            Object::Commit(commit) => {
+                let parents = match others.is_empty() {
+                    true => commit.parents(),
+                    false => &others,
+                };
+                self.commit(repo, &hash, &commit, parents, out)
+            }
+            Object::Tree(_) => {
+                let [other] = others.as_slice() else {
+                    eprintln!("error: Need exactly two trees, separated by a space");
+                    return Ok(());
+                };
+                let changes = diff_trees(repo, Some(&hash), Some(other), self.recursive)?;
+                // the trees are echoed even if they are the same
+                writeln!(out, "{line}")?;
+                self.write(&changes, out)
+            }
+            _ => bail!("{hash} is neither a commit nor a tree"),
+        }
+    }
+
+    /// prints the hash of the commit and its changes, if it has any. merges are skipped
+    fn commit(
+        &self,
+        repo: &Repository,
+        hash: &Hash,
+        commit: &Commit,
+        parents: &[Hash],
+        out: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let parent_tree = match parents {
+            [] if self.root => None,
+            [parent] => Some(repo.read_commit(parent)?.tree().clone()),
+            _ => return Ok(()),
+        };
+        let changes = diff_trees(
+            repo,
+            parent_tree.as_ref(),
+            Some(commit.tree()),
+            self.recursive,
+        )?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        write!(out, "{hash}{}", self.terminator())?;
+        self.write(&changes, out)
+    }
+
+    fn terminator(&self) -> char {
+        match self.nul {
+            true => '\0',
+            false => '\n',
+        }
+    }
+
+    fn write(&self, changes: &[Change], out: &mut impl Write) -> anyhow::Result<()> {
+        let terminator = self.terminator();
+        let separator = match self.nul {
+            true => '\0',
+            false => '\t',
+        };
+        for change in changes {
+            match self.format {
+                Format::Raw => {
+                    let side = |side: &Option<(u32, Hash)>| match side {
+                        Some((mode, hash)) => (*mode, hash.to_string()),
+                        None => (0, "0".repeat(40)),
+                    };
+                    let (old_mode, old_hash) = side(&change.old);
+                    let (new_mode, new_hash) = side(&change.new);
+                    write!(
+                        out,
+                        ":{old_mode:06o} {new_mode:06o} {old_hash} {new_hash} {}{separator}",
+                        change.status
+                    )?;
+                }
+                Format::NameOnly => {}
+                Format::NameStatus => write!(out, "{}{separator}", change.status)?,
+            }
+            out.write_all(&change.path)?;
+            write!(out, "{terminator}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_order() {
+        let hash = Hash::from_bytes(b"");
+        let entry = |name: &[u8], mode| (name.to_vec(), mode, hash.clone());
+        // `a.b` sorts between the file `a` and the directory `a`, which is compared as `a/`
+        assert_eq!(
+            compare(&entry(b"a", 0o100644), &entry(b"a.b", 0o100644)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&entry(b"a", DIRECTORY), &entry(b"a.b", 0o100644)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare(&entry(b"a", 0o100644), &entry(b"a", DIRECTORY)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&entry(b"a", 0o100644), &entry(b"a", 0o120000)),
+            Ordering::Equal
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! the ASCII history graph of `log --graph`, drawn like git's `graph.c` without colors
+
+use crate::hash::Hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// lines that leave all branch lines unchanged
+    Padding,
+    /// lines that make room for the edges of an octopus merge
+    PreCommit,
+    /// the line with the commit
+    Commit,
+    /// the line below a merge, with an edge to each parent
+    PostMerge,
+    /// lines that move branch lines left until they are where they belong
+    Collapsing,
+}
+
+/// the state of the graph between commits, see [`Graph::update`] and [`Graph::next_line`]
+#[derive(Debug)]
+pub struct Graph {
+    commit: Option<Hash>,
+    parents: Vec<Hash>,
+    state: State,
+    prev_state: State,
+    /// the width of the lines for the current commit, to align what follows them
+    width: usize,
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// how many lines making room for an octopus merge were drawn
+    expansion_row: usize,
+    /// where the first parent of a merge goes: 0 to the left of the merge, 1 below it
+    merge_layout: isize,
+    /// how many more columns there are after the commit than before it
+    edges_added: isize,
+    prev_edges_added: isize,
+    /// the commits each column leads to, before and after the current commit
+    columns: Vec<Hash>,
+    new_columns: Vec<Hash>,
+    /// for each character position of a line, the new column the branch line there goes to
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self {
+            commit: None,
+            parents: vec![],
+            state: State::Padding,
+            prev_state: State::Padding,
+            width: 0,
+            commit_index: 0,
+            prev_commit_index: 0,
+            expansion_row: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: vec![],
+            new_columns: vec![],
+            mapping: vec![],
+            old_mapping: vec![],
+        }
+    }
+
+    /// moves on to the next commit, whose parents are those that are shown
+    pub fn update(&mut self, commit: Hash, parents: Vec<Hash>) {
+        self.commit = Some(commit);
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+        // no line was drawn in the new state, so the previous state stays
+        self.state = match self.needs_pre_commit_line() {
+            true => State::PreCommit,
+            false => State::Commit,
+        };
+    }
+
+    /// whether all lines of the current commit have been drawn
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    fn find_new_column(&self, commit: &Hash) -> Option<usize> {
+        self.new_columns.iter().position(|c| c == commit)
+    }
+
+    fn insert_into_new_columns(&mut self, commit: &Hash, index: Option<usize>) {
+        let i = match self.find_new_column(commit) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(commit.clone());
+                self.new_columns.len() - 1
+            }
+        };
+        let i = i as isize;
+        let mapping_index;
+        match index {
+            // the first parent of a merge decides how the merge is drawn
+            Some(index) if self.parents.len() > 1 && self.merge_layout == -1 => {
+                let distance = index as isize - i;
+                let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+                self.merge_layout = if distance > 0 { 0 } else { 1 };
+                self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+                mapping_index = self.width as isize + (self.merge_layout - 1) * shift;
+                self.width = (self.width as isize + 2 * self.merge_layout) as usize;
+            }
+            // the edges of a merge join the last existing column right away
+            _ if self.edges_added > 0 && self.width >= 2 && self.mapping[self.width - 2] == i => {
+                mapping_index = self.width as isize - 2;
+                self.edges_added = -1;
+            }
+            _ => {
+                mapping_index = self.width as isize;
+                self.width += 2;
+            }
+        }
+        self.mapping[mapping_index as usize] = i;
+    }
+
+    fn update_columns(&mut self) {
+        self.columns = std::mem::take(&mut self.new_columns);
+        let max_new_columns = self.columns.len() + self.parents.len();
+        self.mapping = vec![-1; 2 * max_new_columns];
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        let commit = self.commit.clone().expect("updated with a commit");
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(column) => column.clone(),
+                None if seen_this => break,
+                None => commit.clone(),
+            };
+            if column == commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone() {
+                    self.insert_into_new_columns(&parent, Some(i));
+                }
+                // the commit takes up space even without parents
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(&column, None);
+            }
+        }
+        while self.mapping.len() > 1 && self.mapping.last() == Some(&-1) {
+            self.mapping.pop();
+        }
+    }
+
+    fn update_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    /// whether every branch line is in its column, or one to the right of it where a `/` leads
+    /// to it
+    fn is_mapping_correct(&self) -> bool {
+        self.mapping
+            .iter()
+            .enumerate()
+            .all(|(i, &target)| target < 0 || target == (i / 2) as isize)
+    }
+
+    /// the commits of the columns of the commit line, with the commit at the end if it has no
+    /// column yet
+    fn old_columns(&self) -> Vec<Hash> {
+        let commit = self.commit.as_ref().expect("updated with a commit");
+        let mut columns = self.columns.clone();
+        if !columns.contains(commit) {
+            columns.push(commit.clone());
+        }
+        columns
+    }
+
+    /// the number of parents of an octopus merge drawn with dashes
+    fn dashed_parents(&self) -> isize {
+        self.parents.len() as isize + self.merge_layout - 3
+    }
+
+    /// whether branch lines to the right of an octopus merge need to move further right first
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && (self.expansion_row as isize) < self.dashed_parents() * 2
+    }
+
+    fn pre_commit_line(&mut self, line: &mut String) {
+        let commit = self.commit.clone().expect("updated with a commit");
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if *column == commit {
+                seen_this = true;
+                line.push('|');
+                line.push_str(&" ".repeat(self.expansion_row));
+            } else if seen_this && self.expansion_row == 0 {
+                // branch lines leaning right after a merge keep doing so
+                match self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+            } else if seen_this {
+                line.push('\\');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(State::Commit);
+        }
+    }
+
+    fn padding_line(&self, line: &mut String) {
+        for _ in &self.new_columns {
+            line.push_str("| ");
+        }
+    }
+
+    fn commit_line(&mut self, line: &mut String) {
+        let commit = self.commit.clone().expect("updated with a commit");
+        let mut seen_this = false;
+        for (i, column) in self.old_columns().iter().enumerate() {
+            if *column == commit {
+                seen_this = true;
+                line.push('*');
+                // an octopus merge has dashes to its further parents
+                let dashed = self.dashed_parents();
+                for n in 0..dashed.max(0) {
+                    line.push('-');
+                    line.push(if n == dashed - 1 { '.' } else { '-' });
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push('\\');
+            } else if seen_this && self.edges_added == 1 {
+                // a branch line coming from the merge above keeps leaning right
+                if self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    line.push('\\');
+                } else {
+                    line.push('|');
+                }
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping.get(2 * i + 1) == Some(&(i as isize))
+                && self.mapping.get(2 * i).is_some_and(|&m| m < i as isize)
+            {
+                line.push('/');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+
+        if self.parents.len() > 1 {
+            self.update_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    fn post_merge_line(&mut self, line: &mut String) {
+        const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+        let commit = self.commit.clone().expect("updated with a commit");
+        let mut seen_this = false;
+        // the first parent's column left of the merge, which gets `_` edges
+        let mut parent_column = false;
+        for (i, column) in self.old_columns().iter().enumerate() {
+            if *column == commit {
+                seen_this = true;
+                let mut index = self.merge_layout as usize;
+                for j in 0..self.parents.len() {
+                    line.push(MERGE_CHARS[index]);
+                    if index == 2 {
+                        if self.edges_added > 0 || j < self.parents.len() - 1 {
+                            line.push(' ');
+                        }
+                    } else {
+                        index += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                line.push(if self.edges_added > 0 { '\\' } else { '|' });
+                line.push(' ');
+            } else {
+                line.push('|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    line.push(if parent_column { '_' } else { ' ' });
+                }
+            }
+            if Some(column) == self.parents.first() {
+                parent_column = true;
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    fn collapsing_line(&mut self, line: &mut String) {
+        let size = self.mapping.len();
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        self.mapping = vec![-1; size];
+        let mut horizontal_edge: Option<usize> = None;
+        let mut horizontal_target = -1;
+
+        for i in 0..size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // branch lines only ever move left
+            if (target * 2) as usize == i {
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                self.mapping[i - 1] = target;
+                if horizontal_edge.is_none() {
+                    horizontal_edge = Some(i);
+                    horizontal_target = target;
+                    let mut j = (target * 2 + 3) as usize;
+                    while j + 2 < i {
+                        self.mapping[j] = target;
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // joins the branch line to the left, which goes to the same commit
+            } else {
+                // crosses the branch line to the left
+                self.mapping[i - 2] = target;
+                if horizontal_edge.is_none() {
+                    horizontal_target = target;
+                    horizontal_edge = Some(i - 1);
+                    let mut j = (target * 2 + 3) as usize;
+                    while j + 2 < i {
+                        self.mapping[j] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+
+        self.old_mapping = self.mapping.clone();
+        if self.mapping.last().is_some_and(|&m| m < 0) {
+            self.mapping.pop();
+        }
+
+        let mut used_horizontal = false;
+        for i in 0..self.mapping.len() {
+            let target = self.mapping[i];
+            if target < 0 {
+                line.push(' ');
+            } else if (target * 2) as usize == i {
+                line.push('|');
+            } else if target == horizontal_target && Some(i + 1) != horizontal_edge {
+                // only the first segment of a horizontal edge continues on the next line
+                if i != (target * 2 + 3) as usize {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                line.push('_');
+            } else {
+                if used_horizontal && horizontal_edge.is_some_and(|edge| i < edge) {
+                    self.mapping[i] = -1;
+                }
+                line.push('/');
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        }
+    }
+
+    /// the next line of the graph, padded to the width of the commit's lines, and whether it is
+    /// the line with the commit
+    pub fn next_line(&mut self) -> (String, bool) {
+        let mut line = String::new();
+        let state = self.state;
+        match state {
+            State::Padding => self.padding_line(&mut line),
+            State::PreCommit => self.pre_commit_line(&mut line),
+            State::Commit => self.commit_line(&mut line),
+            State::PostMerge => self.post_merge_line(&mut line),
+            State::Collapsing => self.collapsing_line(&mut line),
+        }
+        let width = line.chars().count();
+        if width < self.width {
+            line.push_str(&" ".repeat(self.width - width));
+        }
+        (line, state == State::Commit)
+    }
+
+    /// a line that leaves all branch lines unchanged, to separate commits
+    pub fn padding(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+        let mut line = String::new();
+        for column in &self.columns {
+            line.push('|');
+            match Some(column) == self.commit.as_ref() && self.parents.len() > 2 {
+                true => line.push_str(&" ".repeat((self.parents.len() - 2) * 2)),
+                false => line.push(' '),
+            }
+        }
+        let width = line.chars().count();
+        if width < self.width {
+            line.push_str(&" ".repeat(self.width - width));
+        }
+        self.prev_state = State::Padding;
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u32) -> Hash {
+        format!("{n:040}").parse().unwrap()
+    }
+
+    /// the lines of the graph for commits given as `(commit, parents)`
+    fn draw(commits: &[(u32, &[u32])]) -> Vec<String> {
+        let mut graph = Graph::new();
+        let mut lines = vec![];
+        for &(commit, parents) in commits {
+            graph.update(hash(commit), parents.iter().map(|&p| hash(p)).collect());
+            loop {
+                let (line, _) = graph.next_line();
+                lines.push(line.trim_end().to_owned());
+                if graph.is_finished() {
+                    break;
+                }
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn merge() {
+        let lines = draw(&[
+            (9, &[8, 5]),
+            (5, &[4]),
+            (4, &[1]),
+            (8, &[7]),
+            (7, &[1]),
+            (1, &[]),
+        ]);
+        assert_eq!(lines, ["*", "|\\", "| *", "| *", "* |", "* |", "|/", "*"]);
+    }
+}
@@ -5,7 +5,7 @@ use sha1::{Digest, Sha1};
 
 use crate::{PathBufExt, Writeable};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash)]
 pub struct Hash {
     buf: [u8; 20],
 }
@@ -37,6 +37,11 @@ impl Hash {
         Some(Self { buf })
     }
 
+    /// the raw 20 bytes of the hash
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
     pub fn from_bytes(b: &[u8]) -> Self {
         let mut hasher = Sha1::new();
         hasher.update(b);
@@ -69,6 +74,7 @@ impl FromStr for Hash {
             match ch {
                 '0'..='9' => Ok((ch as u8) - b'0'),
                 'a'..='f' => Ok((ch as u8) - b'a' + 10),
+                'A'..='F' => Ok((ch as u8) - b'A' + 10),
                 _ => Err(HashError::UnexpectedChar(ch)),
             }
         }
@@ -0,0 +1,264 @@
+use anyhow::{bail, Context};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike,
+};
+
+use crate::{config::Config, object::Event, repository::Repository};
+
+/// who an identity is for, which decides the environment variables and config keys consulted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    fn env(self, field: &str) -> Option<String> {
+        let prefix = match self {
+            Role::Author => "GIT_AUTHOR",
+            Role::Committer => "GIT_COMMITTER",
+        };
+        std::env::var(format!("{prefix}_{field}")).ok()
+    }
+
+    fn config<'a>(self, config: &'a Config, field: &str) -> Option<&'a str> {
+        let section = match self {
+            Role::Author => "author",
+            Role::Committer => "committer",
+        };
+        config
+            .get(&format!("{section}.{field}"))
+            .or_else(|| config.get(&format!("user.{field}")))
+    }
+}
+
+/// splits `Name <email>`, as given to `--author`
+pub fn parse_ident(s: &str) -> Option<(String, String)> {
+    let (name, rest) = s.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    Some((name.trim().to_owned(), email.trim().to_owned()))
+}
+
+/// the identity and time for `role`, from `ident` if given, otherwise the environment or config
+pub fn event(repo: &Repository, role: Role, ident: Option<&str>) -> anyhow::Result<Event> {
+    let (name, email) = match ident {
+        Some(ident) => {
+            parse_ident(ident).with_context(|| format!("malformed --author parameter '{ident}'"))?
+        }
+        None => {
+            let config = repo.config()?;
+            let name = role
+                .env("NAME")
+                .or_else(|| role.config(&config, "name").map(str::to_owned));
+            let email = role
+                .env("EMAIL")
+                .or_else(|| role.config(&config, "email").map(str::to_owned))
+                .or_else(|| std::env::var("EMAIL").ok());
+            let (Some(name), Some(email)) = (name, email) else {
+                let who = match role {
+                    Role::Author => "Author",
+                    Role::Committer => "Committer",
+                };
+                bail!(
+                    "{who} identity unknown
+
+*** Please tell me who you are.
+
+Run
+
+  git config --global user.email \"you@example.com\"
+  git config --global user.name \"Your Name\"
+
+to set your account's default identity.
+Omit --global to set the identity only in this repository."
+                );
+            };
+            (name, email)
+        }
+    };
+    if name.is_empty() {
+        bail!("empty ident name (for <{email}>) not allowed");
+    }
+
+    let time = match role.env("DATE") {
+        Some(date) => parse_date(&date).with_context(|| format!("invalid date format: {date}"))?,
+        None => Local::now().fixed_offset(),
+    };
+    Ok(Event::at(name, email, time))
+}
+
+/// parses a `±hhmm`, `±hh:mm`, `±hh` or `Z` timezone
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    if s == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, digits) = match s.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    let digits = digits.replacen(':', "", 1);
+    if !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// splits a trailing timezone off an ISO 8601 date, which may follow the time without a space
+fn split_offset(s: &str) -> (&str, Option<FixedOffset>) {
+    if let Some(date) = s.strip_suffix('Z') {
+        return (date.trim_end(), FixedOffset::east_opt(0));
+    }
+    for len in [6, 5, 3] {
+        let Some(at) = s.len().checked_sub(len) else {
+            continue;
+        };
+        // a date without a time ends in `-dd`, which is no timezone
+        if !s.is_char_boundary(at) || !s[..at].contains(':') {
+            continue;
+        }
+        if let Some(offset) = parse_offset(&s[at..]) {
+            return (s[..at].trim_end(), Some(offset));
+        }
+    }
+    (s, None)
+}
+
+/// parses a date in one of the formats git accepts in `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE`:
+/// its internal `<seconds> <±hhmm>` format, RFC 2822 and ISO 8601
+pub fn parse_date(s: &str) -> anyhow::Result<DateTime<FixedOffset>> {
+    let s = s.trim();
+
+    // the internal format, optionally with a leading `@`
+    let internal = s.strip_prefix('@').unwrap_or(s);
+    let (seconds, offset) = internal.split_once(' ').unwrap_or((internal, "+0000"));
+    if let (Ok(seconds), Some(offset)) = (seconds.parse::<i64>(), parse_offset(offset)) {
+        if seconds >= 100_000_000 || s.starts_with('@') {
+            let time = DateTime::from_timestamp(seconds, 0).context("timestamp out of range")?;
+            return Ok(time.with_timezone(&offset));
+        }
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc2822(s) {
+        return Ok(time);
+    }
+
+    let (local, offset) = split_offset(s);
+    const DATE_TIMES: &[&str] = &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+        "%Y.%m.%d %H:%M:%S",
+        "%m/%d/%Y %H:%M:%S",
+        "%d.%m.%Y %H:%M:%S",
+    ];
+    const DATES: &[&str] = &["%Y-%m-%d", "%Y.%m.%d", "%m/%d/%Y", "%d.%m.%Y"];
+    let naive = DATE_TIMES
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(local, f).ok())
+        .or_else(|| {
+            // a date alone keeps the current time of day, as in git
+            let date = DATES
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(local, f).ok())?;
+            let now: NaiveTime = Local::now().time();
+            Some(date.and_time(now.with_nanosecond(0).unwrap_or(now)))
+        })
+        .with_context(|| format!("unknown date format {s:?}"))?;
+
+    let time = match offset {
+        Some(offset) => offset.from_local_datetime(&naive).single(),
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.fixed_offset()),
+    };
+    let time = time.with_context(|| format!("invalid date {s:?}"))?;
+    // git only stores whole seconds
+    Ok(time.with_nanosecond(0).unwrap_or(time))
+}
+
+/// parses the dates of options like `--since`, which may also be relative like `2.weeks.ago`,
+/// into a timestamp
+pub fn parse_approxidate(s: &str) -> anyhow::Result<i64> {
+    let now = Local::now().timestamp();
+    let words: Vec<&str> = s.split([' ', '.']).filter(|w| !w.is_empty()).collect();
+    let seconds = |unit: &str| {
+        let unit = unit.strip_suffix('s').unwrap_or(unit);
+        Some(match unit {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => return None,
+        })
+    };
+    match words.as_slice() {
+        ["now"] => return Ok(now),
+        ["yesterday"] => return Ok(now - 86400),
+        [n, unit, "ago"] => {
+            if let (Ok(n), Some(unit)) = (n.parse::<i64>(), seconds(unit)) {
+                return Ok(now - n * unit);
+            }
+        }
+        _ => {}
+    }
+    Ok(parse_date(s)?.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(date: &str, seconds: i64, offset: i32) {
+        let time = parse_date(date).unwrap();
+        assert_eq!(time.timestamp(), seconds, "{date}");
+        assert_eq!(time.offset().local_minus_utc(), offset, "{date}");
+    }
+
+    #[test]
+    fn dates() {
+        check("1112911993 +0530", 1112911993, 19800);
+        check("@1112911993 -0700", 1112911993, -25200);
+        check("@0", 0, 0);
+        check("Thu, 07 Apr 2005 22:13:13 +0200", 1112904793, 7200);
+        check("2005-04-07T22:13:13+02:00", 1112904793, 7200);
+        check("2005-04-07 22:13:13 +0200", 1112904793, 7200);
+        check("2005-04-07T20:13:13Z", 1112904793, 0);
+        check("2005-04-07T22:13:13.019+02:00", 1112904793, 7200);
+        check("2005.04.07 22:13:13 +0200", 1112904793, 7200);
+        check("04/07/2005 22:13:13 +0200", 1112904793, 7200);
+        check("07.04.2005 22:13:13 +02", 1112904793, 7200);
+        assert!(parse_date("not a date").is_err());
+        assert!(parse_date("1112911993 +05:3x").is_err());
+
+        let now = Local::now().timestamp();
+        let ago = parse_approxidate("2.weeks.ago").unwrap();
+        assert!((now - 14 * 86400 - ago).abs() <= 1);
+        assert_eq!(parse_approxidate("@1112911993").unwrap(), 1112911993);
+    }
+
+    #[test]
+    fn idents() {
+        assert_eq!(
+            parse_ident("A U Thor <author@example.com>"),
+            Some(("A U Thor".to_owned(), "author@example.com".to_owned()))
+        );
+        assert_eq!(parse_ident("no email"), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use crate::{pathspec::wildmatch, repository::Repository};
+
+#[derive(Debug)]
+struct Pattern {
+    /// the directory of the `.gitignore` the pattern is from, relative to the worktree
+    base: Vec<u8>,
+    glob: Vec<u8>,
+    negated: bool,
+    dir_only: bool,
+    /// patterns with a slash are matched against the path, others against the file name
+    anchored: bool,
+}
+
+/// the patterns of `core.excludesFile`, `.git/info/exclude` and the `.gitignore` files read so far
+#[derive(Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<Pattern>,
+}
+
+impl Ignore {
+    /// reads `core.excludesFile`, `.git/info/exclude` and the `.gitignore` at the top of the worktree
+    pub fn load(repo: &Repository) -> Self {
+        let mut ignore = Self::default();
+        let excludes_file = repo
+            .config()
+            .ok()
+            .and_then(|config| config.get_path("core.excludesFile"));
+        if let Some(Ok(excludes)) = excludes_file.map(std::fs::read) {
+            ignore.add_patterns(b"", &excludes);
+        }
+        if let Ok(exclude) = std::fs::read(repo.git_dir().join("info/exclude")) {
+            ignore.add_patterns(b"", &exclude);
+        }
+        ignore.add_dir(b"");
+        ignore
+    }
+
+    /// reads the `.gitignore` in `dir`, if there is one
+    pub fn add_dir(&mut self, dir: &[u8]) {
+        let path = match dir {
+            b"" => Path::new(".gitignore").to_owned(),
+            dir => Path::new(std::str::from_utf8(dir).unwrap_or_default()).join(".gitignore"),
+        };
+        if let Ok(contents) = std::fs::read(path) {
+            self.add_patterns(dir, &contents);
+        }
+    }
+
+    fn add_patterns(&mut self, base: &[u8], contents: &[u8]) {
+        for line in contents.split(|&c| c == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            // trailing spaces are ignored unless escaped
+            let mut end = line.len();
+            while end > 0 && line[end - 1] == b' ' && !line[..end - 1].ends_with(b"\\") {
+                end -= 1;
+            }
+            let mut glob = &line[..end];
+            if glob.is_empty() || glob.starts_with(b"#") {
+                continue;
+            }
+            let negated = glob.starts_with(b"!");
+            if negated {
+                glob = &glob[1..];
+            }
+            let dir_only = glob.ends_with(b"/");
+            if dir_only {
+                glob = &glob[..glob.len() - 1];
+            }
+            let anchored = glob.contains(&b'/');
+            let glob = glob.strip_prefix(b"/").unwrap_or(glob);
+
+            self.patterns.push(Pattern {
+                base: base.to_vec(),
+                glob: glob.to_vec(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+    }
+
+    /// whether the worktree path or a directory it is in is ignored, reading the `.gitignore`
+    /// files of the directories on the way
+    pub fn is_path_ignored(&mut self, path: &[u8], is_dir: bool) -> bool {
+        let mut dir = 0;
+        while let Some(slash) = path[dir..].iter().position(|&c| c == b'/') {
+            let parent = &path[..dir + slash];
+            if self.is_ignored(parent, true) {
+                return true;
+            }
+            self.add_dir(parent);
+            dir += slash + 1;
+        }
+        self.is_ignored(path, is_dir)
+    }
+
+    /// whether the worktree path is ignored, the last matching pattern decides
+    pub fn is_ignored(&self, path: &[u8], is_dir: bool) -> bool {
+        for pattern in self.patterns.iter().rev() {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            let relative = match pattern.base.as_slice() {
+                b"" => path,
+                base => match path.strip_prefix(base) {
+                    Some(rest) if rest.starts_with(b"/") => &rest[1..],
+                    _ => continue,
+                },
+            };
+            let matched = if pattern.anchored {
+                wildmatch(&pattern.glob, relative, true)
+            } else {
+                let name = relative.rsplit(|&c| c == b'/').next().unwrap_or(relative);
+                wildmatch(&pattern.glob, name, true)
+            };
+            if matched {
+                return !pattern.negated;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let mut ignore = Ignore::default();
+        ignore.add_patterns(b"", b"# comment\n*.o\n!keep.o\n/target/\nbuild\n");
+        ignore.add_patterns(b"sub", b"local\n");
+
+        assert!(ignore.is_ignored(b"main.o", false));
+        assert!(ignore.is_ignored(b"dir/main.o", false));
+        assert!(!ignore.is_ignored(b"keep.o", false));
+        assert!(ignore.is_ignored(b"target", true));
+        assert!(!ignore.is_ignored(b"target", false));
+        assert!(!ignore.is_ignored(b"dir/target", true));
+        assert!(ignore.is_ignored(b"dir/build", false));
+        assert!(ignore.is_ignored(b"sub/x/local", false));
+        assert!(!ignore.is_ignored(b"local", false));
+    }
+}
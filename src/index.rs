@@ -0,0 +1,760 @@
+use std::{
+    ffi::OsStr,
+    fs::{File, Metadata},
+    io::{Read, Write},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use nom::{
+    bytes::complete::{tag, take, take_until},
+    number::complete::{be_u16, be_u32, u8 as byte},
+    IResult,
+};
+use walkdir::WalkDir;
+
+use crate::{
+    hash::Hash, ignore::Ignore, pathspec::Pathspec, repository::Repository, BlobType, HashObject,
+    ReadError, Readable, Writeable,
+};
+
+const SIGNATURE: &[u8] = b"DIRC";
+const CHECKSUM_LEN: usize = 20;
+
+const ASSUME_VALID: u16 = 0x8000;
+const EXTENDED: u16 = 0x4000;
+const STAGE_MASK: u16 = 0x3000;
+const STAGE_SHIFT: u16 = 12;
+const NAME_MASK: u16 = 0x0fff;
+
+const SKIP_WORKTREE: u16 = 0x4000;
+const INTENT_TO_ADD: u16 = 0x2000;
+
+/// extensions that record byte offsets into the file and are stale once it is rewritten
+const OFFSET_EXTENSIONS: [&[u8; 4]; 2] = [b"EOIE", b"IEOT"];
+/// extensions that cache information derived from the entries
+const CACHE_EXTENSIONS: [&[u8; 4]; 2] = [b"TREE", b"UNTR"];
+
+#[derive(Debug, derive_more::Display, Clone, thiserror::Error)]
+pub enum IndexError {
+    #[display(fmt = "bad index signature")]
+    BadSignature,
+    #[display(fmt = "bad index version {_0}")]
+    UnsupportedVersion(u32),
+    #[display(fmt = "bad index file sha1 signature")]
+    ChecksumMismatch,
+    #[display(fmt = "index file corrupt")]
+    FormatError,
+    #[display(fmt = "index uses {_0} extension, which we do not understand")]
+    UnknownExtension(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Timestamp {
+    pub seconds: u32,
+    pub nanoseconds: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: Timestamp,
+    pub mtime: Timestamp,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub hash: Hash,
+    /// the assume-valid bit and the stage, the name length is derived from `path`
+    flags: u16,
+    /// the skip-worktree and intent-to-add bits
+    extended_flags: u16,
+    pub path: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// an entry for a file with the given stat data, in stage 0
+    pub fn new(path: Vec<u8>, hash: Hash, metadata: &Metadata) -> Self {
+        let mut entry = Self {
+            ctime: Timestamp::default(),
+            mtime: Timestamp::default(),
+            dev: 0,
+            ino: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash,
+            flags: 0,
+            extended_flags: 0,
+            path,
+        };
+        entry.update_stat(metadata);
+        entry
+    }
+
+    /// an entry without stat data, as created by `update-index --cacheinfo`
+    pub fn from_cacheinfo(mode: u32, hash: Hash, path: Vec<u8>) -> Self {
+        Self {
+            ctime: Timestamp::default(),
+            mtime: Timestamp::default(),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash,
+            flags: 0,
+            extended_flags: 0,
+            path,
+        }
+    }
+
+    /// hashes the file at `path` in the worktree, writing the blob to the object store
+    pub fn from_worktree(repo: &Repository, path: &[u8], write: bool) -> anyhow::Result<Self> {
+        let fs_path = worktree_path(path);
+        let metadata = std::fs::symlink_metadata(&fs_path)?;
+        let content = if metadata.file_type().is_symlink() {
+            std::fs::read_link(&fs_path)?
+                .into_os_string()
+                .into_encoded_bytes()
+        } else {
+            std::fs::read(&fs_path)?
+        };
+        let cmd = HashObject::typed(BlobType::Blob, content)?;
+        if write && !repo.has_object(&cmd.hash())? {
+            cmd.write(repo)?;
+        }
+        Ok(Self::new(path.to_vec(), cmd.hash(), &metadata))
+    }
+
+    /// whether the stat data still describes the file, so it need not be hashed again
+    pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+        let mut other = self.clone();
+        other.update_stat(metadata);
+        self.mtime == other.mtime
+            && self.ctime == other.ctime
+            && self.ino == other.ino
+            && self.uid == other.uid
+            && self.gid == other.gid
+            && self.size == other.size
+            && self.mode == other.mode
+    }
+
+    /// refreshes the stat data, git truncates all of these to 32 bits
+    pub fn update_stat(&mut self, metadata: &Metadata) {
+        self.ctime = Timestamp {
+            seconds: metadata.ctime() as u32,
+            nanoseconds: metadata.ctime_nsec() as u32,
+        };
+        self.mtime = Timestamp {
+            seconds: metadata.mtime() as u32,
+            nanoseconds: metadata.mtime_nsec() as u32,
+        };
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.mode = mode_from_metadata(metadata);
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.size() as u32;
+    }
+
+    /// 0 for normal entries, 1 to 3 for the sides of a merge conflict
+    pub fn stage(&self) -> u16 {
+        (self.flags & STAGE_MASK) >> STAGE_SHIFT
+    }
+
+    pub fn assume_valid(&self) -> bool {
+        self.flags & ASSUME_VALID != 0
+    }
+
+    pub fn skip_worktree(&self) -> bool {
+        self.extended_flags & SKIP_WORKTREE != 0
+    }
+
+    pub fn intent_to_add(&self) -> bool {
+        self.extended_flags & INTENT_TO_ADD != 0
+    }
+
+    fn is_extended(&self) -> bool {
+        self.extended_flags != 0
+    }
+}
+
+/// the mode git records for a file: 100644, 100755, 120000 or 160000
+fn mode_from_metadata(metadata: &Metadata) -> u32 {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        0o120000
+    } else if file_type.is_dir() {
+        0o160000
+    } else if metadata.mode() & 0o111 != 0 {
+        0o100755
+    } else {
+        0o100644
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Extension {
+    signature: [u8; 4],
+    data: Vec<u8>,
+}
+
+/// converts a path relative to the worktree into one on the file system
+pub fn worktree_path(path: &[u8]) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(path))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    version: u32,
+    /// sorted by path, then stage
+    entries: Vec<IndexEntry>,
+    extensions: Vec<Extension>,
+    /// when the index file was last written, entries modified since are racily clean
+    timestamp: Option<Timestamp>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: 2,
+            entries: vec![],
+            extensions: vec![],
+            timestamp: None,
+        }
+    }
+}
+
+impl Index {
+    pub fn path(repo: &Repository) -> PathBuf {
+        repo.git_dir().join("index")
+    }
+
+    /// reads the index of the repository, which is empty if there is no index file
+    pub fn load(repo: &Repository) -> anyhow::Result<Self> {
+        let mut f = match File::open(Self::path(repo)) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => Err(e).context("failed to open index")?,
+        };
+        let mut index = Index::read(&mut f)?;
+        let metadata = f.metadata()?;
+        index.timestamp = Some(Timestamp {
+            seconds: metadata.mtime() as u32,
+            nanoseconds: metadata.mtime_nsec() as u32,
+        });
+        Ok(index)
+    }
+
+    /// writes the index through `index.lock`, so readers never see a partial file
+    pub fn save(&self, repo: &Repository) -> anyhow::Result<()> {
+        let path = Self::path(repo);
+        let lock = path.with_extension("lock");
+        let mut f = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+            .with_context(|| format!("unable to create '{}': File exists", lock.display()))?;
+        let written = self.fmt(&mut f).and_then(|_| f.sync_all());
+        if let Err(e) = written {
+            std::fs::remove_file(&lock)?;
+            Err(e)?;
+        }
+        std::fs::rename(&lock, &path)?;
+        Ok(())
+    }
+
+    /// sets the version the index is written as, which is raised to 3 when needed
+    pub fn set_version(&mut self, version: u32) -> Result<(), IndexError> {
+        if !(2..=4).contains(&version) {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        self.version = version;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    fn position(&self, path: &[u8], stage: u16) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|e| (e.path.as_slice(), e.stage()).cmp(&(path, stage)))
+    }
+
+    /// the stage 0 entry at `path`
+    pub fn get(&self, path: &[u8]) -> Option<&IndexEntry> {
+        self.position(path, 0).ok().map(|i| &self.entries[i])
+    }
+
+    pub fn get_mut(&mut self, path: &[u8]) -> Option<&mut IndexEntry> {
+        self.position(path, 0).ok().map(|i| &mut self.entries[i])
+    }
+
+    /// adds or replaces the entry, resolving conflicts at its path and entries it replaces
+    /// as a file or directory
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.remove(&entry.path);
+        let path = &entry.path;
+        let mut dir = path.as_slice();
+        while let Some(end) = dir.iter().rposition(|&c| c == b'/') {
+            dir = &dir[..end];
+            self.remove(dir);
+        }
+        self.remove_dir(path);
+
+        let i = self.position(path, entry.stage()).unwrap_or_else(|i| i);
+        self.entries.insert(i, entry);
+    }
+
+    /// removes all stages of the entry at `path`
+    pub fn remove(&mut self, path: &[u8]) -> bool {
+        self.invalidate();
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        self.entries.len() != before
+    }
+
+    /// removes all entries below the directory `dir`
+    pub fn remove_dir(&mut self, dir: &[u8]) {
+        self.invalidate();
+        self.entries
+            .retain(|e| !(e.path.starts_with(dir) && e.path.get(dir.len()) == Some(&b'/')));
+    }
+
+    /// whether the file in the worktree may differ from the entry, hashing it if the stat data
+    /// cannot tell
+    pub fn is_modified(
+        &self,
+        repo: &Repository,
+        entry: &IndexEntry,
+        metadata: &Metadata,
+    ) -> anyhow::Result<bool> {
+        let racy = self.timestamp.is_some_and(|t| t <= entry.mtime);
+        if entry.stat_matches(metadata) && !racy {
+            return Ok(false);
+        }
+        let current = IndexEntry::from_worktree(repo, &entry.path, false)?;
+        Ok(current.hash != entry.hash || current.mode != entry.mode)
+    }
+
+    /// drops the extensions caching information about the entries, after they changed
+    fn invalidate(&mut self) {
+        self.extensions
+            .retain(|e| !CACHE_EXTENSIONS.contains(&&e.signature));
+    }
+}
+
+/// files in the worktree matching the pathspec, sorted by path
+pub fn worktree_files(
+    repo: &Repository,
+    pathspec: &Pathspec,
+    respect_ignore: bool,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut ignore = match respect_ignore {
+        true => Ignore::load(repo),
+        false => Ignore::default(),
+    };
+    let mut files = vec![];
+    let walk = WalkDir::new(".")
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let path = relative_path(e.path());
+            let is_dir = e.file_type().is_dir();
+            if e.file_name() == ".git" || ignore.is_ignored(&path, is_dir) {
+                return false;
+            }
+            if is_dir {
+                ignore.add_dir(&path);
+            }
+            true
+        });
+    for entry in walk {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let path = relative_path(entry.path());
+        if pathspec.matches(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// a path as stored in the index, relative to the worktree and without a leading `./`
+fn relative_path(path: &Path) -> Vec<u8> {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    path.as_os_str().as_bytes().to_vec()
+}
+
+/// the variable length integer used for the v4 path prefix compression
+fn offset_varint(s: &[u8]) -> IResult<&[u8], usize> {
+    let (mut s, mut b) = byte(s)?;
+    let mut value = (b & 0x7f) as usize;
+    while b & 0x80 != 0 {
+        (s, b) = byte(s)?;
+        value = ((value + 1) << 7) | (b & 0x7f) as usize;
+    }
+    Ok((s, value))
+}
+
+fn write_offset_varint<W: Write>(f: &mut W, mut value: usize) -> std::io::Result<()> {
+    let mut buf = [0; 16];
+    let mut pos = buf.len() - 1;
+    buf[pos] = (value & 0x7f) as u8;
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        pos -= 1;
+        buf[pos] = 0x80 | (value & 0x7f) as u8;
+        value >>= 7;
+    }
+    f.write_all(&buf[pos..])
+}
+
+fn timestamp(s: &[u8]) -> IResult<&[u8], Timestamp> {
+    let (s, seconds) = be_u32(s)?;
+    let (s, nanoseconds) = be_u32(s)?;
+    Ok((
+        s,
+        Timestamp {
+            seconds,
+            nanoseconds,
+        },
+    ))
+}
+
+fn entry<'a>(version: u32, previous: &[u8], s: &'a [u8]) -> IResult<&'a [u8], IndexEntry> {
+    let start = s;
+    let (s, ctime) = timestamp(s)?;
+    let (s, mtime) = timestamp(s)?;
+    let (s, dev) = be_u32(s)?;
+    let (s, ino) = be_u32(s)?;
+    let (s, mode) = be_u32(s)?;
+    let (s, uid) = be_u32(s)?;
+    let (s, gid) = be_u32(s)?;
+    let (s, size) = be_u32(s)?;
+    let (s, hash) = take(20usize)(s)?;
+    let hash = Hash::from_raw(hash).expect("took 20 bytes");
+    let (s, flags) = be_u16(s)?;
+    let (s, extended_flags) = if flags & EXTENDED != 0 && version >= 3 {
+        be_u16(s)?
+    } else {
+        (s, 0)
+    };
+
+    let (s, path) = if version == 4 {
+        let (s, strip) = offset_varint(s)?;
+        if strip > previous.len() {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                s,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        let (s, suffix) = take_until("\0")(s)?;
+        let (s, _) = tag("\0")(s)?;
+        let mut path = previous[..previous.len() - strip].to_vec();
+        path.extend_from_slice(suffix);
+        (s, path)
+    } else {
+        let (s, path) = if flags & NAME_MASK == NAME_MASK {
+            take_until("\0")(s)?
+        } else {
+            take((flags & NAME_MASK) as usize)(s)?
+        };
+        let consumed = start.len() - s.len();
+        let padding = 8 - consumed % 8;
+        let (s, nul) = take(padding)(s)?;
+        if nul.iter().any(|&b| b != 0) {
+            return Err(nom::Err::Failure(nom::error::Error::new(
+                s,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        (s, path.to_vec())
+    };
+
+    let entry = IndexEntry {
+        ctime,
+        mtime,
+        dev,
+        ino,
+        mode,
+        uid,
+        gid,
+        size,
+        hash,
+        flags: flags & (ASSUME_VALID | STAGE_MASK),
+        extended_flags,
+        path,
+    };
+    Ok((s, entry))
+}
+
+fn extension(s: &[u8]) -> IResult<&[u8], Extension> {
+    let (s, signature) = take(4usize)(s)?;
+    let (s, len) = be_u32(s)?;
+    let (s, data) = take(len as usize)(s)?;
+    let extension = Extension {
+        signature: signature.try_into().expect("took 4 bytes"),
+        data: data.to_vec(),
+    };
+    Ok((s, extension))
+}
+
+impl TryFrom<&[u8]> for Index {
+    type Error = IndexError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        if s.len() < CHECKSUM_LEN {
+            return Err(IndexError::FormatError);
+        }
+        let (content, checksum) = s.split_at(s.len() - CHECKSUM_LEN);
+        // an all zero checksum is written with `index.skipHash`
+        let skipped = checksum.iter().all(|&b| b == 0);
+        if !skipped && Hash::from_bytes(content).as_bytes() != checksum {
+            return Err(IndexError::ChecksumMismatch);
+        }
+
+        fn header(s: &[u8]) -> IResult<&[u8], (&[u8], u32, u32)> {
+            let (s, signature) = take(4usize)(s)?;
+            let (s, version) = be_u32(s)?;
+            let (s, count) = be_u32(s)?;
+            Ok((s, (signature, version, count)))
+        }
+        let (mut s, (signature, version, count)) =
+            header(content).map_err(|_| IndexError::FormatError)?;
+        if signature != SIGNATURE {
+            return Err(IndexError::BadSignature);
+        }
+        if !(2..=4).contains(&version) {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let previous = entries.last().map_or(&[][..], |e| e.path.as_slice());
+            let (rest, entry) = entry(version, previous, s).map_err(|_| IndexError::FormatError)?;
+            entries.push(entry);
+            s = rest;
+        }
+
+        let mut extensions = vec![];
+        while !s.is_empty() {
+            let (rest, extension) = extension(s).map_err(|_| IndexError::FormatError)?;
+            // lowercase extensions are required to understand the index, we know none of them
+            if extension.signature[0].is_ascii_lowercase() {
+                let name = String::from_utf8_lossy(&extension.signature).into_owned();
+                return Err(IndexError::UnknownExtension(name));
+            }
+            if !OFFSET_EXTENSIONS.contains(&&extension.signature) {
+                extensions.push(extension);
+            }
+            s = rest;
+        }
+
+        Ok(Index {
+            version,
+            entries,
+            extensions,
+            timestamp: None,
+        })
+    }
+}
+
+impl Readable for Index {
+    type Error = IndexError;
+
+    fn read<R: Read>(mut r: R) -> Result<Self, ReadError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        let mut contents = Vec::new();
+        r.read_to_end(&mut contents).map_err(ReadError::IoError)?;
+        let index: Self = contents
+            .as_slice()
+            .try_into()
+            .map_err(ReadError::ParseError)?;
+
+        Ok(index)
+    }
+}
+
+impl Writeable for Index {
+    fn fmt<W: Write>(&self, f: &mut W) -> std::io::Result<()> {
+        let extended = self.entries.iter().any(IndexEntry::is_extended);
+        let version = match self.version {
+            2 | 3 if extended => 3,
+            2 | 3 => 2,
+            v => v,
+        };
+
+        let mut body = Vec::new();
+        body.write_all(SIGNATURE)?;
+        body.write_all(&version.to_be_bytes())?;
+        body.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+
+        let mut previous: &[u8] = &[];
+        for entry in &self.entries {
+            let start = body.len();
+            for field in [
+                entry.ctime.seconds,
+                entry.ctime.nanoseconds,
+                entry.mtime.seconds,
+                entry.mtime.nanoseconds,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                body.write_all(&field.to_be_bytes())?;
+            }
+            body.write_all(entry.hash.as_bytes())?;
+
+            let mut flags = entry.flags | entry.path.len().min(NAME_MASK as usize) as u16;
+            if entry.is_extended() {
+                flags |= EXTENDED;
+            }
+            body.write_all(&flags.to_be_bytes())?;
+            if entry.is_extended() {
+                body.write_all(&entry.extended_flags.to_be_bytes())?;
+            }
+
+            if version == 4 {
+                let common = previous
+                    .iter()
+                    .zip(&entry.path)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_offset_varint(&mut body, previous.len() - common)?;
+                body.write_all(&entry.path[common..])?;
+                body.write_all(b"\0")?;
+                previous = &entry.path;
+            } else {
+                body.write_all(&entry.path)?;
+                let padding = 8 - (body.len() - start) % 8;
+                body.write_all(&[0; 8][..padding])?;
+            }
+        }
+
+        for extension in &self.extensions {
+            body.write_all(&extension.signature)?;
+            body.write_all(&(extension.data.len() as u32).to_be_bytes())?;
+            body.write_all(&extension.data)?;
+        }
+
+        let checksum = Hash::from_bytes(&body);
+        f.write_all(&body)?;
+        f.write_all(checksum.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &std::path::Path, args: &[&str]) {
+        let status = Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// an index written by real git, with a long path, an executable and an intent-to-add entry
+    fn real_index(version: &str) -> Vec<u8> {
+        let dir = tempdir::TempDir::new("git-rs-index").unwrap();
+        for file in ["a", "b/c", "b/d", "x"] {
+            std::fs::create_dir_all(dir.path().join(file).parent().unwrap()).unwrap();
+            std::fs::write(dir.path().join(file), file).unwrap();
+        }
+        std::fs::set_permissions(
+            dir.path().join("x"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+        // longer than the file system allows, so it only exists in the index
+        let long = "d/".repeat(2100) + "f";
+        let blob_a = "2e65efe2a145dda7ee51d1741299f848e5bf752e";
+
+        git(dir.path(), &["init"]);
+        git(dir.path(), &["config", "index.version", version]);
+        git(dir.path(), &["add", "a", "b"]);
+        git(
+            dir.path(),
+            &[
+                "update-index",
+                "--add",
+                "--cacheinfo",
+                &format!("100644,{blob_a},{long}"),
+            ],
+        );
+        git(dir.path(), &["add", "-N", "x"]);
+        git(dir.path(), &["write-tree"]);
+        std::fs::read(dir.path().join(".git/index")).unwrap()
+    }
+
+    #[test]
+    fn round_trip_real_index() {
+        for version in ["2", "4"] {
+            let raw = real_index(version);
+            let index = Index::try_from(raw.as_slice()).unwrap();
+            assert_eq!(index.version, if version == "2" { 3 } else { 4 });
+            assert_eq!(index.entries.len(), 5);
+            assert!(index.entries[3].path.len() > NAME_MASK as usize);
+            assert!(index.entries[4].intent_to_add());
+            assert_eq!(index.entries[4].mode, 0o100755);
+            assert_eq!(index.extensions[0].signature, *b"TREE");
+
+            let mut written = vec![];
+            index.fmt(&mut written).unwrap();
+            assert_eq!(written, raw);
+        }
+    }
+
+    #[test]
+    fn version_follows_extended_flags() {
+        let raw = real_index("2");
+        let mut index = Index::try_from(raw.as_slice()).unwrap();
+        index.entries.pop();
+
+        let mut written = vec![];
+        index.fmt(&mut written).unwrap();
+        let index = Index::try_from(written.as_slice()).unwrap();
+        assert_eq!(index.version, 2);
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let mut raw = real_index("2");
+        raw[40] ^= 1;
+        assert!(matches!(
+            Index::try_from(raw.as_slice()),
+            Err(IndexError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn varint() {
+        for value in [0, 1, 127, 128, 255, 16511, 16512, 1 << 20] {
+            let mut buf = vec![];
+            write_offset_varint(&mut buf, value).unwrap();
+            assert_eq!(offset_varint(&buf).unwrap(), (&[][..], value));
+        }
+    }
+}
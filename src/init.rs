@@ -0,0 +1,350 @@
+use std::{
+    fs::Permissions,
+    io::ErrorKind,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use walkdir::WalkDir;
+
+use crate::{
+    config::{self, Config},
+    refs, IoErrorExt,
+};
+
+/// where templates are read from when neither `--template`, `GIT_TEMPLATE_DIR` nor
+/// `init.templateDir` say otherwise
+const DEFAULT_TEMPLATE_DIR: &str = "/usr/share/git-core/templates";
+
+const DESCRIPTION: &str =
+    "Unnamed repository; edit this file 'description' to name the repository.\n";
+
+const EXCLUDE: &str = "# git ls-files --others --exclude-from=.git/info/exclude
+# Lines that start with '#' are comments.
+# For a project mostly in C, the following would be a good set of
+# exclude patterns (uncomment them if you want to use them):
+# *.[oa]
+# *~
+";
+
+const DEFAULT_BRANCH_HINT: &str =
+    "hint: Using 'master' as the name for the initial branch. This default branch name
+hint: is subject to change. To configure the initial branch name to use in all
+hint: of your new repositories, which will suppress this warning, call:
+hint: \n\
+hint: \tgit config --global init.defaultBranch <name>
+hint: \n\
+hint: Names commonly chosen instead of 'master' are 'main', 'trunk' and
+hint: 'development'. The just-created branch can be renamed via this command:
+hint: \n\
+hint: \tgit branch -m <name>";
+
+/// who may access the repository, as `--shared` and `core.sharedRepository` say
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shared {
+    /// permissions are left to the umask
+    Umask,
+    /// the group can write
+    Group,
+    /// the group can write and everybody can read
+    Everybody,
+    /// files get exactly these permissions
+    Mode(u32),
+}
+
+impl FromStr for Shared {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "umask" => return Ok(Shared::Umask),
+            "group" => return Ok(Shared::Group),
+            "all" | "world" | "everybody" => return Ok(Shared::Everybody),
+            _ => {}
+        }
+        if let Ok(mode) = u32::from_str_radix(s, 8) {
+            return match mode {
+                0 => Ok(Shared::Umask),
+                1 => Ok(Shared::Group),
+                2 => Ok(Shared::Everybody),
+                mode if mode & 0o600 != 0o600 || mode > 0o777 => bail!(
+                    "problem with core.sharedRepository filemode value (0{mode:03o}).
+The owner of files must always have read and write permissions."
+                ),
+                mode => Ok(Shared::Mode(mode)),
+            };
+        }
+        match config::parse_bool(Some(s)) {
+            Some(true) => Ok(Shared::Group),
+            Some(false) => Ok(Shared::Umask),
+            None => bail!("bad boolean config value '{s}' for 'arg'"),
+        }
+    }
+}
+
+impl Shared {
+    /// the permissions of a file or directory created with `mode`, like git's `adjust_shared_perm`
+    fn mode(self, mode: u32, dir: bool) -> u32 {
+        let mut new = match self {
+            Shared::Umask => return mode,
+            Shared::Group => mode & 0o777 | 0o660,
+            Shared::Everybody => mode & 0o777 | 0o664,
+            Shared::Mode(shared) => shared & 0o666,
+        };
+        // whoever can read a directory or an executable can also execute it
+        if dir || mode & 0o100 != 0 {
+            new |= (new & 0o444) >> 2;
+        }
+        // new files in shared directories belong to the group of the directory
+        if dir && new & 0o070 != 0 {
+            new |= 0o2000;
+        }
+        new
+    }
+
+    /// the value of `core.sharedRepository`, if it is set
+    fn config_value(self) -> Option<String> {
+        match self {
+            Shared::Umask => None,
+            Shared::Group => Some("1".to_owned()),
+            Shared::Everybody => Some("2".to_owned()),
+            Shared::Mode(mode) => Some(format!("0{mode:03o}")),
+        }
+    }
+}
+
+/// the `init` command
+#[derive(Debug)]
+pub struct Init {
+    pub bare: bool,
+    /// the branch HEAD points to, otherwise `init.defaultBranch` or `master`
+    pub initial_branch: Option<String>,
+    /// where templates are copied from, an empty path copies none
+    pub template: Option<PathBuf>,
+    pub shared: Shared,
+    pub object_format: Option<String>,
+    pub quiet: bool,
+}
+
+impl Init {
+    /// creates a directory, or changes its permissions if it exists already
+    fn create_dir(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir(path)
+            .ignore(ErrorKind::AlreadyExists, ())
+            .with_context(|| format!("cannot mkdir {}", path.display()))?;
+        self.adjust(path)
+    }
+
+    /// writes a file unless it exists already
+    fn create_file(&self, path: &Path, content: &str) -> anyhow::Result<()> {
+        if path.exists() {
+            return Ok(());
+        }
+        std::fs::write(path, content)
+            .with_context(|| format!("cannot write {}", path.display()))?;
+        self.adjust(path)
+    }
+
+    fn adjust(&self, path: &Path) -> anyhow::Result<()> {
+        if self.shared == Shared::Umask {
+            return Ok(());
+        }
+        let metadata = std::fs::symlink_metadata(path)?;
+        if metadata.is_symlink() {
+            return Ok(());
+        }
+        let mode = self
+            .shared
+            .mode(metadata.permissions().mode(), metadata.is_dir());
+        std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+        Ok(())
+    }
+
+    /// copies the template directory into the new git directory, without replacing anything,
+    /// returning false if templates are turned off with an empty `--template`
+    fn copy_templates(&self, config: &Config, git_dir: &Path) -> anyhow::Result<bool> {
+        let (template, explicit) = match &self.template {
+            Some(template) => (template.clone(), true),
+            None => match std::env::var_os("GIT_TEMPLATE_DIR") {
+                Some(template) => (template.into(), true),
+                None => match config.get_path("init.templateDir") {
+                    Some(template) => (template, true),
+                    None => (DEFAULT_TEMPLATE_DIR.into(), false),
+                },
+            },
+        };
+        if template.as_os_str().is_empty() {
+            return Ok(false);
+        }
+        if !template.is_dir() {
+            if explicit {
+                eprintln!("warning: templates not found in {}", template.display());
+            }
+            return Ok(true);
+        }
+        for entry in WalkDir::new(&template).min_depth(1) {
+            let entry = entry?;
+            let target = git_dir.join(entry.path().strip_prefix(&template)?);
+            if entry.file_type().is_dir() {
+                self.create_dir(&target)?;
+            } else if target.symlink_metadata().is_ok() {
+                continue;
+            } else if entry.file_type().is_symlink() {
+                std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+            } else {
+                std::fs::copy(entry.path(), &target)
+                    .with_context(|| format!("cannot copy to {}", target.display()))?;
+                self.adjust(&target)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// whether the file system keeps the executable bit, tried on the config file
+    fn filemode(config: &Path) -> anyhow::Result<bool> {
+        let mode = std::fs::metadata(config)?.permissions().mode();
+        std::fs::set_permissions(config, Permissions::from_mode(mode ^ 0o100))?;
+        let changed = std::fs::metadata(config)?.permissions().mode() != mode;
+        std::fs::set_permissions(config, Permissions::from_mode(mode))?;
+        Ok(changed)
+    }
+
+    /// creates the repository in `directory` or the current directory, or reinitializes it
+    pub fn run(&self, directory: Option<&Path>) -> anyhow::Result<()> {
+        let format_version = match self.object_format.as_deref() {
+            None | Some("sha1") => 0,
+            Some("sha256") => bail!("the sha256 object format is not supported"),
+            Some(format) => bail!("unknown hash algorithm '{format}'"),
+        };
+        let global = Config::load_global()?;
+        let (branch, configured) = match &self.initial_branch {
+            Some(branch) => (branch.clone(), true),
+            None => match global.get("init.defaultBranch") {
+                Some(branch) => (branch.to_owned(), true),
+                None => ("master".to_owned(), false),
+            },
+        };
+        if !refs::valid_ref_name(&format!("refs/heads/{branch}")) {
+            bail!("invalid initial branch name: '{branch}'");
+        }
+
+        if let Some(directory) = directory {
+            std::fs::create_dir_all(directory)
+                .with_context(|| format!("cannot mkdir {}", directory.display()))?;
+            std::env::set_current_dir(directory)
+                .with_context(|| format!("cannot chdir to {}", directory.display()))?;
+        }
+        let git_dir = match std::env::var_os("GIT_DIR") {
+            Some(git_dir) => PathBuf::from(git_dir),
+            None if self.bare => PathBuf::from("."),
+            None => PathBuf::from(".git"),
+        };
+        let reinit = git_dir.join("HEAD").exists();
+
+        self.create_dir(&git_dir)?;
+        // what the templates of upstream have, in case they are not installed
+        if self.copy_templates(&global, &git_dir)? {
+            self.create_file(&git_dir.join("description"), DESCRIPTION)?;
+            self.create_dir(&git_dir.join("info"))?;
+            self.create_file(&git_dir.join("info/exclude"), EXCLUDE)?;
+        }
+        for dir in [
+            "refs",
+            "refs/heads",
+            "refs/tags",
+            "objects",
+            "objects/info",
+            "objects/pack",
+        ] {
+            self.create_dir(&git_dir.join(dir))?;
+        }
+
+        match reinit {
+            true if self.initial_branch.is_some() => {
+                eprintln!("warning: re-init: ignored --initial-branch={branch}")
+            }
+            true => {}
+            false => {
+                if !configured {
+                    eprintln!("{DEFAULT_BRANCH_HINT}");
+                }
+                self.create_file(
+                    &git_dir.join("HEAD"),
+                    &format!("ref: refs/heads/{branch}\n"),
+                )?;
+            }
+        }
+
+        let config_path = git_dir.join("config");
+        let existing = Config::load_file(&config_path, Some(&git_dir))?;
+        let version = existing
+            .get("core.repositoryformatversion")
+            .and_then(config::parse_int)
+            .unwrap_or(format_version);
+        if version > 1 {
+            bail!("unknown repository format version: {version}");
+        }
+        config::set(
+            &config_path,
+            "core.repositoryformatversion",
+            &version.to_string(),
+        )?;
+        let filemode = Self::filemode(&config_path)?;
+        config::set(&config_path, "core.filemode", &filemode.to_string())?;
+        // a repository keeps being bare or not unless told otherwise
+        if self.bare || !reinit {
+            config::set(&config_path, "core.bare", &self.bare.to_string())?;
+        }
+        if !self.bare && !reinit {
+            config::set(&config_path, "core.logallrefupdates", "true")?;
+        }
+        if let Some(shared) = self.shared.config_value() {
+            config::set(&config_path, "core.sharedrepository", &shared)?;
+            config::set(&config_path, "receive.denyNonFastforwards", "true")?;
+        }
+        self.adjust(&config_path)?;
+
+        if !self.quiet {
+            let path = git_dir.canonicalize()?;
+            let shared = match self.shared {
+                Shared::Umask => "",
+                _ => "shared ",
+            };
+            match reinit {
+                true => println!(
+                    "Reinitialized existing {shared}Git repository in {}/",
+                    path.display()
+                ),
+                false => println!(
+                    "Initialized empty {shared}Git repository in {}/",
+                    path.display()
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared() {
+        assert_eq!("group".parse::<Shared>().unwrap(), Shared::Group);
+        assert_eq!("true".parse::<Shared>().unwrap(), Shared::Group);
+        assert_eq!("world".parse::<Shared>().unwrap(), Shared::Everybody);
+        assert_eq!("false".parse::<Shared>().unwrap(), Shared::Umask);
+        assert_eq!("0640".parse::<Shared>().unwrap(), Shared::Mode(0o640));
+        assert!("0440".parse::<Shared>().is_err());
+        assert!("bogus".parse::<Shared>().is_err());
+
+        assert_eq!(Shared::Group.mode(0o755, true), 0o2775);
+        assert_eq!(Shared::Group.mode(0o644, false), 0o664);
+        assert_eq!(Shared::Mode(0o640).mode(0o755, false), 0o750);
+        assert_eq!(Shared::Mode(0o600).mode(0o755, true), 0o700);
+        assert_eq!(Shared::Umask.mode(0o644, false), 0o644);
+    }
+}
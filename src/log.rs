@@ -0,0 +1,794 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{stdout, BufWriter, Write},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use chrono::{format::StrftimeItems, DateTime, FixedOffset, Local};
+
+use crate::{
+    graph::Graph,
+    hash::Hash,
+    object::{Commit, Event, Object, Offset},
+    refs::{self, RefValue},
+    repository::Repository,
+    revision,
+    walk::{self, WalkOptions},
+    BlobType, Writeable,
+};
+
+#[derive(Debug, derive_more::Display, Clone, thiserror::Error)]
+pub enum FormatError {
+    #[display(fmt = "unknown date format {_0}")]
+    UnknownDate(String),
+    #[display(fmt = "invalid --pretty format: {_0}")]
+    UnknownPretty(String),
+}
+
+/// how dates are shown, from `--date`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DateMode {
+    /// like `Thu Apr 7 15:13:13 2005 -0700`
+    #[default]
+    Default,
+    /// like `2 weeks ago`
+    Relative,
+    /// the default format in the local timezone
+    Local,
+    /// like `2005-04-07 15:13:13 -0700`
+    Iso,
+    /// like `2005-04-07T15:13:13-07:00`
+    IsoStrict,
+    /// like `Thu, 7 Apr 2005 15:13:13 -0700`
+    Rfc,
+    /// like `2005-04-07`
+    Short,
+    /// seconds since the epoch
+    Unix,
+    /// seconds since the epoch and the offset, as stored
+    Raw,
+    /// a strftime format
+    Format(String),
+}
+
+impl FromStr for DateMode {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "default" => DateMode::Default,
+            "relative" => DateMode::Relative,
+            "local" => DateMode::Local,
+            "iso" | "iso8601" => DateMode::Iso,
+            "iso-strict" | "iso8601-strict" => DateMode::IsoStrict,
+            "rfc" | "rfc2822" => DateMode::Rfc,
+            "short" => DateMode::Short,
+            "unix" => DateMode::Unix,
+            "raw" => DateMode::Raw,
+            s => match s.strip_prefix("format:") {
+                Some(format)
+                    if !StrftimeItems::new(format)
+                        .any(|item| item == chrono::format::Item::Error) =>
+                {
+                    DateMode::Format(format.to_owned())
+                }
+                _ => return Err(FormatError::UnknownDate(s.to_owned())),
+            },
+        })
+    }
+}
+
+/// how long ago something happened, `diff` seconds before now, rounded like git does
+fn relative(diff: i64) -> String {
+    fn ago(n: i64, unit: &str) -> String {
+        match n {
+            1 => format!("1 {unit} ago"),
+            n => format!("{n} {unit}s ago"),
+        }
+    }
+
+    if diff < 0 {
+        return "in the future".to_owned();
+    }
+    if diff < 90 {
+        return ago(diff, "second");
+    }
+    let minutes = (diff + 30) / 60;
+    if minutes < 90 {
+        return ago(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return ago(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return ago(days, "day");
+    }
+    if days < 70 {
+        return ago((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return ago((days + 15) / 30, "month");
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return ago(years, "year");
+        }
+        let years = match years {
+            1 => "1 year".to_owned(),
+            n => format!("{n} years"),
+        };
+        return format!("{years}, {}", ago(months, "month"));
+    }
+    ago((days + 183) / 365, "year")
+}
+
+impl DateMode {
+    pub fn show(&self, event: &Event) -> String {
+        let seconds = event.offset().seconds();
+        // valid offsets are within a day
+        let zone = FixedOffset::east_opt(seconds).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        let offset = Offset::from_seconds(zone.local_minus_utc());
+        let time = DateTime::from_timestamp(event.timestamp(), 0)
+            .unwrap_or_default()
+            .with_timezone(&zone);
+        match self {
+            DateMode::Default => format!("{} {offset}", time.format("%a %b %-d %H:%M:%S %Y")),
+            DateMode::Relative => relative(Local::now().timestamp() - event.timestamp()),
+            DateMode::Local => time
+                .with_timezone(&Local)
+                .format("%a %b %-d %H:%M:%S %Y")
+                .to_string(),
+            DateMode::Iso => format!("{} {offset}", time.format("%Y-%m-%d %H:%M:%S")),
+            DateMode::IsoStrict => time.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            DateMode::Rfc => format!("{} {offset}", time.format("%a, %-d %b %Y %H:%M:%S")),
+            DateMode::Short => time.format("%Y-%m-%d").to_string(),
+            DateMode::Unix => event.timestamp().to_string(),
+            DateMode::Raw => format!("{} {offset}", event.timestamp()),
+            DateMode::Format(format) => time.format(format).to_string(),
+        }
+    }
+}
+
+/// how commits are shown, from `--pretty` or `--format`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Pretty {
+    /// the hash and subject on one line
+    Oneline,
+    /// the hash, author and subject
+    Short,
+    /// the hash, author, date and message
+    #[default]
+    Medium,
+    /// the hash, author, committer and message
+    Full,
+    /// the hash, author, committer, their dates and message
+    Fuller,
+    /// the commit's headers and message
+    Raw,
+    /// a format with `%` placeholders, ending each commit with a newline for `tformat:`, or
+    /// separating them with one for `format:`
+    Format { format: String, terminator: bool },
+}
+
+impl FromStr for Pretty {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "oneline" => Pretty::Oneline,
+            "short" => Pretty::Short,
+            "medium" => Pretty::Medium,
+            "full" => Pretty::Full,
+            "fuller" => Pretty::Fuller,
+            "raw" => Pretty::Raw,
+            s => {
+                let (format, terminator) = match s.split_once(':') {
+                    Some(("format", format)) => (format, false),
+                    Some(("tformat", format)) => (format, true),
+                    // anything with a placeholder is a format on its own
+                    _ if s.contains('%') => (s, true),
+                    _ => return Err(FormatError::UnknownPretty(s.to_owned())),
+                };
+                Pretty::Format {
+                    format: format.to_owned(),
+                    terminator,
+                }
+            }
+        })
+    }
+}
+
+/// how refs pointing to commits are shown next to them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decorate {
+    #[default]
+    No,
+    /// like `main` and `tag: v1`
+    Short,
+    /// like `refs/heads/main` and `tag: refs/tags/v1`
+    Full,
+}
+
+/// the refs pointing to each commit, in the order they are shown
+struct Decorations {
+    refs: HashMap<Hash, Vec<String>>,
+    /// the branch HEAD points to, shown as `HEAD -> branch`
+    current: Option<String>,
+}
+
+impl Decorations {
+    fn load(repo: &Repository) -> anyhow::Result<Self> {
+        let mut refs: HashMap<Hash, Vec<String>> = HashMap::new();
+        // like git, later refs come first, and HEAD comes before all of them
+        let mut add = |hash: Hash, name: String| -> anyhow::Result<()> {
+            let mut hash = hash;
+            loop {
+                refs.entry(hash.clone())
+                    .or_default()
+                    .insert(0, name.clone());
+                match repo.read_object(&hash)? {
+                    Object::Tag(tag) => hash = tag.object().clone(),
+                    _ => return Ok(()),
+                }
+            }
+        };
+        for (name, hash) in refs::list(repo, "refs/")? {
+            add(hash, name)?;
+        }
+        let mut current = None;
+        if let Some(hash) = refs::resolve(repo, "HEAD")? {
+            add(hash, "HEAD".to_owned())?;
+            if let Some(RefValue::Symbolic(branch)) = refs::read(repo, "HEAD")? {
+                current = Some(branch);
+            }
+        }
+        Ok(Self { refs, current })
+    }
+
+    /// the refs pointing to a commit, like `HEAD -> main, tag: v1, topic`
+    fn show(&self, hash: &Hash, decorate: Decorate) -> Option<String> {
+        let names = self.refs.get(hash)?;
+        let short = |name: &str| -> String {
+            let (kind, short) = ["refs/heads/", "refs/remotes/", "refs/tags/"]
+                .iter()
+                .find_map(|prefix| Some((*prefix, name.strip_prefix(prefix)?)))
+                .unwrap_or(("", name));
+            let name = match decorate {
+                Decorate::Full => name,
+                _ => short,
+            };
+            match kind {
+                "refs/tags/" => format!("tag: {name}"),
+                _ => name.to_owned(),
+            }
+        };
+        let current = self
+            .current
+            .as_ref()
+            .filter(|current| names.contains(current));
+        let mut shown = vec![];
+        for name in names {
+            match current {
+                Some(current) if name == "HEAD" => {
+                    shown.push(format!("HEAD -> {}", short(current)));
+                }
+                Some(current) if name == current => {}
+                _ => shown.push(short(name)),
+            }
+        }
+        Some(shown.join(", "))
+    }
+}
+
+/// the first paragraph of a message joined into one line, and the rest after blank lines
+fn split_message(message: &str) -> (String, &str) {
+    let blank = |line: &str| line.trim().is_empty();
+    let mut lines = message.split_inclusive('\n').skip_while(|line| blank(line));
+    let subject: Vec<&str> = lines
+        .by_ref()
+        .take_while(|line| !blank(line))
+        .map(str::trim_end)
+        .collect();
+    let body: String = lines.skip_while(|line| blank(line)).collect();
+    (subject.join(" "), &message[message.len() - body.len()..])
+}
+
+/// expands tabs to the next multiple of 8 characters
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    for c in line.chars() {
+        match c {
+            '\t' => {
+                let spaces = 8 - expanded.chars().count() % 8;
+                expanded.push_str(&" ".repeat(spaces));
+            }
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+/// the author or committer placeholder `%a<what>` or `%c<what>`, if it is one
+fn person(event: &Event, what: char, date: &DateMode) -> Option<String> {
+    Some(match what {
+        'n' => event.name().to_owned(),
+        'e' => event.email().to_owned(),
+        'l' => event
+            .email()
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .to_owned(),
+        'd' => date.show(event),
+        'D' => DateMode::Rfc.show(event),
+        'r' => DateMode::Relative.show(event),
+        't' => DateMode::Unix.show(event),
+        'i' => DateMode::Iso.show(event),
+        'I' => DateMode::IsoStrict.show(event),
+        's' => DateMode::Short.show(event),
+        _ => return None,
+    })
+}
+
+/// what is needed to show one commit
+struct Shown<'a> {
+    hash: &'a Hash,
+    commit: &'a Commit,
+    /// the parents after simplification, which are shown instead of the commit's own
+    parents: &'a [Hash],
+}
+
+/// the `log` command
+#[derive(Debug)]
+pub struct Log {
+    pub options: WalkOptions,
+    pub pretty: Pretty,
+    pub abbrev_commit: bool,
+    pub graph: bool,
+    pub decorate: Decorate,
+    pub date: DateMode,
+    /// starts from all refs and HEAD
+    pub all: bool,
+}
+
+/// what is written for each commit, with the graph drawn to the left of each line
+struct Writer<'a, W: Write> {
+    log: &'a Log,
+    repo: &'a Repository,
+    out: W,
+    graph: Option<Graph>,
+    decorations: Option<Decorations>,
+    abbrev_len: usize,
+    abbreviated: HashMap<Hash, String>,
+    /// whether a commit was shown, so that the next is separated from it
+    shown_one: bool,
+    /// whether the last commit's message did not end in a newline
+    missing_newline: bool,
+}
+
+impl<W: Write> Writer<'_, W> {
+    fn abbreviate(&mut self, hash: &Hash) -> anyhow::Result<String> {
+        if let Some(abbreviated) = self.abbreviated.get(hash) {
+            return Ok(abbreviated.clone());
+        }
+        let abbreviated = revision::abbreviate(self.repo, hash, self.abbrev_len)?;
+        self.abbreviated.insert(hash.clone(), abbreviated.clone());
+        Ok(abbreviated)
+    }
+
+    fn decorations(&mut self, hash: &Hash, decorate: Decorate) -> anyhow::Result<Option<String>> {
+        if self.decorations.is_none() {
+            self.decorations = Some(Decorations::load(self.repo)?);
+        }
+        Ok(self.decorations.as_ref().unwrap().show(hash, decorate))
+    }
+
+    /// the graph up to and including the start of the commit's line
+    fn graph_commit(&mut self) -> anyhow::Result<()> {
+        let Some(graph) = &mut self.graph else {
+            return Ok(());
+        };
+        loop {
+            let (line, commit_line) = graph.next_line();
+            self.out.write_all(line.as_bytes())?;
+            if commit_line {
+                return Ok(());
+            }
+            writeln!(self.out)?;
+        }
+    }
+
+    /// the start of the next line of the graph
+    fn graph_line(&mut self) -> anyhow::Result<()> {
+        if let Some(graph) = &mut self.graph {
+            self.out.write_all(graph.next_line().0.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn graph_padding(&mut self) -> anyhow::Result<()> {
+        if let Some(graph) = &mut self.graph {
+            self.out.write_all(graph.padding().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// writes the message with the graph before each line but the first, and then the rest of
+    /// the graph for the commit
+    fn message(&mut self, message: &str) -> anyhow::Result<()> {
+        let mut lines = message.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            self.out.write_all(line.as_bytes())?;
+            if line.ends_with('\n') && lines.peek().is_some() {
+                self.graph_line()?;
+            }
+        }
+        let Some(graph) = &mut self.graph else {
+            return Ok(());
+        };
+        if graph.is_finished() {
+            return Ok(());
+        }
+        let newline_terminated = message.ends_with('\n');
+        if !newline_terminated {
+            writeln!(self.out)?;
+        }
+        loop {
+            self.out.write_all(graph.next_line().0.as_bytes())?;
+            if graph.is_finished() {
+                break;
+            }
+            writeln!(self.out)?;
+        }
+        if newline_terminated {
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    /// the person and date lines of the built in formats, and the indented message
+    fn pretty(&mut self, shown: &Shown) -> anyhow::Result<String> {
+        let Shown {
+            commit, parents, ..
+        } = *shown;
+        let pretty = &self.log.pretty;
+        let date = |event: &Event| self.log.date.show(event);
+        let who = |event: &Event| format!("{} <{}>", event.name(), event.email());
+        let message = String::from_utf8_lossy(commit.message());
+        let mut out = String::new();
+        if *pretty == Pretty::Oneline {
+            return Ok(split_message(&message).0);
+        }
+
+        if *pretty == Pretty::Raw {
+            let mut raw = vec![];
+            Writeable::fmt(commit, &mut raw)?;
+            let raw = String::from_utf8_lossy(&raw);
+            let body = raw.split_once('\0').map_or(&*raw, |(_, body)| body);
+            let headers = body.split_once("\n\n").map_or(body, |(headers, _)| headers);
+            for line in headers.lines() {
+                if line.starts_with("parent ") {
+                    continue;
+                }
+                writeln!(out, "{line}")?;
+                if line.starts_with("tree ") {
+                    for parent in parents {
+                        writeln!(out, "parent {parent}")?;
+                    }
+                }
+            }
+        } else {
+            if parents.len() > 1 {
+                let mut merge = vec![];
+                for parent in parents {
+                    merge.push(self.abbreviate(parent)?);
+                }
+                writeln!(out, "Merge: {}", merge.join(" "))?;
+            }
+            let (author, committer) = (commit.author(), commit.committer());
+            match pretty {
+                Pretty::Short => writeln!(out, "Author: {}", who(author))?,
+                Pretty::Medium => {
+                    writeln!(out, "Author: {}", who(author))?;
+                    writeln!(out, "Date:   {}", date(author))?;
+                }
+                Pretty::Full => {
+                    writeln!(out, "Author: {}", who(author))?;
+                    writeln!(out, "Commit: {}", who(committer))?;
+                }
+                Pretty::Fuller => {
+                    writeln!(out, "Author:     {}", who(author))?;
+                    writeln!(out, "AuthorDate: {}", date(author))?;
+                    writeln!(out, "Commit:     {}", who(committer))?;
+                    writeln!(out, "CommitDate: {}", date(committer))?;
+                }
+                _ => {}
+            }
+        }
+        writeln!(out)?;
+
+        let expand = matches!(pretty, Pretty::Medium | Pretty::Full | Pretty::Fuller);
+        let lines = message.lines().skip_while(|line| line.trim().is_empty());
+        for line in lines {
+            let line = line.trim_end();
+            if line.is_empty() && *pretty == Pretty::Short {
+                break;
+            }
+            match expand {
+                true => writeln!(out, "    {}", expand_tabs(line))?,
+                false => writeln!(out, "    {line}")?,
+            }
+        }
+        let end = out.trim_end().len();
+        out.truncate(end);
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// expands the `%` placeholders of a format, leaving unknown ones as they are
+    fn format(&mut self, format: &str, shown: &Shown) -> anyhow::Result<String> {
+        let Shown {
+            hash,
+            commit,
+            parents,
+        } = *shown;
+        let message = String::from_utf8_lossy(commit.message());
+        let (subject, body) = split_message(&message);
+        let mut out = String::new();
+        let mut rest = format;
+        while let Some(percent) = rest.find('%') {
+            out.push_str(&rest[..percent]);
+            rest = &rest[percent + 1..];
+
+            // `%+x` adds a newline before a non-empty expansion, `% x` a space, and `%-x`
+            // removes the newlines before an empty one
+            let modifier = rest.chars().next().filter(|c| matches!(c, '+' | '-' | ' '));
+            let spec = match modifier {
+                Some(_) => &rest[1..],
+                None => rest,
+            };
+
+            let mut chars = spec.chars();
+            let (expansion, len) = match (chars.next(), chars.next()) {
+                (Some('%'), _) if modifier.is_none() => ("%".to_owned(), 1),
+                (Some('n'), _) => ("\n".to_owned(), 1),
+                (Some('x'), _) => {
+                    match spec
+                        .get(1..3)
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    {
+                        Some(byte) => ((byte as char).to_string(), 3),
+                        None => (String::new(), 0),
+                    }
+                }
+                (Some('H'), _) => (hash.to_string(), 1),
+                (Some('h'), _) => (self.abbreviate(hash)?, 1),
+                (Some('T'), _) => (commit.tree().to_string(), 1),
+                (Some('t'), _) => (self.abbreviate(commit.tree())?, 1),
+                (Some('P'), _) => (
+                    parents
+                        .iter()
+                        .map(Hash::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    1,
+                ),
+                (Some('p'), _) => {
+                    let mut abbreviated = vec![];
+                    for parent in parents {
+                        abbreviated.push(self.abbreviate(parent)?);
+                    }
+                    (abbreviated.join(" "), 1)
+                }
+                (Some('s'), _) => (subject.clone(), 1),
+                (Some('b'), _) => (body.to_owned(), 1),
+                (Some('B'), _) => (message.to_string(), 1),
+                (Some('e'), _) => (String::new(), 1),
+                (Some('d'), _) => {
+                    let decorations = self.decorations(hash, Decorate::Short)?;
+                    (
+                        decorations.map(|d| format!(" ({d})")).unwrap_or_default(),
+                        1,
+                    )
+                }
+                (Some('D'), _) => (
+                    self.decorations(hash, Decorate::Short)?.unwrap_or_default(),
+                    1,
+                ),
+                (Some(who @ ('a' | 'c')), Some(what)) => {
+                    let event = match who {
+                        'a' => commit.author(),
+                        _ => commit.committer(),
+                    };
+                    match person(event, what, &self.log.date) {
+                        Some(expansion) => (expansion, 2),
+                        None => (String::new(), 0),
+                    }
+                }
+                _ => (String::new(), 0),
+            };
+
+            if len == 0 {
+                out.push('%');
+                continue;
+            }
+            rest = &spec[len..];
+            match modifier {
+                Some('+') if !expansion.is_empty() => out.push('\n'),
+                Some(' ') if !expansion.is_empty() => out.push(' '),
+                Some('-') if expansion.is_empty() => {
+                    let end = out.trim_end_matches('\n').len();
+                    out.truncate(end);
+                }
+                _ => {}
+            }
+            out.push_str(&expansion);
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// writes one commit, like git's `show_log`
+    fn show(&mut self, shown: &Shown) -> anyhow::Result<()> {
+        let log = self.log;
+        let terminator = match &log.pretty {
+            Pretty::Oneline => true,
+            Pretty::Format { terminator, .. } => *terminator,
+            _ => false,
+        };
+        if self.shown_one && !terminator {
+            // separating newlines continue the graph, unless the last message ended mid-line
+            if !self.missing_newline {
+                self.graph_padding()?;
+            }
+            writeln!(self.out)?;
+        }
+        self.shown_one = true;
+        self.graph_commit()?;
+
+        let message = match &log.pretty {
+            Pretty::Format { format, .. } => self.format(format, shown)?,
+            pretty => {
+                if *pretty != Pretty::Oneline {
+                    write!(self.out, "commit ")?;
+                }
+                let hash = match log.abbrev_commit {
+                    true => self.abbreviate(shown.hash)?,
+                    false => shown.hash.to_string(),
+                };
+                write!(self.out, "{hash}")?;
+                if log.decorate != Decorate::No {
+                    if let Some(decorations) = self.decorations(shown.hash, log.decorate)? {
+                        write!(self.out, " ({decorations})")?;
+                    }
+                }
+                match pretty {
+                    Pretty::Oneline => write!(self.out, " ")?,
+                    _ => {
+                        writeln!(self.out)?;
+                        self.graph_line()?;
+                    }
+                }
+                self.pretty(shown)?
+            }
+        };
+        self.missing_newline = !message.ends_with('\n');
+        self.message(&message)?;
+
+        let empty = matches!(&log.pretty, Pretty::Format { format, .. } if format.is_empty());
+        if terminator && !empty {
+            if !self.missing_newline {
+                self.graph_padding()?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Log {
+    pub fn run(&self, repo: &Repository, revs: &[String]) -> anyhow::Result<()> {
+        let mut include = vec![];
+        let mut exclude = vec![];
+        if self.all {
+            include.extend(revision::all_tips(repo)?);
+        }
+        for rev in revs {
+            let (positive, negative) = revision::range(repo, rev)?;
+            include.extend(positive);
+            exclude.extend(negative);
+        }
+        if revs.is_empty() && !self.all {
+            let (branch, head) = refs::follow(repo, "HEAD")?;
+            let head = head.with_context(|| {
+                format!(
+                    "your current branch '{}' does not have any commits yet",
+                    refs::shorten(&branch)
+                )
+            })?;
+            include.push(head);
+        }
+        let peel = |hashes: Vec<Hash>| -> anyhow::Result<Vec<Hash>> {
+            hashes
+                .into_iter()
+                .map(|hash| revision::peel(repo, "", hash, BlobType::Commit))
+                .collect()
+        };
+        let (include, exclude) = (peel(include)?, peel(exclude)?);
+
+        let mut options = self.options.clone();
+        // the graph needs parents after their children
+        if self.graph && options.order == walk::Order::Default {
+            options.order = walk::Order::Topo;
+        }
+        let history = walk::history(repo, &include, &exclude, &options)?;
+
+        let mut writer = Writer {
+            log: self,
+            repo,
+            out: BufWriter::new(stdout().lock()),
+            graph: self.graph.then(Graph::new),
+            decorations: None,
+            abbrev_len: revision::abbrev_len(repo)?,
+            abbreviated: HashMap::new(),
+            shown_one: false,
+            missing_newline: false,
+        };
+        for hash in &history.commits {
+            let commit = repo.read_commit(hash)?;
+            let rewritten = &history.parents[hash];
+            if let Some(graph) = &mut writer.graph {
+                graph.update(hash.clone(), rewritten.clone());
+            }
+            // like git, parents are only rewritten for the graph
+            let parents = match self.graph {
+                true => rewritten.as_slice(),
+                false => commit.parents(),
+            };
+            let shown = Shown {
+                hash,
+                commit: &commit,
+                parents,
+            };
+            writer.show(&shown)?;
+        }
+        Ok(writer.out.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_dates() {
+        let day = 86400;
+        assert_eq!(relative(-5), "in the future");
+        assert_eq!(relative(1), "1 second ago");
+        assert_eq!(relative(89), "89 seconds ago");
+        assert_eq!(relative(90), "2 minutes ago");
+        assert_eq!(relative(3 * 3600), "3 hours ago");
+        assert_eq!(relative(36 * 3600), "2 days ago");
+        assert_eq!(relative(20 * day), "3 weeks ago");
+        assert_eq!(relative(100 * day), "3 months ago");
+        assert_eq!(relative(365 * day), "1 year ago");
+        assert_eq!(relative(550 * day), "1 year, 6 months ago");
+        assert_eq!(relative(9000 * day), "25 years ago");
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(split_message("subject\n"), ("subject".to_owned(), ""));
+        assert_eq!(
+            split_message("\nsubject\nmore\n\n\n  body\n\nend\n"),
+            ("subject more".to_owned(), "  body\n\nend\n")
+        );
+        assert_eq!(expand_tabs("a\tb"), "a       b");
+    }
+}
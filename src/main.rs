@@ -2,23 +2,43 @@ use anyhow::{bail, Context};
 use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use hash::Hash;
 use itertools::Itertools;
-use object::{Blob, Object, Tree, ZlibReadExt, ZlibWriter};
+use object::{Object, Tree, ZlibWriter};
 use std::{
     fmt::Debug,
-    fs::{create_dir, File},
-    io::{self, stdout, BufRead, BufReader, Write},
+    fs::File,
+    io::{self, stdout, BufRead, BufReader, IsTerminal, Read, Write},
     path::{Path, PathBuf},
     process::ExitCode,
 };
 use walkdir::WalkDir;
 
-use crate::object::{Commit, Event};
+use crate::ident::Role;
+use crate::index::Index;
+use crate::object::{Commit, Tag};
+use crate::pathspec::{wildmatch, Pathspec};
+use crate::refs::{Expect, Transaction};
+use crate::repository::Repository;
+use crate::revision::RevisionError;
+mod commit;
+mod commit_graph;
+mod config;
+mod diff;
+mod graph;
 mod hash;
+mod ident;
+mod ignore;
+mod index;
+mod init;
+mod log;
+mod merge_base;
 mod object;
-
-pub fn root() -> PathBuf {
-    ".git".into()
-}
+mod pack;
+mod pathspec;
+mod refs;
+mod repository;
+mod revision;
+mod staging;
+mod walk;
 
 trait PathBufExt {
     fn push_dir<P: AsRef<Path>>(self, path: P) -> Self;
@@ -75,6 +95,12 @@ pub trait Readable {
         Self: Sized;
 }
 
+impl Writeable for Vec<u8> {
+    fn fmt<W: std::io::Write>(&self, f: &mut W) -> std::io::Result<()> {
+        f.write_all(self)
+    }
+}
+
 impl<T> Writeable for &T
 where
     T: Writeable,
@@ -86,13 +112,50 @@ where
 
 #[derive(Debug, Parser)]
 struct Cli {
+    /// runs as if started in this directory, relative to the previous one if given again
+    #[clap(short = 'C', value_name = "PATH")]
+    dir: Vec<PathBuf>,
+    /// uses this git directory instead of searching for one, like `GIT_DIR`
+    #[clap(long, value_name = "PATH")]
+    git_dir: Option<PathBuf>,
+
     #[clap(subcommand)]
     subcommand: Command,
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
-    Init,
+    /// Creates an empty repository or reinitializes an existing one
+    Init {
+        /// creates a repository without a worktree
+        #[clap(long)]
+        bare: bool,
+        /// the branch HEAD points to, instead of init.defaultBranch or master
+        #[clap(short = 'b', long, value_name = "BRANCH")]
+        initial_branch: Option<String>,
+        /// copies the files in this directory into the git directory
+        #[clap(long, value_name = "DIR")]
+        template: Option<String>,
+        /// makes the repository writable by the group, everybody or with the given mode
+        #[clap(
+            long,
+            value_name = "PERMISSIONS",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "group"
+        )]
+        shared: Option<String>,
+        /// the hash algorithm of the repository
+        #[clap(long, value_name = "FORMAT")]
+        object_format: Option<String>,
+        /// only prints errors and warnings
+        #[clap(short, long)]
+        quiet: bool,
+
+        /// where to create the repository, created if it does not exist
+        directory: Option<PathBuf>,
+    },
+    #[clap(group(ArgGroup::new("batch_mode").args(&["batch", "batch_check", "batch_command"])))]
     CatFile {
         /// pretty-prints object
         #[clap(short = 'p', group = "mode")]
@@ -102,20 +165,58 @@ enum Command {
         #[clap(short = 'e', group = "mode")]
         exists: bool,
 
-        #[clap(requires = "mode")]
-        object: String,
+        /// prints the type of the object
+        #[clap(short = 't', group = "mode")]
+        typ: bool,
+
+        /// prints the size of the object
+        #[clap(short = 's', group = "mode")]
+        size: bool,
+
+        /// prints info and content of the objects named on stdin
+        #[clap(long, num_args = 0..=1, require_equals = true, value_name = "FORMAT", default_missing_value = Batch::DEFAULT_FORMAT, conflicts_with = "mode")]
+        batch: Option<String>,
+
+        /// prints info of the objects named on stdin
+        #[clap(long, num_args = 0..=1, require_equals = true, value_name = "FORMAT", default_missing_value = Batch::DEFAULT_FORMAT, conflicts_with = "mode")]
+        batch_check: Option<String>,
+
+        /// reads `contents <object>`, `info <object>` and `flush` commands from stdin
+        #[clap(long, num_args = 0..=1, require_equals = true, value_name = "FORMAT", default_missing_value = Batch::DEFAULT_FORMAT, conflicts_with = "mode")]
+        batch_command: Option<String>,
+
+        /// processes every object in the repository instead of reading stdin
+        #[clap(long, requires = "batch_mode")]
+        batch_all_objects: bool,
+
+        /// only flushes the output when the buffer is full or on `flush`
+        #[clap(long, requires = "batch_mode")]
+        buffer: bool,
+
+        /// the expected type of the object when printing its raw content, or the object
+        #[clap(value_name = "TYPE", conflicts_with = "batch_mode")]
+        type_or_object: Option<String>,
+
+        #[clap(conflicts_with = "mode")]
+        object: Option<String>,
     },
-    #[clap(group(ArgGroup::new("input").required(true).args(&["file", "stdin"])  ))]
+    #[clap(group(ArgGroup::new("input").required(true).args(&["file", "stdin", "stdin_paths"])  ))]
     HashObject {
         /// Writes the object back to the store
         #[clap(short)]
         write: bool,
         /// Type of the object
-        #[clap(short, value_enum)]
-        typ: Option<BlobType>,
+        #[clap(short, default_value_t = BlobType::Blob.to_string())]
+        typ: String,
         /// Read from stdin instead of file
         #[clap(long)]
         stdin: bool,
+        /// Read file names from stdin, one per line, instead of file
+        #[clap(long)]
+        stdin_paths: bool,
+        /// Hashes the content without checking that it is a valid object of its type
+        #[clap(long)]
+        literally: bool,
 
         /// The file
         file: Option<String>,
@@ -132,20 +233,527 @@ enum Command {
         tree_hash: String,
     },
 
-    WriteTree {},
+    /// Writes the index as a tree object
+    WriteTree {
+        /// writes the tree of this subdirectory instead
+        #[clap(long, value_name = "DIR")]
+        prefix: Option<String>,
+        /// does not check that the objects in the index exist
+        #[clap(long)]
+        missing_ok: bool,
+        /// snapshots every file in the worktree instead of the index
+        #[clap(long, conflicts_with_all = ["prefix", "missing_ok"])]
+        from_worktree: bool,
+    },
 
     CommitTree {
         #[clap(short)]
-        parent: Vec<Hash>,
+        parent: Vec<String>,
+        /// a paragraph of the message, which is read from stdin if there is none
         #[clap(short)]
         message: Vec<String>,
+        /// overrides the author, given as `Name <email>`
+        #[clap(long)]
+        author: Option<String>,
+
+        tree: String,
+    },
+
+    /// Records the index as a new commit on the current branch
+    Commit {
+        /// the commit message, several are joined as paragraphs
+        #[clap(short, long)]
+        message: Vec<String>,
+        /// reads the commit message from a file, or stdin for `-`
+        #[clap(short = 'F', long, conflicts_with = "message")]
+        file: Option<PathBuf>,
+        /// replaces the commit HEAD points to instead of adding one on top
+        #[clap(long)]
+        amend: bool,
+        /// commits even if the tree is the same as the parent's
+        #[clap(long)]
+        allow_empty: bool,
+        /// overrides the author, given as `Name <email>`
+        #[clap(long)]
+        author: Option<String>,
+    },
+
+    /// Creates a tag object from the tag read from stdin
+    Mktag,
+
+    /// Adds file contents to the index
+    Add {
+        /// stages changes in the whole worktree, including new and removed files
+        #[clap(short = 'A', long, conflicts_with = "update")]
+        all: bool,
+        /// only stages changes to files already in the index
+        #[clap(short, long)]
+        update: bool,
+        /// also adds ignored files
+        #[clap(short, long)]
+        force: bool,
+        /// only shows what would be added
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+        #[clap(short, long)]
+        verbose: bool,
+
+        pathspec: Vec<String>,
+    },
+
+    /// Removes files from the worktree and the index
+    Rm {
+        /// only removes the files from the index
+        #[clap(long)]
+        cached: bool,
+        /// removes directories recursively
+        #[clap(short)]
+        recursive: bool,
+        /// removes files even if they have changes
+        #[clap(short, long)]
+        force: bool,
+        #[clap(short, long)]
+        quiet: bool,
+        /// only shows what would be removed
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+
+        #[clap(required = true)]
+        pathspec: Vec<String>,
+    },
+
+    /// Registers file contents in the index
+    UpdateIndex {
+        /// adds files not yet in the index
+        #[clap(long)]
+        add: bool,
+        /// removes files missing from the worktree
+        #[clap(long)]
+        remove: bool,
+        /// removes the files from the index even if they still exist
+        #[clap(long)]
+        force_remove: bool,
+        /// refreshes the stat information of unchanged files
+        #[clap(long)]
+        refresh: bool,
+        /// only records the hash, without writing the object
+        #[clap(long)]
+        info_only: bool,
+        /// adds an entry for an object, which need not be in the worktree
+        #[clap(long, value_name = "MODE,OBJECT,PATH")]
+        cacheinfo: Vec<String>,
+        /// sets or clears the executable bit of the files
+        #[clap(long, value_name = "(+|-)x", allow_hyphen_values = true)]
+        chmod: Option<String>,
+        /// writes the index in the given format version
+        #[clap(long)]
+        index_version: Option<u32>,
 
-        tree: Hash,
+        files: Vec<String>,
     },
+
+    /// Updates, deletes or verifies refs, atomically
+    UpdateRef {
+        /// deletes the ref, if it has the old value
+        #[clap(short)]
+        delete: bool,
+        /// updates a symbolic ref itself instead of the ref it points to
+        #[clap(long)]
+        no_deref: bool,
+        /// the reason for the update, added to the reflogs
+        #[clap(short, value_name = "REASON")]
+        message: Option<String>,
+        /// reads updates from stdin, applying either all or none of them
+        #[clap(long, conflicts_with_all = ["delete", "args"])]
+        stdin: bool,
+
+        /// the ref, the new value unless deleting, and the expected old value
+        #[clap(value_name = "REF [NEW] [OLD]")]
+        args: Vec<String>,
+    },
+
+    /// Reads, changes or deletes a symbolic ref
+    SymbolicRef {
+        /// deletes the symbolic ref
+        #[clap(short, long, conflicts_with_all = ["target", "short"])]
+        delete: bool,
+        /// does not complain about refs that are not symbolic
+        #[clap(short, long)]
+        quiet: bool,
+        /// prints the target shortened, like `main` for `refs/heads/main`
+        #[clap(long)]
+        short: bool,
+
+        name: String,
+        /// the ref to point `name` at
+        target: Option<String>,
+    },
+
+    /// Moves loose refs into .git/packed-refs
+    PackRefs {
+        /// packs all refs instead of only tags and refs that are already packed
+        #[clap(long)]
+        all: bool,
+        /// removes the loose refs after packing them, the default
+        #[clap(long, overrides_with = "no_prune")]
+        prune: bool,
+        /// keeps the loose refs
+        #[clap(long, overrides_with = "prune")]
+        no_prune: bool,
+    },
+
+    /// Prints the objects revisions like `HEAD~2` or `main..topic` name
+    RevParse {
+        /// requires exactly one revision, which must name an object
+        #[clap(long)]
+        verify: bool,
+        /// with --verify, fails without a message
+        #[clap(short, long)]
+        quiet: bool,
+        /// abbreviates the hash, implying --verify, to at least this many digits or `core.abbrev`
+        #[clap(long, value_name = "LENGTH", num_args = 0..=1, require_equals = true)]
+        short: Option<Option<usize>>,
+
+        revs: Vec<String>,
+    },
+
+    /// Lists commits reachable from revisions, newest first, `^rev` and `A..B` exclude commits
+    RevList {
+        /// starts from all refs and HEAD
+        #[clap(long)]
+        all: bool,
+        /// only prints how many commits would be shown
+        #[clap(long)]
+        count: bool,
+        /// also lists the trees and blobs of the commits, with their paths
+        #[clap(long)]
+        objects: bool,
+
+        #[clap(required_unless_present = "all")]
+        revs: Vec<String>,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    /// Shows commit logs, newest first, `^rev` and `A..B` exclude commits
+    Log {
+        /// starts from all refs and HEAD
+        #[clap(long)]
+        all: bool,
+        /// shows each commit on one line, short for --pretty=oneline --abbrev-commit
+        #[clap(long)]
+        oneline: bool,
+        /// oneline, short, medium, full, fuller, raw, or format:<format> with placeholders like
+        /// %H, %h, %an, %ad and %s
+        #[clap(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true)]
+        pretty: Option<Option<String>>,
+        /// the same as --pretty
+        #[clap(long, value_name = "FORMAT", conflicts_with = "pretty")]
+        format: Option<String>,
+        /// abbreviates the hashes of commits
+        #[clap(long)]
+        abbrev_commit: bool,
+        /// draws the history as a graph next to the commits
+        #[clap(long)]
+        graph: bool,
+        /// shows the refs pointing to commits, as short or full names
+        #[clap(long, value_name = "STYLE", num_args = 0..=1, require_equals = true)]
+        decorate: Option<Option<String>>,
+        /// shows no refs, even when printing to a terminal
+        #[clap(long, conflicts_with = "decorate")]
+        no_decorate: bool,
+        /// how dates are shown: relative, local, iso, iso-strict, rfc, short, unix, raw or
+        /// format:<strftime format>
+        #[clap(long, value_name = "FORMAT")]
+        date: Option<String>,
+
+        /// revisions to start from, HEAD by default, and paths that are not revisions
+        revs: Vec<String>,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    /// Finds the best common ancestors of commits, to use as the base of a merge
+    #[clap(group(ArgGroup::new("mode").args(&["octopus", "is_ancestor", "independent", "fork_point"])))]
+    MergeBase {
+        /// prints all best common ancestors, not only one
+        #[clap(short, long, conflicts_with_all = ["is_ancestor", "independent", "fork_point"])]
+        all: bool,
+        /// finds the best common ancestors of all commits together, as for an octopus merge
+        #[clap(long)]
+        octopus: bool,
+        /// only exits successfully if the first commit is an ancestor of the second
+        #[clap(long)]
+        is_ancestor: bool,
+        /// prints the commits that are not reachable from any of the others
+        #[clap(long)]
+        independent: bool,
+        /// finds where a commit, HEAD by default, forked from the history of a ref according
+        /// to the ref's reflog
+        #[clap(long)]
+        fork_point: bool,
+
+        commits: Vec<String>,
+    },
+
+    /// Compares the trees of a commit and its parent, or two trees
+    DiffTree {
+        /// compares the contents of changed subtrees instead of the subtrees
+        #[clap(short)]
+        recursive: bool,
+        /// only prints the paths of changes
+        #[clap(long, group = "format")]
+        name_only: bool,
+        /// only prints the paths of changes and how they changed
+        #[clap(long, group = "format")]
+        name_status: bool,
+        /// ends paths and lines with NUL
+        #[clap(short = 'z')]
+        nul: bool,
+        /// also compares commits without parents, with the empty tree
+        #[clap(long)]
+        root: bool,
+        /// reads commits from stdin, optionally followed by the parents to compare them with
+        #[clap(long)]
+        stdin: bool,
+
+        /// a commit, or two trees
+        objects: Vec<String>,
+    },
+
+    /// Lists refs and the objects they point to
+    ShowRef {
+        /// also shows HEAD
+        #[clap(long)]
+        head: bool,
+        /// only shows branches
+        #[clap(long)]
+        heads: bool,
+        /// only shows tags
+        #[clap(long)]
+        tags: bool,
+        /// only prints the object names
+        #[clap(short = 's', long)]
+        hash: bool,
+        /// also prints what annotated tags point to, as `<tag>^{}`
+        #[clap(short, long)]
+        dereference: bool,
+        /// requires the patterns to be exact ref names
+        #[clap(long, conflicts_with_all = ["head", "heads", "tags"])]
+        verify: bool,
+        /// prints nothing, only sets the exit status
+        #[clap(short, long)]
+        quiet: bool,
+
+        /// shows refs whose name ends in one of these components
+        patterns: Vec<String>,
+    },
+
+    /// Creates, lists or deletes tags under refs/tags
+    Tag {
+        /// creates an annotated tag object
+        #[clap(short)]
+        annotate: bool,
+        /// the message of an annotated tag, implies -a
+        #[clap(short, long)]
+        message: Vec<String>,
+        /// reads the message of an annotated tag from a file, implies -a
+        #[clap(short = 'F', long)]
+        file: Option<PathBuf>,
+        /// replaces an existing tag
+        #[clap(short, long)]
+        force: bool,
+        /// deletes the given tags
+        #[clap(short, long, conflicts_with_all = ["list", "annotate", "message", "file"])]
+        delete: bool,
+        /// lists tags matching the pattern
+        #[clap(short, long)]
+        list: bool,
+
+        /// the tag to create, or patterns when listing
+        names: Vec<String>,
+    },
+
+    /// Writes a pack of the objects read from stdin
+    PackObjects {
+        /// writes the pack to stdout instead of to files
+        #[clap(long)]
+        stdout: bool,
+        /// reads revisions and packs the objects reachable from them
+        #[clap(long)]
+        revs: bool,
+        /// packs the objects reachable from all refs, with --revs
+        #[clap(long, requires = "revs")]
+        all: bool,
+        /// how many objects are tried as the base of a delta, `pack.window` or 10
+        #[clap(long)]
+        window: Option<usize>,
+        /// the longest chain of deltas, `pack.depth` or 50
+        #[clap(long)]
+        depth: Option<usize>,
+        /// refers to the bases of deltas by offset, which is more compact
+        #[clap(long)]
+        delta_base_offset: bool,
+        /// shows no progress, which is never shown anyway
+        #[clap(short, long)]
+        quiet: bool,
+
+        /// the pack is written to `<base-name>-<checksum>.pack`, with its index next to it
+        #[clap(required_unless_present = "stdout", conflicts_with = "stdout")]
+        base_name: Option<PathBuf>,
+    },
+
+    /// Builds the index of a pack, checking it on the way
+    IndexPack {
+        /// writes the index to this file instead of next to the pack
+        #[clap(short)]
+        output: Option<PathBuf>,
+        /// reads the pack from stdin and stores it in the repository, or at the given path
+        #[clap(long)]
+        stdin: bool,
+        /// appends the bases of deltas that the pack lacks from the repository, with --stdin
+        #[clap(long, requires = "stdin")]
+        fix_thin: bool,
+
+        /// the pack, which has to end in `.pack`
+        #[clap(required_unless_present = "stdin")]
+        pack: Option<PathBuf>,
+    },
+
+    /// Writes the objects of the pack read from stdin as loose objects
+    UnpackObjects {
+        /// checks the pack without writing anything
+        #[clap(short = 'n')]
+        dry_run: bool,
+        /// shows no progress, which is never shown anyway
+        #[clap(short, long)]
+        quiet: bool,
+    },
+
+    /// Reads and writes config files
+    #[clap(group(ArgGroup::new("action").args(&["get", "get_all", "unset", "unset_all", "list"])))]
+    #[clap(group(ArgGroup::new("scope").args(&["system", "global", "local", "worktree", "file"])))]
+    Config {
+        /// prints the last value of the key, exits with 1 if there is none
+        #[clap(long)]
+        get: bool,
+        /// prints all values of a multivar
+        #[clap(long)]
+        get_all: bool,
+        /// sets the key, which must have at most one value
+        #[clap(long)]
+        set: bool,
+        /// removes the key, which must have exactly one value
+        #[clap(long)]
+        unset: bool,
+        /// removes all values of the key
+        #[clap(long)]
+        unset_all: bool,
+        /// lists all variables with their values
+        #[clap(short, long)]
+        list: bool,
+        /// uses /etc/gitconfig, or GIT_CONFIG_SYSTEM
+        #[clap(long)]
+        system: bool,
+        /// uses ~/.gitconfig, or GIT_CONFIG_GLOBAL
+        #[clap(long)]
+        global: bool,
+        /// uses .git/config
+        #[clap(long)]
+        local: bool,
+        /// uses .git/config.worktree
+        #[clap(long)]
+        worktree: bool,
+        /// uses the given file
+        #[clap(short, long)]
+        file: Option<PathBuf>,
+        /// prefixes values with the file they are from
+        #[clap(long)]
+        show_origin: bool,
+        /// checks and canonicalizes values as this type
+        #[clap(long = "type", value_enum)]
+        typ: Option<config::Type>,
+
+        /// the key, like `section.name` or `section.subsection.name`
+        name: Option<String>,
+        /// the value to set
+        value: Option<String>,
+    },
+}
+
+/// the options of `rev-list` and `log` that choose and order the commits of a walk
+#[derive(Debug, clap::Args)]
+#[clap(group(ArgGroup::new("order").args(&["topo_order", "date_order"])))]
+struct WalkArgs {
+    /// shows no parents before all of their children, keeping lines of history together
+    #[clap(long)]
+    topo_order: bool,
+    /// shows no parents before all of their children, otherwise by date
+    #[clap(long)]
+    date_order: bool,
+    /// shows the oldest commits first
+    #[clap(long)]
+    reverse: bool,
+    /// shows at most this many commits
+    #[clap(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+    /// shows only commits more recent than the date
+    #[clap(long, visible_alias = "after", value_name = "DATE")]
+    since: Option<String>,
+    /// shows only commits older than the date
+    #[clap(long, visible_alias = "before", value_name = "DATE")]
+    until: Option<String>,
+    /// only follows the first parent of merges
+    #[clap(long)]
+    first_parent: bool,
+    /// only shows merges
+    #[clap(long, conflicts_with = "no_merges")]
+    merges: bool,
+    /// shows no merges
+    #[clap(long)]
+    no_merges: bool,
+    /// only shows commits that descend from the excluded ones
+    #[clap(long)]
+    ancestry_path: bool,
+
+    /// only shows commits that change these paths
+    #[clap(last = true)]
+    paths: Vec<String>,
+}
+
+impl WalkArgs {
+    fn into_options(self, repo: &Repository) -> anyhow::Result<walk::WalkOptions> {
+        let order = match (self.topo_order, self.date_order) {
+            (true, _) => walk::Order::Topo,
+            (_, true) => walk::Order::Date,
+            _ => walk::Order::Default,
+        };
+        let date = |date: Option<String>| date.as_deref().map(ident::parse_approxidate).transpose();
+        let paths = self
+            .paths
+            .iter()
+            .map(|path| repo.prefixed(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(walk::WalkOptions {
+            order,
+            max_count: self.max_count,
+            reverse: self.reverse,
+            since: date(self.since)?,
+            until: date(self.until)?,
+            first_parent: self.first_parent,
+            merges: match (self.merges, self.no_merges) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            ancestry_path: self.ancestry_path,
+            paths: Pathspec::new(&paths),
+        })
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
-enum BlobType {
+pub enum BlobType {
     #[default]
     Blob,
     Commit,
@@ -153,108 +761,503 @@ enum BlobType {
     Tag,
 }
 
-pub fn init() -> anyhow::Result<()> {
-    let default_branch = "main";
-    std::fs::create_dir(".git")?;
-    std::fs::create_dir(".git/objects")?;
-    std::fs::create_dir(".git/refs")?;
-    let mut f = std::fs::File::create(".git/HEAD")?;
-    writeln!(f, "ref: refs/heads/{default_branch}")?;
-    Ok(())
+impl std::fmt::Display for BlobType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BlobType::Blob => "blob",
+            BlobType::Commit => "commit",
+            BlobType::Tree => "tree",
+            BlobType::Tag => "tag",
+        };
+        write!(f, "{name}")
+    }
 }
 
-pub struct CatFile {
+/// validates a tag object like `git mktag` and writes it to the object store
+pub fn mktag(repo: &Repository, input: &[u8]) -> anyhow::Result<Hash> {
+    let mut raw = format!("tag {}\0", input.len()).into_bytes();
+    raw.extend_from_slice(input);
+    let tag =
+        Tag::try_from(raw.as_slice()).context("tag on stdin did not pass our strict fsck check")?;
+    let mut written = vec![];
+    <Tag as Writeable>::fmt(&tag, &mut written)?;
+    if written != raw {
+        bail!("tag on stdin did not pass our strict fsck check");
+    }
+    if tag.tagger().is_none() {
+        bail!("tag on stdin did not pass our strict fsck check: missing tagger entry");
+    }
+
+    let tagged = CatFile::new(repo, &tag.object().to_string())?
+        .object()
+        .context("could not read tagged object")?;
+    if tagged.typ() != tag.typ() {
+        bail!(
+            "object '{}' tagged as '{}', but is a '{}' type",
+            tag.object(),
+            tag.typ(),
+            tagged.typ()
+        );
+    }
+
+    let cmd = HashObject::new(Object::Tag(tag));
+    cmd.write(repo)?;
+    Ok(cmd.hash())
+}
+
+pub struct CatFile<'a> {
+    repo: &'a Repository,
     hash: Hash,
 }
 
-impl CatFile {
-    pub fn new(hash: &str) -> anyhow::Result<Self> {
-        let hash: Hash = hash.parse().context("failed to parse hash")?;
-        Ok(Self { hash })
+impl<'a> CatFile<'a> {
+    /// the object named by a revision like `HEAD:src/main.rs`
+    pub fn new(repo: &'a Repository, rev: &str) -> anyhow::Result<Self> {
+        let hash = revision::resolve(repo, rev)?;
+        Ok(Self { repo, hash })
     }
 
-    fn path(&self) -> PathBuf {
-        root().push_dir("objects").push_dir(self.hash.object_path())
+    pub fn exists(&self) -> anyhow::Result<bool> {
+        self.repo.has_object(&self.hash)
     }
 
-    pub fn exists(&self) -> anyhow::Result<bool> {
-        let metadata = std::fs::metadata(self.path());
-        match metadata {
-            Ok(m) => {
-                if m.is_file() {
-                    Ok(true)
-                } else {
-                    bail!("path exists but isn't a file");
-                }
+    pub fn object(&self) -> anyhow::Result<Object> {
+        self.repo.read_object(&self.hash)
+    }
+
+    /// reads only the type and size of the object
+    pub fn header(&self) -> anyhow::Result<(BlobType, usize)> {
+        self.repo.object_header(&self.hash)
+    }
+
+    /// prints the raw content of the object, dereferencing it until it has type `typ`
+    pub fn typed(&self, typ: BlobType) -> anyhow::Result<()> {
+        let mut object = self.object()?;
+        while object.typ() != typ {
+            let next = match (&object, typ) {
+                (Object::Tag(tag), _) => tag.object().clone(),
+                (Object::Commit(commit), BlobType::Tree) => commit.tree().clone(),
+                _ => bail!("{}: bad file", self.hash),
+            };
+            object = self.repo.read_object(&next)?;
+        }
+        stdout().lock().write_all(&object.body())?;
+
+        Ok(())
+    }
+
+    pub fn pretty(&self) -> anyhow::Result<()> {
+        let object = self.object()?;
+        let mut out = stdout().lock();
+
+        match &object {
+            Object::Blob(blob) => out.write_all(blob.content())?,
+            Object::Tree(tree) => write!(out, "{}", tree.display())?,
+            Object::Commit(_) | Object::Tag(_) => out.write_all(&object.body())?,
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    /// `--batch`
+    Contents,
+    /// `--batch-check`
+    Info,
+    /// `--batch-command`
+    Command,
+}
+
+/// the streaming protocol of `cat-file --batch` and friends
+pub struct Batch<'a> {
+    repo: &'a Repository,
+    mode: BatchMode,
+    format: String,
+    buffer: bool,
+}
+
+impl<'a> Batch<'a> {
+    pub const DEFAULT_FORMAT: &'static str = "%(objectname) %(objecttype) %(objectsize)";
+
+    pub fn new(repo: &'a Repository, mode: BatchMode, format: String, buffer: bool) -> Self {
+        Self {
+            repo,
+            mode,
+            format,
+            buffer,
+        }
+    }
+
+    /// processes the object names, or commands for `--batch-command`, read from `input`
+    pub fn run<R: BufRead, W: Write>(&self, input: R, out: &mut W) -> anyhow::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            match self.mode {
+                BatchMode::Contents => self.object(out, &line, true)?,
+                BatchMode::Info => self.object(out, &line, false)?,
+                BatchMode::Command => match line.split_once(' ') {
+                    Some(("contents", object)) => self.object(out, object, true)?,
+                    Some(("info", object)) => self.object(out, object, false)?,
+                    None if line == "flush" => {
+                        if !self.buffer {
+                            bail!("flush is only for --buffer mode");
+                        }
+                        out.flush()?;
+                    }
+                    _ => bail!("unknown command: '{line}'"),
+                },
             }
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::NotFound {
-                    Ok(false)
-                } else {
-                    Err(e)?
-                }
+            if !self.buffer {
+                out.flush()?;
             }
         }
+        Ok(())
     }
 
-    pub fn pretty(&self) -> anyhow::Result<()> {
-        let path = self.path();
+    /// processes every object in the repository, in order of their hashes
+    pub fn all_objects<W: Write>(&self, out: &mut W) -> anyhow::Result<()> {
+        let contents = self.mode == BatchMode::Contents;
+        for hash in self.repo.all_objects()? {
+            self.object(out, &hash.to_string(), contents)?;
+        }
+        Ok(())
+    }
 
-        let mut f = File::open(path)?;
+    fn object<W: Write>(&self, out: &mut W, line: &str, contents: bool) -> anyhow::Result<()> {
+        let (name, rest) = if self.format.contains("%(rest)") {
+            match line.split_once([' ', '\t']) {
+                Some((name, rest)) => (name, rest.trim_start_matches([' ', '\t'])),
+                None => (line, ""),
+            }
+        } else {
+            (line, "")
+        };
 
-        // TODO: object, not blob
-        let blob: Blob = f.zlib_read()?;
+        let cat_file = match CatFile::new(self.repo, name) {
+            Ok(cat_file) if cat_file.exists()? => cat_file,
+            Err(err) if matches!(err.downcast_ref(), Some(RevisionError::Ambiguous(_))) => {
+                writeln!(out, "{name} ambiguous")?;
+                return Ok(());
+            }
+            _ => {
+                writeln!(out, "{name} missing")?;
+                return Ok(());
+            }
+        };
+        let (typ, size) = cat_file.header()?;
 
-        stdout().lock().write_all(blob.content())?;
+        let mut format = self.format.as_str();
+        while let Some(start) = format.find("%(") {
+            write!(out, "{}", &format[..start])?;
+            let Some(end) = format[start..].find(')') else {
+                bail!("format element '{}' does not end in ')'", &format[start..]);
+            };
+            match &format[start + 2..start + end] {
+                "objectname" => write!(out, "{}", cat_file.hash)?,
+                "objecttype" => write!(out, "{typ}")?,
+                "objectsize" => write!(out, "{size}")?,
+                "objectsize:disk" => {
+                    write!(out, "{}", self.repo.object_disk_size(&cat_file.hash)?)?
+                }
+                "deltabase" => match self.repo.delta_base(&cat_file.hash)? {
+                    Some(base) => write!(out, "{base}")?,
+                    None => write!(out, "{}", "0".repeat(40))?,
+                },
+                "rest" => write!(out, "{rest}")?,
+                atom => bail!("unknown format element: %({atom})"),
+            }
+            format = &format[start + end + 1..];
+        }
+        writeln!(out, "{format}")?;
 
+        if contents {
+            out.write_all(&cat_file.object()?.body())?;
+            writeln!(out)?;
+        }
         Ok(())
     }
 }
 
 pub struct HashObject {
-    object: Object,
+    /// the serialized object, including the header
+    raw: Vec<u8>,
 }
 
 impl HashObject {
     pub fn new(object: Object) -> Self {
-        Self { object }
+        let mut raw = Vec::new();
+        <Object as Writeable>::fmt(&object, &mut raw).expect("writing to a vec does not fail");
+        Self { raw }
+    }
+
+    /// hashes `content` as an object of type `typ`, checking that it is well-formed
+    pub fn typed(typ: BlobType, content: Vec<u8>) -> anyhow::Result<Self> {
+        if typ == BlobType::Blob {
+            return Ok(Self::new(Object::new_blob(content.as_slice())?));
+        }
+        let cmd = Self::literally(&typ.to_string(), &content);
+        Object::try_from(cmd.raw.as_slice()).with_context(|| format!("corrupt {typ}"))?;
+        Ok(cmd)
+    }
+
+    /// hashes `content` as an object of any type, without checking it
+    pub fn literally(typ: &str, content: &[u8]) -> Self {
+        let mut raw = format!("{typ} {}\0", content.len()).into_bytes();
+        raw.extend_from_slice(content);
+        Self { raw }
     }
 
-    pub fn write(&self) -> anyhow::Result<()> {
+    pub fn write(&self, repo: &Repository) -> anyhow::Result<()> {
         // hash will be computed twice
         // question: do i care?
         let hash = self.hash();
 
-        create_dir(root().push_dir("objects").push_dir(hash.dir()))
-            .ignore(std::io::ErrorKind::AlreadyExists, ())?;
-        let path = root().push_dir("objects").push_dir(hash.object_path());
+        let path = repo.object_file(&hash)?;
         let mut file = File::create(path).context("failed to create object file")?;
 
-        let obj = ZlibWriter::new(&self.object);
+        let obj = ZlibWriter::new(&self.raw);
         obj.fmt(&mut file)?;
 
         Ok(())
     }
 
     pub fn hash(&self) -> Hash {
-        let s = self.object.to_string();
-        Hash::from_bytes(s.as_bytes())
+        Hash::from_bytes(&self.raw)
+    }
+}
+
+/// the repository a command needs, failing if there is none
+fn require(repo: Option<&Repository>) -> anyhow::Result<&Repository> {
+    repo.context("not a git repository (or any of the parent directories): .git")
+}
+
+/// a path given on the command line, relative to where the command was started
+fn user_file(repo: Option<&Repository>, path: &Path) -> PathBuf {
+    match repo {
+        Some(repo) => repo.file(path),
+        None => path.to_owned(),
     }
 }
 
 fn main() -> anyhow::Result<ExitCode> {
     let cli = Cli::parse();
+    for dir in &cli.dir {
+        std::env::set_current_dir(dir)
+            .with_context(|| format!("cannot change to '{}'", dir.display()))?;
+    }
+    let repo = match cli.subcommand {
+        Command::Init { .. } => None,
+        _ => Repository::discover(cli.git_dir.as_deref())?,
+    };
+    let repo = repo.as_ref();
+
+    // init makes a repository, hash-object, config and index-pack also work outside of one
     match cli.subcommand {
-        Command::Init => init()?,
+        Command::Init {
+            bare,
+            initial_branch,
+            template,
+            shared,
+            object_format,
+            quiet,
+            directory,
+        } => {
+            let cmd = init::Init {
+                bare,
+                initial_branch,
+                template: template.map(PathBuf::from),
+                shared: match shared {
+                    Some(shared) => shared.parse()?,
+                    None => init::Shared::Umask,
+                },
+                object_format,
+                quiet,
+            };
+            cmd.run(directory.as_deref())?;
+        }
+        Command::HashObject {
+            write,
+            typ,
+            stdin,
+            stdin_paths,
+            literally,
+            file,
+        } => {
+            let blob_type = BlobType::from_str(&typ, false);
+            if blob_type.is_err() && !literally {
+                bail!("invalid object type \"{typ}\"");
+            }
+            let store = match write {
+                true => Some(require(repo)?),
+                false => None,
+            };
+            let hash_object = |mut source: Box<dyn BufRead>| -> anyhow::Result<()> {
+                let mut content = Vec::new();
+                source.read_to_end(&mut content)?;
+                let cmd = match blob_type {
+                    Ok(typ) if !literally => HashObject::typed(typ, content)?,
+                    _ => HashObject::literally(&typ, &content),
+                };
+
+                if let Some(repo) = store {
+                    cmd.write(repo)?;
+                }
+
+                println!("{}", cmd.hash());
+                Ok(())
+            };
+
+            if stdin_paths {
+                for path in io::stdin().lock().lines() {
+                    let file = File::open(user_file(repo, Path::new(&path?)))?;
+                    hash_object(Box::new(BufReader::new(file)))?;
+                }
+            } else if stdin {
+                hash_object(Box::new(BufReader::new(io::stdin().lock())))?;
+            } else {
+                let file = file.expect("guaranteed to not be none");
+                let file = File::open(user_file(repo, Path::new(&file)))?;
+                hash_object(Box::new(BufReader::new(file)))?;
+            }
+        }
+        Command::Config {
+            get,
+            get_all,
+            set,
+            unset,
+            unset_all,
+            list,
+            system,
+            global,
+            local,
+            worktree,
+            file,
+            show_origin,
+            typ,
+            name,
+            value,
+        } => {
+            let action = match (get, get_all, unset, unset_all, list) {
+                (true, ..) => config::Action::Get,
+                (_, true, ..) => config::Action::GetAll,
+                (_, _, true, ..) => config::Action::Unset,
+                (_, _, _, true, _) => config::Action::UnsetAll,
+                (.., true) => config::Action::List,
+                _ if set || value.is_some() => config::Action::Set,
+                _ => config::Action::Get,
+            };
+            let scope = [
+                (system, config::Scope::System),
+                (global, config::Scope::Global),
+                (local, config::Scope::Local),
+                (worktree, config::Scope::Worktree),
+            ]
+            .into_iter()
+            .find_map(|(given, scope)| given.then_some(scope));
+            let cmd = config::ConfigCommand {
+                action,
+                scope,
+                file: file.map(|file| user_file(repo, &file)),
+                show_origin,
+                typ,
+                git_dir: repo.map(|repo| repo.git_dir().to_owned()),
+            };
+            return cmd.run(name.as_deref(), value.as_deref());
+        }
+        Command::IndexPack {
+            output,
+            stdin,
+            fix_thin,
+            pack,
+        } => {
+            let cmd = pack::IndexPack {
+                output: output.map(|output| user_file(repo, &output)),
+                fix_thin,
+            };
+            let pack = pack.map(|pack| user_file(repo, &pack));
+            match stdin {
+                true => cmd.run_stdin(require(repo)?, io::stdin().lock(), pack.as_deref())?,
+                false => cmd.run(&pack.expect("required without --stdin"))?,
+            }
+        }
+        command => return run(require(repo)?, command),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// runs a command that only works inside of a repository
+fn run(repo: &Repository, command: Command) -> anyhow::Result<ExitCode> {
+    let needs_work_tree = matches!(
+        command,
+        Command::Add { .. }
+            | Command::Rm { .. }
+            | Command::Commit { .. }
+            | Command::WriteTree {
+                from_worktree: true,
+                ..
+            }
+    );
+    if needs_work_tree && repo.is_bare() {
+        bail!("this operation must be run in a work tree");
+    }
+
+    match command {
         Command::CatFile {
             pretty,
             exists,
+            typ,
+            size,
+            batch,
+            batch_check,
+            batch_command,
+            batch_all_objects,
+            buffer,
+            type_or_object,
             object,
         } => {
-            let cat_file = CatFile::new(&object)?;
+            let batch = match (batch, batch_check, batch_command) {
+                (Some(format), _, _) => Some(Batch::new(repo, BatchMode::Contents, format, buffer)),
+                (_, Some(format), _) => Some(Batch::new(repo, BatchMode::Info, format, buffer)),
+                (_, _, Some(format)) => Some(Batch::new(repo, BatchMode::Command, format, buffer)),
+                _ => None,
+            };
+            if let Some(batch) = batch {
+                let mut out = io::BufWriter::new(stdout().lock());
+                if batch_all_objects {
+                    batch.all_objects(&mut out)?;
+                } else {
+                    batch.run(io::stdin().lock(), &mut out)?;
+                }
+                out.flush()?;
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let Some(type_or_object) = type_or_object else {
+                bail!("an object is required");
+            };
+            if let Some(object) = object {
+                let Ok(typ) = BlobType::from_str(&type_or_object, false) else {
+                    bail!("invalid object type \"{type_or_object}\"");
+                };
+                CatFile::new(repo, &object)?.typed(typ)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !(pretty || exists || typ || size) {
+                bail!("one of -p, -e, -t, -s or a type is required");
+            }
+
+            let cat_file = CatFile::new(repo, &type_or_object)?;
             if pretty {
                 cat_file.pretty()?;
             }
+            if typ {
+                println!("{}", cat_file.header()?.0);
+            }
+            if size {
+                println!("{}", cat_file.header()?.1);
+            }
             if exists {
                 if cat_file.exists()? {
                     return Ok(ExitCode::SUCCESS);
@@ -263,44 +1266,25 @@ fn main() -> anyhow::Result<ExitCode> {
                 }
             }
         }
-        Command::HashObject {
-            write,
-            typ: _,
-            stdin,
-            file,
-        } => {
-            let source: Box<dyn BufRead> = if stdin {
-                Box::new(BufReader::new(io::stdin().lock()))
-            } else {
-                let file = file.expect("guaranteed to not be none");
-                let file = File::open(file)?;
-                Box::new(BufReader::new(file))
-            };
-
-            let cmd = HashObject::new(Object::new_blob(source)?);
-
-            if write {
-                cmd.write()?;
-            }
-
-            println!("{}", cmd.hash());
-        }
-
         Command::LsTree {
             name_only,
             object_only,
             tree_hash,
             recursive,
         } => {
-            let hash: Hash = tree_hash.parse()?;
-            let path = root().push_dir("objects").push_dir(hash.object_path());
-
-            let mut f = File::open(path)?;
-            let tree: Tree = f.zlib_read()?;
+            let hash = match revision::resolve_typed(repo, &tree_hash, BlobType::Tree) {
+                Err(err) if matches!(err.downcast_ref(), Some(RevisionError::WrongType(..))) => {
+                    bail!("not a tree object")
+                }
+                hash => hash?,
+            };
+            let Object::Tree(tree) = repo.read_object(&hash)? else {
+                bail!("not a tree object");
+            };
 
             let mut printer = tree.display();
             if recursive {
-                printer.recusive();
+                printer.recusive(repo);
             }
             if name_only {
                 printer.no_type();
@@ -316,7 +1300,21 @@ fn main() -> anyhow::Result<ExitCode> {
             print!("{}", printer);
         }
 
-        Command::WriteTree {} => {
+        Command::WriteTree {
+            prefix,
+            missing_ok,
+            from_worktree: false,
+        } => {
+            let index = Index::load(repo)?;
+            let prefix = prefix.unwrap_or_default();
+            let tree = Tree::write_index(repo, &index, prefix.as_bytes(), missing_ok)?;
+            println!("{tree}");
+        }
+
+        Command::WriteTree {
+            from_worktree: true,
+            ..
+        } => {
             let (ok, err): (Vec<_>, Vec<_>) = WalkDir::new(".")
                 .into_iter()
                 .filter_entry(|e| e.file_name() != ".git")
@@ -324,31 +1322,519 @@ fn main() -> anyhow::Result<ExitCode> {
             for e in err {
                 eprintln!("Error: {e}");
             }
-            let tree = Tree::write_tree(ok.into_iter())?;
+            let tree = Tree::write_tree(repo, ok.into_iter())?;
             println!("{}", tree);
         }
 
         Command::CommitTree {
             parent,
             message,
+            author,
             tree,
         } => {
-            let author = Event::new(
-                "hello world".to_owned(),
-                "hello.world@example.com".to_owned(),
-            );
-            let committer = Event::new(
-                "hello world".to_owned(),
-                "hello.world@example.com".to_owned(),
-            );
-            let message = message.join(" ");
-            let commit = Commit::new(tree, &message, author, committer, parent)?;
-            dbg!(&commit);
+            let tree = revision::resolve_typed(repo, &tree, BlobType::Tree)?;
+            let parent = parent
+                .iter()
+                .map(|parent| revision::resolve_typed(repo, parent, BlobType::Commit))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let author = ident::event(repo, Role::Author, author.as_deref())?;
+            let committer = ident::event(repo, Role::Committer, None)?;
+            // each -m is a paragraph, without any the message is read from stdin
+            let message = match message.is_empty() {
+                true => io::read_to_string(io::stdin().lock())?,
+                false => message
+                    .iter()
+                    .map(|m| match m.ends_with('\n') {
+                        true => m.clone(),
+                        false => format!("{m}\n"),
+                    })
+                    .join("\n"),
+            };
+            let commit = Commit::new(repo, tree, &message, author, committer, parent)?;
             let id = Hash::from_writable(&commit);
-            let mut file = File::create(Object::path(&id)?)?;
+            let mut file = File::create(repo.object_file(&id)?)?;
             ZlibWriter::new(commit).fmt(&mut file)?;
-                println!("{id}");
+            println!("{id}");
+        }
+
+        Command::Commit {
+            message,
+            file,
+            amend,
+            allow_empty,
+            author,
+        } => commit::commit(
+            repo,
+            &message,
+            file.map(|file| match file.as_os_str() == "-" {
+                true => file,
+                false => repo.file(&file),
+            })
+            .as_deref(),
+            amend,
+            allow_empty,
+            author.as_deref(),
+        )?,
+
+        Command::Add {
+            all,
+            update,
+            force,
+            dry_run,
+            verbose,
+            pathspec,
+        } => {
+            let pathspec: Vec<_> = pathspec.iter().map(|p| repo.prefixed(p)).try_collect()?;
+            staging::add(repo, &pathspec, all, update, force, dry_run, verbose)?
         }
+
+        Command::Rm {
+            cached,
+            recursive,
+            force,
+            quiet,
+            dry_run,
+            pathspec,
+        } => {
+            let pathspec: Vec<_> = pathspec.iter().map(|p| repo.prefixed(p)).try_collect()?;
+            staging::rm(repo, &pathspec, cached, recursive, force, quiet, dry_run)?
+        }
+
+        Command::UpdateIndex {
+            add,
+            remove,
+            force_remove,
+            refresh,
+            info_only,
+            cacheinfo,
+            chmod,
+            index_version,
+            files,
+        } => {
+            let cmd = staging::UpdateIndex {
+                add,
+                remove,
+                force_remove,
+                refresh,
+                info_only,
+                cacheinfo,
+                chmod,
+                index_version,
+            };
+            let files: Vec<_> = files.iter().map(|f| repo.prefixed(f)).try_collect()?;
+            return cmd.run(repo, &files);
+        }
+
+        Command::Mktag => {
+            let mut input = vec![];
+            io::stdin().lock().read_to_end(&mut input)?;
+            println!("{}", mktag(repo, &input)?);
+        }
+
+        Command::Tag {
+            annotate,
+            message,
+            file,
+            force,
+            delete,
+            list,
+            names,
+        } => {
+            if delete {
+                for name in names {
+                    let refname = format!("refs/tags/{name}");
+                    let Some(refs::RefValue::Direct(old)) = refs::read(repo, &refname)? else {
+                        bail!("tag '{name}' not found.");
+                    };
+                    let mut transaction = Transaction::default();
+                    transaction.delete(&refname, Expect::Value(old.clone()));
+                    transaction.commit(repo)?;
+                    println!("Deleted tag '{name}' (was {})", &old.to_string()[..7]);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if list || names.is_empty() {
+                for (tag, _) in refs::list(repo, "refs/tags/")? {
+                    let tag = &tag["refs/tags/".len()..];
+                    if names.is_empty()
+                        || names
+                            .iter()
+                            .any(|p| wildmatch(p.as_bytes(), tag.as_bytes(), false))
+                    {
+                        println!("{tag}");
+                    }
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let [name, rest @ ..] = names.as_slice() else {
+                unreachable!("names is not empty");
+            };
+            let object = match rest {
+                [] => refs::head(repo)?,
+                [object] => refs::resolve_object(repo, object)?
+                    .with_context(|| format!("Failed to resolve '{object}' as a valid ref."))?,
+                _ => bail!("too many arguments"),
+            };
+            let refname = format!("refs/tags/{name}");
+            if !refs::valid_ref_name(&refname) {
+                bail!("'{name}' is not a valid tag name.");
+            }
+            let old = match force {
+                true => Expect::Any,
+                false => Expect::Missing,
+            };
+            if old == Expect::Missing && refs::read(repo, &refname)?.is_some() {
+                bail!("tag '{name}' already exists");
+            }
+
+            let message = match file {
+                Some(file) => Some(std::fs::read_to_string(repo.file(&file))?),
+                None if !message.is_empty() => Some(message.join("\n\n")),
+                None => None,
+            };
+            let target = if annotate || message.is_some() {
+                let Some(mut message) = message else {
+                    bail!("no tag message given, use -m or -F");
+                };
+                if !message.ends_with('\n') {
+                    message.push('\n');
+                }
+                let typ = CatFile::new(repo, &object.to_string())?.object()?.typ();
+                let tagger = ident::event(repo, Role::Committer, None)?;
+                let tag = Tag::new(object, typ, name.to_owned(), tagger, &message);
+                let cmd = HashObject::new(Object::Tag(tag));
+                cmd.write(repo)?;
+                cmd.hash()
+            } else {
+                CatFile::new(repo, &object.to_string())?.object()?;
+                object
+            };
+
+            let mut transaction = Transaction::default();
+            transaction.update(&refname, Some(target), old);
+            transaction.commit(repo)?;
+        }
+
+        Command::UpdateRef {
+            delete,
+            no_deref,
+            message,
+            stdin,
+            args,
+        } => {
+            let mut transaction = Transaction::default();
+            transaction.no_deref = no_deref;
+            transaction.message = message.unwrap_or_default();
+            if stdin {
+                transaction.parse(repo, io::stdin().lock())?;
+            } else {
+                let old = |old: Option<&String>| match old {
+                    Some(old) => Expect::parse(repo, old),
+                    None => Ok(Expect::Any),
+                };
+                match (delete, args.as_slice()) {
+                    (true, [name, rest @ ..]) if rest.len() <= 1 => {
+                        transaction.delete(name, old(rest.first())?)
+                    }
+                    (false, [name, new, rest @ ..]) if rest.len() <= 1 => {
+                        transaction.update(name, refs::new_value(repo, new)?, old(rest.first())?)
+                    }
+                    _ => bail!("usage: git update-ref [<options>] (-d <refname> [<old-val>] | <refname> <new-val> [<old-val>] | --stdin)"),
+                }
+            }
+            transaction.commit(repo)?;
+        }
+
+        Command::SymbolicRef {
+            delete,
+            quiet,
+            short,
+            name,
+            target,
+        } => {
+            if delete {
+                refs::delete_symbolic(repo, &name)?;
+            } else if let Some(target) = target {
+                if !target.starts_with("refs/") {
+                    bail!("Refusing to point {name} outside of refs/");
+                }
+                if !refs::valid_ref_name(&target) {
+                    bail!("Refusing to set '{name}' to invalid ref '{target}'");
+                }
+                refs::set_symbolic(repo, &name, &target)?;
+            } else {
+                let Some(refs::RefValue::Symbolic(target)) = refs::read(repo, &name)? else {
+                    if !quiet {
+                        eprintln!("fatal: ref {name} is not a symbolic ref");
+                    }
+                    return Ok(ExitCode::FAILURE);
+                };
+                match short {
+                    true => println!("{}", refs::shorten(&target)),
+                    false => println!("{target}"),
+                }
+            }
+        }
+
+        Command::PackRefs {
+            all,
+            prune: _,
+            no_prune,
+        } => refs::pack(repo, all, !no_prune)?,
+
+        Command::ShowRef {
+            head,
+            heads,
+            tags,
+            hash,
+            dereference,
+            verify,
+            quiet,
+            patterns,
+        } => {
+            let mut found = vec![];
+            if verify {
+                for name in &patterns {
+                    let resolved = match name == "HEAD" || name.starts_with("refs/") {
+                        true => refs::resolve(repo, name)?,
+                        false => None,
+                    };
+                    match resolved {
+                        Some(value) => found.push((name.clone(), value)),
+                        None if quiet => return Ok(ExitCode::FAILURE),
+                        None => bail!("'{name}' - not a valid ref"),
+                    }
+                }
+            } else {
+                if head {
+                    found.extend(refs::resolve(repo, "HEAD")?.map(|h| ("HEAD".to_owned(), h)));
+                }
+                for (name, value) in refs::list(repo, "refs/")? {
+                    let kind_ok = match (heads, tags) {
+                        (false, false) => true,
+                        _ => {
+                            (heads && name.starts_with("refs/heads/"))
+                                || (tags && name.starts_with("refs/tags/"))
+                        }
+                    };
+                    let matched = patterns.is_empty()
+                        || patterns
+                            .iter()
+                            .any(|p| name == *p || name.ends_with(&format!("/{p}")));
+                    if kind_ok && matched {
+                        found.push((name, value));
+                    }
+                }
+            }
+
+            if found.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+            if quiet {
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut out = stdout().lock();
+            for (name, value) in found {
+                match hash {
+                    true => writeln!(out, "{value}")?,
+                    false => writeln!(out, "{value} {name}")?,
+                }
+                if dereference && name.starts_with("refs/tags/") {
+                    let peeled = refs::peel(repo, &value)?;
+                    if peeled != value {
+                        match hash {
+                            true => writeln!(out, "{peeled}")?,
+                            false => writeln!(out, "{peeled} {name}^{{}}")?,
+                        }
+                    }
+                }
+            }
+        }
+        Command::RevParse {
+            verify,
+            quiet,
+            short,
+            revs,
+        } => {
+            let short = match short {
+                Some(len) => Some(len.map_or_else(|| revision::abbrev_len(repo), Ok)?),
+                None => None,
+            };
+            let cmd = revision::RevParse {
+                // like git, abbreviating only works on a single revision
+                verify: verify || short.is_some(),
+                quiet,
+                short,
+            };
+            return cmd.run(repo, &revs);
+        }
+        Command::RevList {
+            all,
+            count,
+            objects,
+            revs,
+            walk,
+        } => {
+            let cmd = walk::RevList {
+                options: walk.into_options(repo)?,
+                objects,
+                count,
+                all,
+            };
+            cmd.run(repo, &revs)?;
+        }
+        Command::Log {
+            all,
+            oneline,
+            pretty,
+            format,
+            abbrev_commit,
+            graph,
+            decorate,
+            no_decorate,
+            date,
+            revs,
+            mut walk,
+        } => {
+            let pretty = match format.or(pretty.map(|pretty| pretty.unwrap_or_default())) {
+                Some(pretty) if !pretty.is_empty() => pretty.parse()?,
+                Some(_) => log::Pretty::Medium,
+                None if oneline => log::Pretty::Oneline,
+                None => log::Pretty::Medium,
+            };
+            let decorate = match decorate.as_ref().map(|style| style.as_deref()) {
+                _ if no_decorate => log::Decorate::No,
+                Some(None | Some("short")) => log::Decorate::Short,
+                Some(Some("full")) => log::Decorate::Full,
+                Some(Some("no")) => log::Decorate::No,
+                Some(Some("auto")) | None => match io::stdout().is_terminal() {
+                    true => log::Decorate::Short,
+                    false => log::Decorate::No,
+                },
+                Some(Some(style)) => bail!("invalid --decorate option: {style}"),
+            };
+            // like git, the first argument that is not a revision but exists starts the paths
+            let mut revisions = vec![];
+            let mut in_paths = false;
+            for rev in revs {
+                if !in_paths {
+                    match revision::range(repo, &rev) {
+                        Ok(_) => {
+                            revisions.push(rev);
+                            continue;
+                        }
+                        Err(_) if Path::new(&rev).exists() => in_paths = true,
+                        Err(err) => return Err(err),
+                    }
+                }
+                walk.paths.push(rev);
+            }
+            let cmd = log::Log {
+                options: walk.into_options(repo)?,
+                abbrev_commit: abbrev_commit || (oneline && pretty == log::Pretty::Oneline),
+                pretty,
+                graph,
+                decorate,
+                date: date
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()?
+                    .unwrap_or_default(),
+                all,
+            };
+            cmd.run(repo, &revisions)?;
+        }
+        Command::MergeBase {
+            all,
+            octopus,
+            is_ancestor,
+            independent,
+            fork_point,
+            commits,
+        } => {
+            let mode = match (octopus, is_ancestor, independent, fork_point) {
+                (true, ..) => merge_base::Mode::Octopus,
+                (_, true, ..) => merge_base::Mode::IsAncestor,
+                (_, _, true, _) => merge_base::Mode::Independent,
+                (.., true) => merge_base::Mode::ForkPoint,
+                _ => merge_base::Mode::Bases,
+            };
+            let cmd = merge_base::MergeBase { mode, all };
+            return cmd.run(repo, &commits);
+        }
+        Command::DiffTree {
+            recursive,
+            name_only,
+            name_status,
+            nul,
+            root,
+            stdin,
+            objects,
+        } => {
+            let format = match (name_only, name_status) {
+                (true, _) => diff::Format::NameOnly,
+                (_, true) => diff::Format::NameStatus,
+                _ => diff::Format::Raw,
+            };
+            let cmd = diff::DiffTree {
+                recursive,
+                format,
+                nul,
+                root,
+            };
+            cmd.run(repo, &objects, stdin)?;
+        }
+        Command::PackObjects {
+            // without it, there is a base name
+            stdout: _,
+            revs,
+            all,
+            window,
+            depth,
+            delta_base_offset,
+            quiet: _,
+            base_name,
+        } => {
+            let config = repo.config()?;
+            let configured = |key: &str, default: usize| {
+                config
+                    .get(key)
+                    .and_then(config::parse_int)
+                    .and_then(|value| usize::try_from(value).ok())
+                    .unwrap_or(default)
+            };
+            let cmd = pack::PackObjects {
+                options: pack::PackOptions {
+                    window: window.unwrap_or_else(|| configured("pack.window", 10)),
+                    depth: depth.unwrap_or_else(|| configured("pack.depth", 50)),
+                    ofs_delta: delta_base_offset,
+                },
+                revs,
+                all,
+            };
+            let base_name = base_name.map(|base_name| repo.file(&base_name));
+            cmd.run(repo, io::stdin().lock(), base_name.as_deref())?;
+        }
+        Command::UnpackObjects { dry_run, quiet: _ } => {
+            pack::unpack_objects(repo, io::stdin().lock(), dry_run)?;
+        }
+        Command::Init { .. }
+        | Command::HashObject { .. }
+        | Command::Config { .. }
+        | Command::IndexPack { .. } => unreachable!("run by main, also outside of a repository"),
     }
     Ok(ExitCode::SUCCESS)
 }
+
+#[cfg(test)]
+mod cli {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn verify() {
+        Cli::command().debug_assert();
+    }
+}
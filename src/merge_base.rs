@@ -0,0 +1,370 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io::{stdout, BufWriter, Write},
+    process::ExitCode,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    hash::Hash,
+    refs,
+    repository::Repository,
+    revision::{self, RevisionError},
+    BlobType,
+};
+
+/// reachable from the first commit of a walk
+const PARENT1: u8 = 1 << 0;
+/// reachable from one of the other commits of a walk
+const PARENT2: u8 = 1 << 1;
+/// reachable from a common ancestor, so not one of the best
+const STALE: u8 = 1 << 2;
+/// a common ancestor that was already found
+const RESULT: u8 = 1 << 3;
+
+/// the generation of commits that are not in the commit-graph, after those that are
+const INFINITY: u32 = u32::MAX;
+
+/// the commits walks go through, read once
+struct Commits<'a> {
+    repo: &'a Repository,
+    /// the generation, committer date and parents of each commit
+    read: HashMap<Hash, (u32, i64, Vec<Hash>)>,
+}
+
+impl<'a> Commits<'a> {
+    fn new(repo: &'a Repository) -> Self {
+        Self {
+            repo,
+            read: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, hash: &Hash) -> anyhow::Result<&(u32, i64, Vec<Hash>)> {
+        if !self.read.contains_key(hash) {
+            let commit = self.repo.read_commit(hash)?;
+            let generation = self.repo.generation(hash).unwrap_or(INFINITY);
+            let time = commit.committer().timestamp();
+            self.read
+                .insert(hash.clone(), (generation, time, commit.parents().to_vec()));
+        }
+        Ok(&self.read[hash])
+    }
+
+    fn generation(&mut self, hash: &Hash) -> anyhow::Result<u32> {
+        Ok(self.get(hash)?.0)
+    }
+
+    fn time(&mut self, hash: &Hash) -> anyhow::Result<i64> {
+        Ok(self.get(hash)?.1)
+    }
+
+    /// inserts a commit before the first older one, like git's `commit_list_insert_by_date`
+    fn insert_by_date(&mut self, list: &mut Vec<Hash>, hash: Hash) -> anyhow::Result<()> {
+        let time = self.time(&hash)?;
+        let mut at = list.len();
+        for (i, other) in list.iter().enumerate() {
+            if self.time(other)? < time {
+                at = i;
+                break;
+            }
+        }
+        list.insert(at, hash);
+        Ok(())
+    }
+
+    /// walks down from `one` and `twos` by generation and date until only commits reachable
+    /// from common ancestors are left, like git's `paint_down_to_common`.
+    ///
+    /// returns the common ancestors found on the way, newest first, and the flags of all
+    /// commits walked through. commits with a generation below `min_generation` are not
+    /// walked through
+    fn paint_down_to_common(
+        &mut self,
+        one: &Hash,
+        twos: &[Hash],
+        min_generation: u32,
+    ) -> anyhow::Result<(Vec<Hash>, HashMap<Hash, u8>)> {
+        let mut flags: HashMap<Hash, u8> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let mut added = 0;
+        let mut push = |commits: &mut Self, queue: &mut BinaryHeap<_>, hash: &Hash| {
+            let &(generation, time, _) = commits.get(hash)?;
+            queue.push((generation, time, Reverse(added), hash.clone()));
+            added += 1;
+            anyhow::Ok(())
+        };
+
+        *flags.entry(one.clone()).or_default() |= PARENT1;
+        let mut result = vec![];
+        if twos.is_empty() {
+            return Ok((vec![one.clone()], flags));
+        }
+        push(self, &mut queue, one)?;
+        for two in twos {
+            *flags.entry(two.clone()).or_default() |= PARENT2;
+            push(self, &mut queue, two)?;
+        }
+
+        while queue.iter().any(|(_, _, _, hash)| flags[hash] & STALE == 0) {
+            let (generation, _, _, hash) = queue.pop().expect("not empty");
+            if generation < min_generation {
+                break;
+            }
+            let mut found = flags[&hash] & (PARENT1 | PARENT2 | STALE);
+            if found == PARENT1 | PARENT2 {
+                if flags[&hash] & RESULT == 0 {
+                    *flags.get_mut(&hash).expect("flagged") |= RESULT;
+                    self.insert_by_date(&mut result, hash.clone())?;
+                }
+                // everything reachable from a common ancestor is not one of the best
+                found |= STALE;
+            }
+            for parent in self.get(&hash)?.2.clone() {
+                let parent_flags = flags.entry(parent.clone()).or_default();
+                if *parent_flags & found == found {
+                    continue;
+                }
+                *parent_flags |= found;
+                push(self, &mut queue, &parent)?;
+            }
+        }
+        Ok((result, flags))
+    }
+
+    /// the common ancestors of `one` and any of `twos` that are not reachable from other
+    /// common ancestors, newest first. more than one can be left if `cleanup` is false
+    fn merge_bases(
+        &mut self,
+        one: &Hash,
+        twos: &[Hash],
+        cleanup: bool,
+    ) -> anyhow::Result<Vec<Hash>> {
+        if twos.contains(one) {
+            return Ok(vec![one.clone()]);
+        }
+        let (found, flags) = self.paint_down_to_common(one, twos, 0)?;
+        let bases: Vec<Hash> = found
+            .into_iter()
+            .filter(|hash| flags[hash] & STALE == 0)
+            .collect();
+        if !cleanup || bases.len() < 2 {
+            return Ok(bases);
+        }
+        // the walk may stop early at a common ancestor that is reachable from another
+        let mut bases = self.remove_redundant(&bases)?;
+        let mut times = HashMap::new();
+        for hash in &bases {
+            times.insert(hash.clone(), self.time(hash)?);
+        }
+        bases.sort_by_key(|hash| Reverse(times[hash]));
+        Ok(bases)
+    }
+
+    /// the commits that are not reachable from any of the others, in the order they are given
+    fn remove_redundant(&mut self, commits: &[Hash]) -> anyhow::Result<Vec<Hash>> {
+        let mut redundant = vec![false; commits.len()];
+        for (i, commit) in commits.iter().enumerate() {
+            if redundant[i] {
+                continue;
+            }
+            let mut min_generation = self.generation(commit)?;
+            let mut others = vec![];
+            for (j, other) in commits.iter().enumerate() {
+                if i == j || redundant[j] {
+                    continue;
+                }
+                min_generation = min_generation.min(self.generation(other)?);
+                others.push((j, other.clone()));
+            }
+            let twos: Vec<Hash> = others.iter().map(|(_, other)| other.clone()).collect();
+            let (_, flags) = self.paint_down_to_common(commit, &twos, min_generation)?;
+            if flags[commit] & PARENT2 != 0 {
+                redundant[i] = true;
+            }
+            for (j, other) in &others {
+                if flags[other] & PARENT1 != 0 {
+                    redundant[*j] = true;
+                }
+            }
+        }
+        Ok(commits
+            .iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(hash, _)| hash.clone())
+            .collect())
+    }
+}
+
+impl Repository {
+    /// the best common ancestors of `one` and any of `others`, newest first: the common
+    /// ancestors that are not reachable from other common ancestors
+    pub fn merge_bases(&self, one: &Hash, others: &[Hash]) -> anyhow::Result<Vec<Hash>> {
+        Commits::new(self).merge_bases(one, others, true)
+    }
+
+    /// the best common ancestors of all of the commits together
+    pub fn octopus_merge_bases(&self, commits: &[Hash]) -> anyhow::Result<Vec<Hash>> {
+        let mut walk = Commits::new(self);
+        let Some((first, rest)) = commits.split_first() else {
+            return Ok(vec![]);
+        };
+        let mut bases = vec![first.clone()];
+        for commit in rest {
+            let mut next = vec![];
+            for base in &bases {
+                next.extend(walk.merge_bases(commit, std::slice::from_ref(base), true)?);
+            }
+            bases = next;
+        }
+        Ok(bases)
+    }
+
+    /// whether `ancestor` is reachable from `descendant`, or the same commit
+    pub fn is_ancestor(&self, ancestor: &Hash, descendant: &Hash) -> anyhow::Result<bool> {
+        let mut walk = Commits::new(self);
+        let generation = walk.generation(ancestor)?;
+        // ancestors have a smaller generation than their descendants
+        if generation > walk.generation(descendant)? {
+            return Ok(false);
+        }
+        let twos = std::slice::from_ref(descendant);
+        let (_, flags) = walk.paint_down_to_common(ancestor, twos, generation)?;
+        Ok(flags[ancestor] & PARENT2 != 0)
+    }
+
+    /// the commits that are not reachable from any of the others, without duplicates and in
+    /// the order they are given
+    pub fn independent(&self, commits: &[Hash]) -> anyhow::Result<Vec<Hash>> {
+        let mut unique: Vec<Hash> = vec![];
+        for commit in commits {
+            if !unique.contains(commit) {
+                unique.push(commit.clone());
+            }
+        }
+        Commits::new(self).remove_redundant(&unique)
+    }
+
+    /// where `commit` forked from the history of `refname`, as its reflog remembers it: the
+    /// best common ancestor of `commit` and all the commits the ref pointed to, if that is one
+    /// of them
+    pub fn fork_point(&self, refname: &str, commit: &Hash) -> anyhow::Result<Option<Hash>> {
+        let (full_name, current) =
+            refs::dwim(self, refname)?.with_context(|| format!("No such ref: '{refname}'"))?;
+        let entries = refs::reflog(self, &full_name)?;
+        let values = entries
+            .first()
+            .map(|(old, _)| old)
+            .into_iter()
+            .chain(entries.iter().map(|(_, new)| new));
+        let mut candidates: Vec<Hash> = vec![];
+        for hash in values {
+            let is_commit = matches!(self.object_header(hash), Ok((BlobType::Commit, _)));
+            if is_commit && !candidates.contains(hash) {
+                candidates.push(hash.clone());
+            }
+        }
+        if candidates.is_empty() {
+            candidates.push(revision::peel(self, refname, current, BlobType::Commit)?);
+        }
+
+        let bases = Commits::new(self).merge_bases(commit, &candidates, false)?;
+        match bases.as_slice() {
+            [base] if candidates.contains(base) => Ok(Some(base.clone())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// what `merge-base` finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// the best common ancestors of the first commit and any of the others
+    Bases,
+    /// the best common ancestors of all commits together
+    Octopus,
+    /// whether the first commit is an ancestor of the second, only as the exit status
+    IsAncestor,
+    /// the commits that are not reachable from the others
+    Independent,
+    /// where a commit forked from a ref, using its reflog
+    ForkPoint,
+}
+
+/// the `merge-base` command
+#[derive(Debug)]
+pub struct MergeBase {
+    pub mode: Mode,
+    /// prints all best common ancestors instead of one
+    pub all: bool,
+}
+
+impl MergeBase {
+    pub fn run(&self, repo: &Repository, args: &[String]) -> anyhow::Result<ExitCode> {
+        let commit = |spec: &str| -> anyhow::Result<Hash> {
+            revision::resolve_typed(repo, spec, BlobType::Commit).map_err(|err| match err
+                .downcast_ref::<RevisionError>(
+            ) {
+                Some(RevisionError::WrongType(..)) => {
+                    anyhow::anyhow!("Not a valid commit name {spec}")
+                }
+                _ => err,
+            })
+        };
+        let commits = || {
+            args.iter()
+                .map(|arg| commit(arg))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        let found = match self.mode {
+            Mode::IsAncestor => {
+                let [ancestor, descendant] = args else {
+                    bail!("--is-ancestor takes exactly two commits");
+                };
+                return Ok(
+                    match repo.is_ancestor(&commit(ancestor)?, &commit(descendant)?)? {
+                        true => ExitCode::SUCCESS,
+                        false => ExitCode::FAILURE,
+                    },
+                );
+            }
+            Mode::ForkPoint => {
+                let (refname, derived) = match args {
+                    [refname] => (refname, "HEAD"),
+                    [refname, derived] => (refname, derived.as_str()),
+                    _ => bail!("--fork-point takes a ref and at most one commit"),
+                };
+                repo.fork_point(refname, &commit(derived)?)?
+                    .into_iter()
+                    .collect()
+            }
+            Mode::Independent => repo.independent(&commits()?)?,
+            Mode::Octopus => repo.independent(&repo.octopus_merge_bases(&commits()?)?)?,
+            Mode::Bases => {
+                let commits = commits()?;
+                let [one, others @ ..] = commits.as_slice() else {
+                    bail!("at least two commits are needed");
+                };
+                if others.is_empty() {
+                    bail!("at least two commits are needed");
+                }
+                repo.merge_bases(one, others)?
+            }
+        };
+
+        if found.is_empty() {
+            return Ok(ExitCode::FAILURE);
+        }
+        let all = self.all || self.mode == Mode::Independent;
+        let mut out = BufWriter::new(stdout().lock());
+        for hash in found.iter().take(if all { found.len() } else { 1 }) {
+            writeln!(out, "{hash}")?;
+        }
+        out.flush()?;
+        Ok(ExitCode::SUCCESS)
+    }
+}
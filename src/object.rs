@@ -3,14 +3,14 @@ use std::{
     collections::HashMap,
     ffi::OsString,
     fmt::Display,
-    fs::{create_dir, File},
+    fs::File,
     io::BufRead,
     os::unix::{ffi::OsStringExt, fs::PermissionsExt},
     path::{Path, PathBuf},
 };
 
-use anyhow::Context;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
@@ -21,7 +21,9 @@ use nom::{
 };
 use walkdir::DirEntry;
 
-use crate::{hash::Hash, root, IoErrorExt, PathBufExt};
+use crate::index::{Index, IndexEntry};
+use crate::repository::Repository;
+use crate::{hash::Hash, BlobType, PathBufExt};
 use crate::{ReadError, Readable, Writeable};
 
 pub struct ZlibWriter<T>(T);
@@ -53,6 +55,29 @@ where
     }
 }
 
+/// decompresses just enough of an object to read its type and size
+pub fn read_header<R: Read>(r: R) -> Result<(BlobType, usize), ReadError<ParseError>> {
+    let mut decoder = ZlibDecoder::new(r);
+    let mut header = [0; 32];
+    let mut filled = 0;
+    while filled < header.len() && !header[..filled].contains(&b'\0') {
+        let read = decoder
+            .read(&mut header[filled..])
+            .map_err(ReadError::IoError)?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    let (_, (typ, len)) = object_header(&header[..filled])
+        .map_err(|_| ReadError::ParseError(ParseError::FormatError))?;
+    let typ = std::str::from_utf8(typ)
+        .ok()
+        .and_then(|typ| BlobType::from_str(typ, false).ok())
+        .ok_or(ReadError::ParseError(ParseError::FormatError))?;
+    Ok((typ, len))
+}
+
 impl<T> Writeable for ZlibWriter<T>
 where
     T: Writeable,
@@ -107,23 +132,34 @@ pub enum ParseError {
     LengthMismatch,
 }
 
+/// parses the `<type> <size>\0` header every object starts with
+pub fn object_header(s: &[u8]) -> IResult<&[u8], (&[u8], usize)> {
+    let (s, typ) = nom::character::complete::alpha1(s)?;
+    let (s, _) = tag(" ")(s)?;
+    let (s, len) = digit1(s)?;
+    let err = nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::Digit));
+    let len: usize = len.parse_to().ok_or(err)?;
+    let (s, _) = tag("\0")(s)?;
+    Ok((s, (typ, len)))
+}
+
+/// strips the header off an object of type `typ`, checking the advertised length
+fn object_body<'a>(typ: &str, s: &'a [u8]) -> Result<&'a [u8], ParseError> {
+    let (body, (found, len)) = object_header(s).map_err(|_| ParseError::FormatError)?;
+    if found != typ.as_bytes() || body.len() < len {
+        return Err(ParseError::FormatError);
+    }
+    if body.len() > len {
+        return Err(ParseError::LengthMismatch);
+    }
+    Ok(body)
+}
+
 impl TryFrom<&[u8]> for Blob {
     type Error = ParseError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        fn parser(s: &[u8]) -> IResult<&[u8], &[u8]> {
-            let (s, _) = tag("blob ")(s)?;
-            let (s, len) = digit1(s)?;
-            let (s, _) = tag("\0")(s)?;
-            let err = nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::Digit));
-            let len: usize = len.parse_to().ok_or(err)?;
-            let (rest, blob) = nom::bytes::complete::take(len)(s)?;
-            Ok((rest, blob))
-        }
-        let (rest, blob) = parser(value).map_err(|_| ParseError::FormatError)?;
-        if !rest.is_empty() {
-            return Err(ParseError::LengthMismatch);
-        }
+        let blob = object_body("blob", value)?;
         Ok(Blob {
             content: blob.to_vec(),
         })
@@ -147,10 +183,12 @@ impl Readable for Blob {
     }
 }
 
-#[derive(Debug, derive_more::Display)]
+#[derive(Debug)]
 pub enum Object {
     Blob(Blob),
     Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
 }
 
 impl Writeable for Object {
@@ -158,12 +196,48 @@ impl Writeable for Object {
         match self {
             Object::Blob(b) => <Blob as Writeable>::fmt(b, f)?,
             Object::Tree(t) => <Tree as Writeable>::fmt(t, f)?,
+            Object::Commit(c) => <Commit as Writeable>::fmt(c, f)?,
+            Object::Tag(t) => <Tag as Writeable>::fmt(t, f)?,
         }
 
         Ok(())
     }
 }
 
+impl TryFrom<&[u8]> for Object {
+    type Error = ParseError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        let (_, (typ, _)) = object_header(s).map_err(|_| ParseError::FormatError)?;
+        let object = match typ {
+            b"blob" => Object::Blob(s.try_into()?),
+            b"tree" => Object::Tree(s.try_into()?),
+            b"commit" => Object::Commit(s.try_into()?),
+            b"tag" => Object::Tag(s.try_into()?),
+            _ => return Err(ParseError::FormatError),
+        };
+        Ok(object)
+    }
+}
+
+impl Readable for Object {
+    type Error = ParseError;
+
+    fn read<R: std::io::Read>(mut r: R) -> Result<Self, ReadError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        let mut contents = Vec::new();
+        r.read_to_end(&mut contents).map_err(ReadError::IoError)?;
+        let object: Self = contents
+            .as_slice()
+            .try_into()
+            .map_err(ReadError::ParseError)?;
+
+        Ok(object)
+    }
+}
+
 impl Object {
     pub fn new_blob(mut source: impl BufRead) -> anyhow::Result<Self> {
         let mut buf = Vec::new();
@@ -173,23 +247,33 @@ impl Object {
     }
 
     pub fn hash(&self) -> Hash {
-        let s = self.to_string();
-        Hash::from_bytes(s.as_bytes())
+        Hash::from_writable(self)
     }
 
-    /// creates everything necessary to write the object file
-    pub fn path(h: &Hash) -> anyhow::Result<PathBuf> {
-        let path = root().push_dir("objects").push_dir(h.dir());
-        create_dir(path).ignore(std::io::ErrorKind::AlreadyExists, ())?;
-        let path = root().push_dir("objects").push_dir(h.object_path());
-        Ok(path)
+    pub fn typ(&self) -> BlobType {
+        match self {
+            Object::Blob(_) => BlobType::Blob,
+            Object::Tree(_) => BlobType::Tree,
+            Object::Commit(_) => BlobType::Commit,
+            Object::Tag(_) => BlobType::Tag,
+        }
+    }
+
+    /// the serialized object without the `<type> <size>\0` header
+    pub fn body(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.fmt(&mut buf).expect("writing to a vec does not fail");
+        let start = buf.iter().position(|&c| c == b'\0').map_or(0, |i| i + 1);
+        buf.split_off(start)
     }
 }
 
 const REGULAR_FILE: u32 = 0o100644;
 const EXECUTABLE_FILE: u32 = 0o100755;
 const SYMBOLIC_LINK: u32 = 0o120000;
-const DIRECTORY: u32 = 0o040000;
+/// the mode of subtrees in trees
+pub(crate) const DIRECTORY: u32 = 0o040000;
+const GITLINK: u32 = 0o160000;
 
 #[repr(u32)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -198,12 +282,29 @@ enum Perms {
     ExecutableFile = EXECUTABLE_FILE,
     SymbolicLink = SYMBOLIC_LINK,
     Directory = DIRECTORY,
+    /// a commit of a submodule
+    Gitlink = GITLINK,
+}
+
+impl TryFrom<u32> for Perms {
+    type Error = ParseError;
+
+    fn try_from(perm: u32) -> Result<Self, Self::Error> {
+        match perm {
+            REGULAR_FILE => Ok(Perms::RegularFile),
+            EXECUTABLE_FILE => Ok(Perms::ExecutableFile),
+            SYMBOLIC_LINK => Ok(Perms::SymbolicLink),
+            DIRECTORY => Ok(Perms::Directory),
+            GITLINK => Ok(Perms::Gitlink),
+            _ => Err(ParseError::FormatError),
+        }
+    }
 }
 
 impl Perms {
     fn rendered_size(&self) -> usize {
         let me = *self as u32;
-        let size = (u32::BITS - me.leading_zeros() + 2) / 3;
+        let size = (u32::BITS - me.leading_zeros()).div_ceil(3);
 
         size as usize
     }
@@ -218,6 +319,7 @@ mod perms_size {
         assert_eq!(Perms::RegularFile.rendered_size(), 6);
         assert_eq!(Perms::SymbolicLink.rendered_size(), 6);
         assert_eq!(Perms::Directory.rendered_size(), 5);
+        assert_eq!(Perms::Gitlink.rendered_size(), 6);
     }
 }
 
@@ -279,17 +381,6 @@ impl Readable for Tree {
 impl TryFrom<&[u8]> for Tree {
     type Error = ParseError;
     fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
-        fn header(s: &[u8]) -> IResult<&[u8], &[u8]> {
-            let (s, _) = tag("tree ")(s)?;
-            let (s, len) = digit1(s)?;
-            let err = nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::Digit));
-            let len: usize = len.parse_to().ok_or(err)?;
-            let (s, _) = tag("\0")(s)?;
-
-            let (s, body) = nom::bytes::complete::take(len)(s)?;
-            Ok((s, body))
-        }
-
         fn entry(s: &[u8]) -> IResult<&[u8], TreeEntry> {
             let (s, perm) = oct_digit1(s)?;
             let (s, _) = tag(" ")(s)?;
@@ -307,17 +398,7 @@ impl TryFrom<&[u8]> for Tree {
                 }))
             }
             let perm: u32 = parse_perm(perm).ok_or_else(|| err(s))?;
-            let perms = if perm == REGULAR_FILE {
-                Perms::RegularFile
-            } else if perm == EXECUTABLE_FILE {
-                Perms::ExecutableFile
-            } else if perm == SYMBOLIC_LINK {
-                Perms::SymbolicLink
-            } else if perm == DIRECTORY {
-                Perms::Directory
-            } else {
-                return Err(err(s));
-            };
+            let perms = Perms::try_from(perm).map_err(|_| err(s))?;
 
             let (s, name) = take_until("\0")(s)?;
             let (s, _) = tag("\0")(s)?;
@@ -329,10 +410,7 @@ impl TryFrom<&[u8]> for Tree {
             Ok((s, TreeEntry { perms, name, hash }))
         }
 
-        let (rest, mut body) = header(s).map_err(|_| ParseError::FormatError)?;
-        if !rest.is_empty() {
-            Err(ParseError::LengthMismatch)?;
-        }
+        let mut body = object_body("tree", s)?;
         let mut entries = vec![];
 
         while !body.is_empty() {
@@ -345,20 +423,177 @@ impl TryFrom<&[u8]> for Tree {
     }
 }
 
+impl TreeEntry {
+    /// trees are sorted by name, comparing directories as if their name ended in `/`
+    fn sort_key(&self) -> impl Iterator<Item = &u8> {
+        let suffix: &[u8] = match self.perms {
+            Perms::Directory => b"/",
+            _ => b"",
+        };
+        self.name.as_encoded_bytes().iter().chain(suffix)
+    }
+}
+
 impl Tree {
-    pub fn display(&self) -> TreePrinter {
+    fn new(mut entries: Vec<TreeEntry>) -> Self {
+        entries.sort_by(|a, b| a.sort_key().cmp(b.sort_key()));
+        Self { entries }
+    }
+
+    /// writes the trees for the index entries below `prefix`, returning the hash of the top one
+    pub fn write_index(
+        repo: &Repository,
+        index: &Index,
+        prefix: &[u8],
+        missing_ok: bool,
+    ) -> anyhow::Result<Hash> {
+        let prefix = match prefix {
+            b"" => Vec::new(),
+            prefix => {
+                let mut prefix = prefix.strip_suffix(b"/").unwrap_or(prefix).to_vec();
+                prefix.push(b'/');
+                prefix
+            }
+        };
+
+        let mut entries = vec![];
+        for entry in index.entries() {
+            if entry.stage() != 0 {
+                anyhow::bail!(
+                    "{}: unmerged ({})\ngit-write-tree: error building trees",
+                    String::from_utf8_lossy(&entry.path),
+                    entry.hash
+                );
+            }
+            if entry.path.starts_with(&prefix) && !entry.intent_to_add() {
+                entries.push(entry);
+            }
+        }
+        if entries.is_empty() && !prefix.is_empty() {
+            anyhow::bail!(
+                "git-write-tree: prefix {} not found",
+                String::from_utf8_lossy(&prefix)
+            );
+        }
+
+        fn build(
+            repo: &Repository,
+            entries: &[&IndexEntry],
+            base: usize,
+            missing_ok: bool,
+        ) -> anyhow::Result<Hash> {
+            let mut children = vec![];
+            let mut i = 0;
+            while i < entries.len() {
+                let entry = entries[i];
+                let name = &entry.path[base..];
+                let Some(slash) = name.iter().position(|&c| c == b'/') else {
+                    let perms = Perms::try_from(entry.mode)
+                        .map_err(|_| anyhow::anyhow!("invalid mode {:o}", entry.mode))?;
+                    if perms != Perms::Gitlink && !missing_ok && !repo.has_object(&entry.hash)? {
+                        anyhow::bail!(
+                            "invalid object {:o} {} for '{}'\ngit-write-tree: error building trees",
+                            entry.mode,
+                            entry.hash,
+                            String::from_utf8_lossy(&entry.path)
+                        );
+                    }
+                    children.push(TreeEntry {
+                        perms,
+                        name: OsString::from_vec(name.to_vec()),
+                        hash: entry.hash.clone(),
+                    });
+                    i += 1;
+                    continue;
+                };
+
+                let dir = &name[..=slash];
+                let len = entries[i..]
+                    .iter()
+                    .take_while(|e| e.path[base..].starts_with(dir))
+                    .count();
+                let hash = build(repo, &entries[i..i + len], base + dir.len(), missing_ok)?;
+                children.push(TreeEntry {
+                    perms: Perms::Directory,
+                    name: OsString::from_vec(name[..slash].to_vec()),
+                    hash,
+                });
+                i += len;
+            }
+
+            let tree = Tree::new(children);
+            let hash = Hash::from_writable(&tree);
+            if !repo.has_object(&hash)? {
+                let mut f = File::create(repo.object_file(&hash)?)?;
+                ZlibWriter::new(&tree).fmt(&mut f)?;
+            }
+            Ok(hash)
+        }
+
+        build(repo, &entries, prefix.len(), missing_ok)
+    }
+
+    pub fn display(&self) -> TreePrinter<'_> {
         TreePrinter {
             tree: self,
             show_name: true,
             show_perms: true,
             show_object: true,
             show_type: true,
-            recurse: false,
+            recurse: None,
             prefix: PathBuf::new(),
         }
     }
 
-    pub fn write_tree<I>(files: I) -> anyhow::Result<Hash>
+    /// looks up the mode and hash at a slash separated path, reading subtrees as needed
+    pub fn find(&self, repo: &Repository, path: &[u8]) -> anyhow::Result<Option<(u32, Hash)>> {
+        let (name, rest) = match path.iter().position(|&c| c == b'/') {
+            Some(i) => (&path[..i], Some(&path[i + 1..])),
+            None => (path, None),
+        };
+        let Some(entry) = self
+            .entries
+            .iter()
+            .find(|e| e.name.as_encoded_bytes() == name)
+        else {
+            return Ok(None);
+        };
+        match rest {
+            None => Ok(Some((entry.perms as u32, entry.hash.clone()))),
+            Some(rest) if entry.perms == Perms::Directory => {
+                let Object::Tree(tree) = repo.read_object(&entry.hash)? else {
+                    anyhow::bail!("{} is not a tree", entry.hash);
+                };
+                tree.find(repo, rest)
+            }
+            Some(_) => Ok(None),
+        }
+    }
+
+    /// the names of the entries and the objects they refer to, without submodule commits
+    pub fn children(&self) -> impl Iterator<Item = (&[u8], BlobType, &Hash)> {
+        self.entries.iter().filter_map(|entry| {
+            let typ = match entry.perms {
+                Perms::Directory => BlobType::Tree,
+                Perms::Gitlink => return None,
+                _ => BlobType::Blob,
+            };
+            Some((entry.name.as_encoded_bytes(), typ, &entry.hash))
+        })
+    }
+
+    /// the names, modes and objects of all entries, in the order of the tree
+    pub fn entries(&self) -> impl Iterator<Item = (&[u8], u32, &Hash)> {
+        self.entries.iter().map(|entry| {
+            (
+                entry.name.as_encoded_bytes(),
+                entry.perms as u32,
+                &entry.hash,
+            )
+        })
+    }
+
+    pub fn write_tree<I>(repo: &Repository, files: I) -> anyhow::Result<Hash>
     where
         I: Iterator<Item = DirEntry>,
     {
@@ -376,6 +611,7 @@ impl Tree {
         }
 
         fn foo(
+            repo: &Repository,
             map: &HashMap<PathBuf, Vec<DirEntry>>,
             trees: &mut Vec<Tree>,
             current: &Path,
@@ -384,11 +620,11 @@ impl Tree {
             let mut children = vec![];
             for entry in entries {
                 let hash = if entry.file_type().is_dir() {
-                    foo(map, trees, entry.path())?
+                    foo(repo, map, trees, entry.path())?
                 } else {
                     let blob = Blob::new(std::fs::read(entry.path())?);
                     let id = Hash::from_writable(&blob);
-                    let path = Object::path(&id)?;
+                    let path = repo.object_file(&id)?;
                     let mut f = File::create(path)?;
                     ZlibWriter::new(blob).fmt(&mut f)?;
 
@@ -410,18 +646,18 @@ impl Tree {
                 })
             }
 
-            let tree = Tree { entries: children };
+            let tree = Tree::new(children);
             let hash = Hash::from_writable(&tree);
             trees.push(tree);
             Ok(hash)
         }
 
         let mut trees = vec![];
-        let hashed = foo(&collection, &mut trees, PathBuf::from(".").as_path())?;
+        let hashed = foo(repo, &collection, &mut trees, PathBuf::from(".").as_path())?;
 
         for tree in trees {
             let hashed = Hash::from_writable(&tree);
-            let path = Object::path(&hashed)?;
+            let path = repo.object_file(&hashed)?;
             let mut f = File::create(path)?;
             let writer = ZlibWriter::new(&tree);
             writer.fmt(&mut f)?;
@@ -433,15 +669,16 @@ impl Tree {
 
 pub struct TreePrinter<'a> {
     tree: &'a Tree,
+    /// where subtrees are read from, `None` unless recursing
+    recurse: Option<&'a Repository>,
     show_name: bool,
     show_perms: bool,
     show_type: bool,
-    recurse: bool,
     show_object: bool,
     prefix: PathBuf,
 }
 
-impl TreePrinter<'_> {
+impl<'a> TreePrinter<'a> {
     pub fn no_name(&mut self) -> &mut Self {
         self.show_name = false;
         self
@@ -462,8 +699,8 @@ impl TreePrinter<'_> {
         self
     }
 
-    pub fn recusive(&mut self) -> &mut Self {
-        self.recurse = true;
+    pub fn recusive(&mut self, repo: &'a Repository) -> &mut Self {
+        self.recurse = Some(repo);
         self
     }
 }
@@ -498,6 +735,7 @@ impl Display for TreePrinter<'_> {
                 write_sep(f, " ", need_sep)?;
                 let typ = match entry.perms {
                     Perms::Directory => "tree",
+                    Perms::Gitlink => "commit",
                     _ => "blob",
                 };
                 write!(f, "{typ}")?;
@@ -511,26 +749,15 @@ impl Display for TreePrinter<'_> {
             }
 
             if self.show_name {
-                write_sep(f, " ", need_sep)?;
+                write_sep(f, "\t", need_sep)?;
                 print_name(f, &name)?;
                 // need_sep = true;
             }
 
             writeln!(f)?;
 
-            if self.recurse && entry.perms == Perms::Directory {
-                let path = root()
-                    .push_dir("objects")
-                    .push_dir(entry.hash.object_path());
-                let Ok(data) = std::fs::read(path) else {
-                    continue;
-                };
-                let mut decoder = ZlibDecoder::new(data.as_slice());
-                let mut contents = Vec::new();
-                let Ok(_) = decoder.read_to_end(&mut contents) else {
-                    continue;
-                };
-                let Ok(tree): Result<Tree, _> = contents.as_slice().try_into() else {
+            if let (Some(repo), Perms::Directory) = (self.recurse, entry.perms) {
+                let Ok(Object::Tree(tree)) = repo.read_object(&entry.hash) else {
                     continue;
                 };
                 let tree = &tree;
@@ -548,49 +775,171 @@ impl Display for TreePrinter<'_> {
     }
 }
 
-#[derive(Debug)]
+/// a timezone as written in commits and tags, like `+0530` for 5 hours and 30 minutes east of UTC.
+///
+/// it is kept as written, so that objects with odd offsets like `-0000` or `+051800` round trip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offset(String);
+
+impl Offset {
+    /// the offset of a timezone `seconds` east of UTC, rounded to minutes
+    pub fn from_seconds(seconds: i32) -> Self {
+        let sign = if seconds < 0 { '-' } else { '+' };
+        let minutes = seconds.unsigned_abs() / 60;
+        Offset(format!("{sign}{:02}{:02}", minutes / 60, minutes % 60))
+    }
+
+    /// seconds east of UTC, 0 for offsets that are not `±hhmm`
+    pub fn seconds(&self) -> i32 {
+        let (sign, digits) = match self.0.split_at_checked(1) {
+            Some(("-", digits)) => (-1, digits),
+            Some(("+", digits)) => (1, digits),
+            _ => return 0,
+        };
+        match (digits.len(), digits.parse::<i32>()) {
+            (4, Ok(hhmm)) => sign * (hhmm / 100 * 3600 + hhmm % 100 * 60),
+            _ => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Offset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// who did something, and when
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Event {
     name: String,
     email: String,
-    time: DateTime<Local>,
+    /// seconds since the epoch
+    timestamp: i64,
+    offset: Offset,
 }
 
 impl Event {
-    pub fn new(name: String, email: String) -> Self {
-        let now: DateTime<Local> = Local::now();
-        // let x = now.offset().local_minus_utc();
+    pub fn new(name: String, email: String, timestamp: i64, offset: Offset) -> Self {
         Event {
             name,
             email,
-            time: now,
+            timestamp,
+            offset,
         }
     }
+
+    /// an event at `time`, in its timezone
+    pub fn at(name: String, email: String, time: DateTime<FixedOffset>) -> Self {
+        let offset = Offset::from_seconds(time.offset().local_minus_utc());
+        Self::new(name, email, time.timestamp(), offset)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// seconds since the epoch
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn offset(&self) -> &Offset {
+        &self.offset
+    }
 }
 
 impl Writeable for Event {
     fn fmt<W: std::io::Write>(&self, f: &mut W) -> std::io::Result<()> {
-        let offset = self.time.offset().local_minus_utc() / 60 / 60;
-        let sign = offset >= 0;
         write!(
             f,
-            "{} <{}> {} {}{:04}",
-            self.name,
-            self.email,
-            self.time.timestamp(),
-            if sign { "+" } else { "-" },
-            offset.abs()
-        )?;
-        Ok(())
+            "{} <{}> {} {}",
+            self.name, self.email, self.timestamp, self.offset
+        )
     }
 }
 
-#[derive(Debug)]
+fn failure(s: &[u8]) -> nom::Err<nom::error::Error<&[u8]>> {
+    nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::Verify))
+}
+
+/// parses a 40 character hex encoded hash
+fn hex_hash(s: &[u8]) -> IResult<&[u8], Hash> {
+    let (rest, hex) = nom::bytes::complete::take(40usize)(s)?;
+    let hash = std::str::from_utf8(hex)
+        .ok()
+        .and_then(|hex| hex.parse().ok())
+        .ok_or_else(|| failure(s))?;
+    Ok((rest, hash))
+}
+
+/// parses `name <email> timestamp +hhmm`, without the trailing newline
+fn event(s: &[u8]) -> IResult<&[u8], Event> {
+    let (s, name) = take_until("<")(s)?;
+    let name = name.strip_suffix(b" ").unwrap_or(name);
+    let (s, _) = tag("<")(s)?;
+    let (s, email) = take_until(">")(s)?;
+    let (s, _) = tag("> ")(s)?;
+    let (s, timestamp) = digit1(s)?;
+    let (s, _) = tag(" ")(s)?;
+    // odd offsets have a missing sign or more or fewer digits, they are kept as they are
+    let (s, offset) = nom::combinator::recognize(nom::sequence::pair(
+        nom::combinator::opt(nom::branch::alt((tag("+"), tag("-")))),
+        digit1,
+    ))(s)?;
+
+    let name = String::from_utf8(name.to_vec()).map_err(|_| failure(s))?;
+    let email = String::from_utf8(email.to_vec()).map_err(|_| failure(s))?;
+    let timestamp: i64 = timestamp.parse_to().ok_or_else(|| failure(s))?;
+    let offset = Offset(String::from_utf8(offset.to_vec()).map_err(|_| failure(s))?);
+
+    Ok((s, Event::new(name, email, timestamp, offset)))
+}
+
+/// parses a header line of a commit or tag, including continuation lines.
+///
+/// continuation lines start with a single space, which is not part of the value
+fn extra_header(s: &[u8]) -> IResult<&[u8], (String, Vec<u8>)> {
+    let (s, key) = nom::bytes::complete::take_till1(|c| c == b' ' || c == b'\n')(s)?;
+    let (s, _) = tag(" ")(s)?;
+    let (mut s, line) = take_until("\n")(s)?;
+    let mut value = line.to_vec();
+    (s, _) = tag("\n")(s)?;
+    while let Some(rest) = s.strip_prefix(b" ") {
+        let (rest, line) = take_until("\n")(rest)?;
+        (s, _) = tag("\n")(rest)?;
+        value.push(b'\n');
+        value.extend_from_slice(line);
+    }
+    let key = String::from_utf8(key.to_vec()).map_err(|_| failure(s))?;
+
+    Ok((s, (key, value)))
+}
+
+fn write_extra_header<W: Write>(f: &mut W, key: &str, value: &[u8]) -> std::io::Result<()> {
+    write!(f, "{key} ")?;
+    for (i, line) in value.split(|&c| c == b'\n').enumerate() {
+        if i != 0 {
+            write!(f, "\n ")?;
+        }
+        f.write_all(line)?;
+    }
+    writeln!(f)
+}
+
+#[derive(Debug, Clone)]
 pub struct Commit {
     parents: Vec<Hash>,
     tree: Hash,
     author: Event,
     committer: Event,
-    commit_message: String,
+    /// headers after the committer, such as `encoding`, `mergetag` or `gpgsig`, in order
+    extra_headers: Vec<(String, Vec<u8>)>,
+    commit_message: Vec<u8>,
 }
 
 impl Writeable for Commit {
@@ -612,48 +961,468 @@ impl Writeable for Commit {
         self.committer.fmt(&mut body)?;
         writeln!(body)?;
 
+        for (key, value) in &self.extra_headers {
+            write_extra_header(&mut body, key, value)?;
+        }
+
         writeln!(body)?;
-        write!(body, "{}", self.commit_message)?;
+        body.write_all(&self.commit_message)?;
 
         write!(f, "commit {}\0", body.len())?;
         f.write_all(body.as_slice())
     }
 }
 
+impl TryFrom<&[u8]> for Commit {
+    type Error = ParseError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        fn body(s: &[u8]) -> IResult<&[u8], Commit> {
+            let (s, _) = tag("tree ")(s)?;
+            let (s, tree) = hex_hash(s)?;
+            let (mut s, _) = tag("\n")(s)?;
+
+            let mut parents = vec![];
+            while let Some(rest) = s.strip_prefix(b"parent ") {
+                let (rest, parent) = hex_hash(rest)?;
+                (s, _) = tag("\n")(rest)?;
+                parents.push(parent);
+            }
+
+            let (s, _) = tag("author ")(s)?;
+            let (s, author) = event(s)?;
+            let (s, _) = tag("\n")(s)?;
+            let (s, _) = tag("committer ")(s)?;
+            let (s, committer) = event(s)?;
+            let (mut s, _) = tag("\n")(s)?;
+
+            let mut extra_headers = vec![];
+            while !s.starts_with(b"\n") {
+                let (rest, header) = extra_header(s)?;
+                extra_headers.push(header);
+                s = rest;
+            }
+            let (s, _) = tag("\n")(s)?;
+
+            let commit = Commit {
+                parents,
+                tree,
+                author,
+                committer,
+                extra_headers,
+                commit_message: s.to_vec(),
+            };
+            Ok((&[], commit))
+        }
+
+        let content = object_body("commit", s)?;
+        let (_, commit) = body(content).map_err(|_| ParseError::FormatError)?;
+        Ok(commit)
+    }
+}
+
 impl Readable for Commit {
     type Error = ParseError;
 
-    fn read<R: std::io::Read>(_: R) -> Result<Self, ReadError<Self::Error>>
+    fn read<R: std::io::Read>(mut r: R) -> Result<Self, ReadError<Self::Error>>
     where
         Self: Sized,
     {
-        todo!()
+        let mut contents = Vec::new();
+        r.read_to_end(&mut contents).map_err(ReadError::IoError)?;
+        let commit: Self = contents
+            .as_slice()
+            .try_into()
+            .map_err(ReadError::ParseError)?;
+
+        Ok(commit)
     }
 }
 
 impl Commit {
     pub fn new(
+        repo: &Repository,
         tree: Hash,
         message: &str,
         author: Event,
         committer: Event,
         parents: impl IntoIterator<Item = Hash>,
     ) -> anyhow::Result<Self> {
-        if !root()
-            .push_dir("objects")
-            .push_dir(tree.object_path())
-            .metadata()
-            .context("tree does not exist")?
-            .is_file()
-        {
+        if !repo.has_object(&tree)? {
             anyhow::bail!("no such tree: {tree}");
         }
         Ok(Commit {
             tree,
             author,
             committer,
-            commit_message: message.to_owned(),
+            extra_headers: vec![],
+            commit_message: message.as_bytes().to_vec(),
             parents: parents.into_iter().collect(),
         })
     }
+
+    pub fn tree(&self) -> &Hash {
+        &self.tree
+    }
+
+    pub fn parents(&self) -> &[Hash] {
+        &self.parents
+    }
+
+    pub fn author(&self) -> &Event {
+        &self.author
+    }
+
+    pub fn committer(&self) -> &Event {
+        &self.committer
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.commit_message
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    object: Hash,
+    typ: BlobType,
+    tag: String,
+    /// very old tags were created without a tagger
+    tagger: Option<Event>,
+    message: Vec<u8>,
+    /// a signature appended to the message, starting at its `-----BEGIN` line
+    signature: Option<Vec<u8>>,
+}
+
+const SIGNATURE_STARTS: [&[u8]; 3] = [
+    b"-----BEGIN PGP SIGNATURE-----",
+    b"-----BEGIN PGP MESSAGE-----",
+    b"-----BEGIN SSH SIGNATURE-----",
+];
+
+/// splits a trailing signature off a tag message
+fn split_signature(message: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let mut start = 0;
+    while start < message.len() {
+        let line = &message[start..];
+        if SIGNATURE_STARTS.iter().any(|sig| line.starts_with(sig)) {
+            return (&message[..start], Some(line));
+        }
+        match line.iter().position(|&c| c == b'\n') {
+            Some(end) => start += end + 1,
+            None => break,
+        }
+    }
+    (message, None)
+}
+
+impl Writeable for Tag {
+    fn fmt<W: Write>(&self, f: &mut W) -> std::io::Result<()> {
+        let mut body =
+            Vec::with_capacity("object".len() + "type".len() + "tag".len() + self.message.len());
+
+        writeln!(body, "object {}", self.object)?;
+        writeln!(body, "type {}", self.typ)?;
+        writeln!(body, "tag {}", self.tag)?;
+        if let Some(tagger) = &self.tagger {
+            write!(body, "tagger ")?;
+            tagger.fmt(&mut body)?;
+            writeln!(body)?;
+        }
+
+        writeln!(body)?;
+        body.write_all(&self.message)?;
+        if let Some(signature) = &self.signature {
+            body.write_all(signature)?;
+        }
+
+        write!(f, "tag {}\0", body.len())?;
+        f.write_all(body.as_slice())
+    }
+}
+
+impl TryFrom<&[u8]> for Tag {
+    type Error = ParseError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        fn body(s: &[u8]) -> IResult<&[u8], Tag> {
+            let (s, _) = tag("object ")(s)?;
+            let (s, object) = hex_hash(s)?;
+            let (s, _) = tag("\n")(s)?;
+            let (s, _) = tag("type ")(s)?;
+            let (s, typ) = take_until("\n")(s)?;
+            let typ = std::str::from_utf8(typ)
+                .ok()
+                .and_then(|typ| BlobType::from_str(typ, false).ok())
+                .ok_or_else(|| failure(s))?;
+            let (s, _) = tag("\n")(s)?;
+            let (s, _) = tag("tag ")(s)?;
+            let (s, name) = take_until("\n")(s)?;
+            let name = String::from_utf8(name.to_vec()).map_err(|_| failure(s))?;
+            let (mut s, _) = tag("\n")(s)?;
+
+            let mut tagger = None;
+            if let Some(rest) = s.strip_prefix(b"tagger ") {
+                let (rest, event) = event(rest)?;
+                (s, _) = tag("\n")(rest)?;
+                tagger = Some(event);
+            }
+            let (s, _) = tag("\n")(s)?;
+            let (message, signature) = split_signature(s);
+
+            let tag = Tag {
+                object,
+                typ,
+                tag: name,
+                tagger,
+                message: message.to_vec(),
+                signature: signature.map(<[u8]>::to_vec),
+            };
+            Ok((&[], tag))
+        }
+
+        let content = object_body("tag", s)?;
+        let (_, tag) = body(content).map_err(|_| ParseError::FormatError)?;
+        Ok(tag)
+    }
+}
+
+impl Readable for Tag {
+    type Error = ParseError;
+
+    fn read<R: std::io::Read>(mut r: R) -> Result<Self, ReadError<Self::Error>>
+    where
+        Self: Sized,
+    {
+        let mut contents = Vec::new();
+        r.read_to_end(&mut contents).map_err(ReadError::IoError)?;
+        let tag: Self = contents
+            .as_slice()
+            .try_into()
+            .map_err(ReadError::ParseError)?;
+
+        Ok(tag)
+    }
+}
+
+impl Tag {
+    pub fn new(object: Hash, typ: BlobType, tag: String, tagger: Event, message: &str) -> Self {
+        Tag {
+            object,
+            typ,
+            tag,
+            tagger: Some(tagger),
+            message: message.as_bytes().to_vec(),
+            signature: None,
+        }
+    }
+
+    pub fn object(&self) -> &Hash {
+        &self.object
+    }
+
+    pub fn typ(&self) -> BlobType {
+        self.typ
+    }
+
+    pub fn tagger(&self) -> Option<&Event> {
+        self.tagger.as_ref()
+    }
+
+    /// the name of the tag, like `v1.0`
+    pub fn name(&self) -> &str {
+        &self.tag
+    }
+}
+
+#[cfg(test)]
+mod commit_parse {
+    use super::*;
+
+    const SIGNED_MERGE: &[u8] = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+parent 3aa835bd3a231d1332d1dbc72a014ab29ec0b2aa
+parent 443aa835bd3a231d1332d1dbc72a014ab29ec0b2
+author A U Thor <author@example.com> 1112911993 +0530
+committer C O Mitter <committer@example.com> 1112912053 -0700
+encoding ISO-8859-1
+mergetag object 3aa835bd3a231d1332d1dbc72a014ab29ec0b2aa
+ type commit
+ tag v1.0
+ tagger C O Mitter <committer@example.com> 1112912053 -0700
+ 
+ release
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+Merge branch 'topic'
+
+with a body
+";
+
+    fn with_header(body: &[u8]) -> Vec<u8> {
+        let mut v = format!("commit {}\0", body.len()).into_bytes();
+        v.extend_from_slice(body);
+        v
+    }
+
+    #[test]
+    fn round_trip() {
+        let raw = with_header(SIGNED_MERGE);
+        let commit = Commit::try_from(raw.as_slice()).unwrap();
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.author.name, "A U Thor");
+        assert_eq!(commit.committer.email, "committer@example.com");
+        let keys: Vec<_> = commit
+            .extra_headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect();
+        assert_eq!(keys, ["encoding", "mergetag", "gpgsig"]);
+        assert_eq!(commit.extra_headers[0].1, b"ISO-8859-1");
+        assert!(commit.extra_headers[1].1.ends_with(b"\n\nrelease"));
+        assert_eq!(
+            commit.commit_message,
+            b"Merge branch 'topic'\n\nwith a body\n"
+        );
+
+        let mut written = vec![];
+        commit.fmt(&mut written).unwrap();
+        assert_eq!(written, raw);
+    }
+
+    #[test]
+    fn root_commit_without_message() {
+        let raw = with_header(
+            b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A <a@b> 0 +0000
+committer A <a@b> 0 +0000
+
+",
+        );
+        let commit = Commit::try_from(raw.as_slice()).unwrap();
+        assert!(commit.parents.is_empty());
+        assert!(commit.commit_message.is_empty());
+
+        let mut written = vec![];
+        commit.fmt(&mut written).unwrap();
+        assert_eq!(written, raw);
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let raw = with_header(b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n");
+        assert!(Commit::try_from(raw.as_slice()).is_err());
+    }
+}
+
+#[cfg(test)]
+mod event_parse {
+    use super::*;
+
+    fn round_trip(raw: &[u8]) -> Event {
+        let (rest, event) = event(raw).unwrap();
+        assert!(rest.is_empty());
+        let mut written = vec![];
+        event.fmt(&mut written).unwrap();
+        assert_eq!(written, raw);
+        event
+    }
+
+    #[test]
+    fn offsets() {
+        let event = round_trip(b"A U Thor <author@example.com> 1112911993 +0530");
+        assert_eq!(event.timestamp, 1112911993);
+        assert_eq!(event.offset, Offset::from_seconds(5 * 3600 + 30 * 60));
+        round_trip(b"C O Mitter <committer@example.com> 1112912053 -0700");
+        // seen in the wild, and kept as they are
+        round_trip(b"A <a@b> 0 -0000");
+        round_trip(b"A <a@b> 1234567890 +051800");
+        round_trip(b"A <a@b> 1234567890 +01");
+        round_trip(b"A <a@b> 1234567890 0100");
+    }
+
+    #[test]
+    fn from_seconds() {
+        assert_eq!(Offset::from_seconds(0).to_string(), "+0000");
+        assert_eq!(Offset::from_seconds(3600).to_string(), "+0100");
+        assert_eq!(
+            Offset::from_seconds(-(9 * 3600 + 30 * 60)).to_string(),
+            "-0930"
+        );
+        assert_eq!(Offset::from_seconds(14 * 3600).to_string(), "+1400");
+        assert_eq!(
+            Offset::from_seconds(5 * 3600 + 45 * 60).to_string(),
+            "+0545"
+        );
+    }
+
+    #[test]
+    fn at() {
+        let time = DateTime::parse_from_rfc3339("2005-04-07T22:13:13+05:30").unwrap();
+        let event = Event::at("A".to_owned(), "a@b".to_owned(), time);
+        let mut written = vec![];
+        event.fmt(&mut written).unwrap();
+        assert_eq!(written, b"A <a@b> 1112892193 +0530");
+    }
+}
+
+#[cfg(test)]
+mod tag_parse {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let body = b"object 3aa835bd3a231d1332d1dbc72a014ab29ec0b2aa
+type commit
+tag v1.0
+tagger C O Mitter <committer@example.com> 1112912053 -0700
+
+release v1.0
+";
+        let mut raw = format!("tag {}\0", body.len()).into_bytes();
+        raw.extend_from_slice(body);
+
+        let Object::Tag(tag) = Object::try_from(raw.as_slice()).unwrap() else {
+            panic!("expected a tag");
+        };
+        assert_eq!(tag.typ, BlobType::Commit);
+        assert_eq!(tag.tag, "v1.0");
+        assert_eq!(tag.message, b"release v1.0\n");
+        assert!(tag.signature.is_none());
+
+        let mut written = vec![];
+        tag.fmt(&mut written).unwrap();
+        assert_eq!(written, raw);
+    }
+
+    #[test]
+    fn signed() {
+        let body = b"object 3aa835bd3a231d1332d1dbc72a014ab29ec0b2aa
+type commit
+tag v1.0
+tagger C O Mitter <committer@example.com> 1112912053 -0700
+
+release v1.0
+-----BEGIN PGP SIGNATURE-----
+
+iQEzBAABCAAdFiEE
+-----END PGP SIGNATURE-----
+";
+        let mut raw = format!("tag {}\0", body.len()).into_bytes();
+        raw.extend_from_slice(body);
+
+        let tag = Tag::try_from(raw.as_slice()).unwrap();
+        assert_eq!(tag.message, b"release v1.0\n");
+        assert!(tag
+            .signature
+            .as_ref()
+            .unwrap()
+            .starts_with(b"-----BEGIN PGP SIGNATURE-----\n"));
+
+        let mut written = vec![];
+        tag.fmt(&mut written).unwrap();
+        assert_eq!(written, raw);
+    }
 }
@@ -0,0 +1,1337 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::Permissions,
+    io::{BufRead, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Context};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+use nom::{
+    bytes::complete::{tag, take},
+    multi::count,
+    number::complete::{be_u32, be_u64},
+    IResult,
+};
+
+use crate::{
+    hash::Hash, refs, repository::Repository, revision, walk, BlobType, HashObject, IoErrorExt,
+};
+
+const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const PACK_HEADER_LEN: usize = 12;
+const HASH_LEN: usize = 20;
+/// offsets with this bit set are an index into the table of 64-bit offsets of a v2 index
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// how many bytes of resolved delta bases are kept, like git's `core.deltaBaseCacheLimit`
+const DELTA_BASE_CACHE_LIMIT: usize = 96 << 20;
+
+#[derive(Debug, derive_more::Display, Clone, thiserror::Error)]
+pub enum PackError {
+    #[display(fmt = "pack index is corrupt")]
+    IndexFormatError,
+    #[display(fmt = "unsupported pack index version {_0}")]
+    UnsupportedIndexVersion(u32),
+    #[display(fmt = "bad pack signature")]
+    BadSignature,
+    #[display(fmt = "unsupported pack version {_0}")]
+    UnsupportedVersion(u32),
+    #[display(fmt = "pack checksum mismatch")]
+    ChecksumMismatch,
+    #[display(fmt = "corrupt pack entry at offset {_0}")]
+    CorruptEntry(u64),
+    #[display(fmt = "delta data is corrupt")]
+    CorruptDelta,
+}
+
+/// the object types of pack entries, which are either whole objects or deltas
+fn object_type(typ: u8) -> Option<BlobType> {
+    match typ {
+        OBJ_COMMIT => Some(BlobType::Commit),
+        OBJ_TREE => Some(BlobType::Tree),
+        OBJ_BLOB => Some(BlobType::Blob),
+        OBJ_TAG => Some(BlobType::Tag),
+        _ => None,
+    }
+}
+
+/// the number of `typ` in the header of pack entries
+fn type_number(typ: BlobType) -> u8 {
+    match typ {
+        BlobType::Commit => OBJ_COMMIT,
+        BlobType::Tree => OBJ_TREE,
+        BlobType::Blob => OBJ_BLOB,
+        BlobType::Tag => OBJ_TAG,
+    }
+}
+
+/// an `.idx` file, which maps the hashes of the objects in a pack to their offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackIndex {
+    /// how many hashes start with a byte up to the index
+    fanout: Vec<u32>,
+    /// sorted
+    hashes: Vec<Hash>,
+    offsets: Vec<u64>,
+    /// the checksum of the pack this indexes
+    pack_checksum: Hash,
+}
+
+fn fanout(s: &[u8]) -> IResult<&[u8], Vec<u32>> {
+    count(be_u32, 256)(s)
+}
+
+fn hash(s: &[u8]) -> IResult<&[u8], Hash> {
+    let (s, raw) = take(HASH_LEN)(s)?;
+    Ok((s, Hash::from_raw(raw).expect("has the length of a hash")))
+}
+
+/// the fanout, hashes and offsets of an index
+type IndexTables = (Vec<u32>, Vec<Hash>, Vec<u64>);
+
+/// version 1: the fanout, then an offset and a hash for each object
+fn index_v1(s: &[u8]) -> IResult<&[u8], IndexTables> {
+    let (mut s, fanout) = fanout(s)?;
+    let n = fanout[255] as usize;
+    let mut hashes = Vec::with_capacity(n);
+    let mut offsets = Vec::with_capacity(n);
+    for _ in 0..n {
+        let (rest, offset) = be_u32(s)?;
+        let (rest, hash) = hash(rest)?;
+        offsets.push(offset as u64);
+        hashes.push(hash);
+        s = rest;
+    }
+    Ok((s, (fanout, hashes, offsets)))
+}
+
+/// version 2: the fanout, all hashes, all crc32s, all offsets, then the large offsets
+fn index_v2(s: &[u8]) -> IResult<&[u8], IndexTables> {
+    let (s, _) = tag(IDX_SIGNATURE)(s)?;
+    let (s, _) = tag(2u32.to_be_bytes())(s)?;
+    let (s, fanout) = fanout(s)?;
+    let n = fanout[255] as usize;
+    let (s, hashes) = count(hash, n)(s)?;
+    let (s, _crc32s) = take(n * 4)(s)?;
+    let (s, small) = count(be_u32, n)(s)?;
+    let large_count = small.iter().filter(|&&o| o & LARGE_OFFSET != 0).count();
+    let (s, large) = count(be_u64, large_count)(s)?;
+    let mut offsets = Vec::with_capacity(n);
+    for offset in small {
+        let offset = match offset & LARGE_OFFSET {
+            0 => offset as u64,
+            _ => *large
+                .get((offset & !LARGE_OFFSET) as usize)
+                .ok_or_else(|| {
+                    nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::Verify))
+                })?,
+        };
+        offsets.push(offset);
+    }
+    Ok((s, (fanout, hashes, offsets)))
+}
+
+impl TryFrom<&[u8]> for PackIndex {
+    type Error = PackError;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        if s.len() < 2 * HASH_LEN {
+            return Err(PackError::IndexFormatError);
+        }
+        let (content, checksum) = s.split_at(s.len() - HASH_LEN);
+        if Hash::from_bytes(content).as_bytes() != checksum {
+            return Err(PackError::ChecksumMismatch);
+        }
+        let (content, pack_checksum) = content.split_at(content.len() - HASH_LEN);
+
+        let parsed = match content.strip_prefix(IDX_SIGNATURE) {
+            Some(rest) => match rest.get(..4) {
+                Some([0, 0, 0, 2]) => index_v2(content),
+                Some(version) => {
+                    let version = u32::from_be_bytes(version.try_into().expect("4 bytes"));
+                    return Err(PackError::UnsupportedIndexVersion(version));
+                }
+                None => return Err(PackError::IndexFormatError),
+            },
+            None => index_v1(content),
+        };
+        let (rest, (fanout, hashes, offsets)) = parsed.map_err(|_| PackError::IndexFormatError)?;
+        if !rest.is_empty() || !hashes.windows(2).all(|w| w[0] < w[1]) {
+            return Err(PackError::IndexFormatError);
+        }
+        Ok(PackIndex {
+            fanout,
+            hashes,
+            offsets,
+            pack_checksum: Hash::from_raw(pack_checksum).expect("has the length of a hash"),
+        })
+    }
+}
+
+impl PackIndex {
+    /// the offset of the object in the pack
+    pub fn find(&self, hash: &Hash) -> Option<u64> {
+        let first = hash.as_bytes()[0] as usize;
+        let start = match first {
+            0 => 0,
+            _ => self.fanout[first - 1] as usize,
+        };
+        let end = self.fanout[first] as usize;
+        let i = self.hashes.get(start..end)?.binary_search(hash).ok()?;
+        Some(self.offsets[start + i])
+    }
+
+    /// the hashes of all objects in the pack, sorted
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    /// the hashes that start with the given lowercase hex digits
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Hash> {
+        let start = self
+            .hashes
+            .partition_point(|hash| hash.to_string().as_str() < prefix);
+        self.hashes[start..]
+            .iter()
+            .take_while(move |hash| hash.to_string().starts_with(prefix))
+    }
+}
+
+/// reads the variable length size that starts a delta
+fn delta_size(s: &mut &[u8]) -> Result<usize, PackError> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let (&c, rest) = s.split_first().ok_or(PackError::CorruptDelta)?;
+        *s = rest;
+        size |= ((c & 0x7f) as usize)
+            .checked_shl(shift)
+            .ok_or(PackError::CorruptDelta)?;
+        shift += 7;
+        if c & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// builds an object from its base and a delta of copy and insert instructions
+pub fn apply_delta(base: &[u8], mut delta: &[u8]) -> Result<Vec<u8>, PackError> {
+    if delta_size(&mut delta)? != base.len() {
+        return Err(PackError::CorruptDelta);
+    }
+    let size = delta_size(&mut delta)?;
+    let mut out = Vec::with_capacity(size);
+    while let Some((&op, rest)) = delta.split_first() {
+        delta = rest;
+        if op & 0x80 != 0 {
+            // copy from the base, the low bits say which bytes of offset and size follow
+            let mut field = |bits: u8, bytes: usize| -> Result<usize, PackError> {
+                let mut value = 0;
+                for i in 0..bytes {
+                    if bits & (1 << i) != 0 {
+                        let (&b, rest) = delta.split_first().ok_or(PackError::CorruptDelta)?;
+                        delta = rest;
+                        value |= (b as usize) << (8 * i);
+                    }
+                }
+                Ok(value)
+            };
+            let offset = field(op & 0x0f, 4)?;
+            let len = match field(op >> 4 & 0x07, 3)? {
+                0 => 0x10000,
+                len => len,
+            };
+            let copied = base
+                .get(offset..offset + len)
+                .ok_or(PackError::CorruptDelta)?;
+            out.extend_from_slice(copied);
+        } else if op != 0 {
+            let len = op as usize;
+            let inserted = delta.get(..len).ok_or(PackError::CorruptDelta)?;
+            out.extend_from_slice(inserted);
+            delta = &delta[len..];
+        } else {
+            return Err(PackError::CorruptDelta);
+        }
+    }
+    if out.len() != size {
+        return Err(PackError::CorruptDelta);
+    }
+    Ok(out)
+}
+
+/// what an entry of a pack holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EntryKind {
+    Whole(BlobType),
+    /// a delta against the entry at this offset
+    OfsDelta(u64),
+    /// a delta against the object with this hash
+    RefDelta(Hash),
+}
+
+/// a pack entry with its data still compressed
+struct Entry<'a> {
+    kind: EntryKind,
+    /// the size of the data once decompressed
+    size: usize,
+    /// the bytes before the compressed data
+    header_len: usize,
+    compressed: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// the entry at `offset` of `entries`, which are the entries of a pack without the trailer
+    fn parse(entries: &'a [u8], offset: u64) -> Result<Self, PackError> {
+        let corrupt = PackError::CorruptEntry(offset);
+        let mut s = entries.get(offset as usize..).ok_or(corrupt.clone())?;
+        let mut next = || -> Result<u8, PackError> {
+            let (&c, rest) = s.split_first().ok_or(corrupt.clone())?;
+            s = rest;
+            Ok(c)
+        };
+
+        // the type and the size of the data, which continues in the following bytes
+        let mut c = next()?;
+        let typ = c >> 4 & 0x07;
+        let mut size = (c & 0x0f) as usize;
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = next()?;
+            size |= ((c & 0x7f) as usize)
+                .checked_shl(shift)
+                .ok_or(corrupt.clone())?;
+            shift += 7;
+        }
+
+        let kind = match typ {
+            OBJ_OFS_DELTA => {
+                // the distance back to the base, with an offset added for every extra byte
+                let mut c = next()?;
+                let mut distance = (c & 0x7f) as u64;
+                while c & 0x80 != 0 {
+                    c = next()?;
+                    distance = ((distance + 1) << 7) | (c & 0x7f) as u64;
+                }
+                EntryKind::OfsDelta(offset.checked_sub(distance).ok_or(corrupt.clone())?)
+            }
+            OBJ_REF_DELTA => {
+                let raw = s.get(..HASH_LEN).ok_or(corrupt.clone())?;
+                s = &s[HASH_LEN..];
+                EntryKind::RefDelta(Hash::from_raw(raw).expect("has the length of a hash"))
+            }
+            typ => EntryKind::Whole(object_type(typ).ok_or(corrupt)?),
+        };
+        Ok(Entry {
+            kind,
+            size,
+            header_len: entries.len() - offset as usize - s.len(),
+            compressed: s,
+        })
+    }
+
+    /// decompresses the data of the entry at `offset`, which has to have the size of its header,
+    /// also returning how many bytes the whole entry takes up
+    fn inflate(&self, offset: u64) -> Result<(Vec<u8>, usize), PackError> {
+        let mut data = Vec::with_capacity(self.size);
+        let mut decoder = ZlibDecoder::new(self.compressed);
+        decoder
+            .read_to_end(&mut data)
+            .map_err(|_| PackError::CorruptEntry(offset))?;
+        if data.len() != self.size {
+            return Err(PackError::CorruptEntry(offset));
+        }
+        Ok((data, self.header_len + decoder.total_in() as usize))
+    }
+}
+
+/// the type and content of an object, shared with the delta base cache
+pub type RawObject = (BlobType, Arc<Vec<u8>>);
+
+/// recently resolved objects that deltas are likely to be based on, by offset
+#[derive(Debug, Default)]
+struct DeltaBaseCache {
+    objects: HashMap<u64, RawObject>,
+    /// oldest first, to evict them first
+    order: VecDeque<u64>,
+    bytes: usize,
+}
+
+impl DeltaBaseCache {
+    fn get(&self, offset: u64) -> Option<RawObject> {
+        self.objects.get(&offset).cloned()
+    }
+
+    fn insert(&mut self, offset: u64, object: RawObject) {
+        let len = object.1.len();
+        if len > DELTA_BASE_CACHE_LIMIT || self.objects.contains_key(&offset) {
+            return;
+        }
+        while self.bytes + len > DELTA_BASE_CACHE_LIMIT {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some((_, data)) = self.objects.remove(&oldest) {
+                self.bytes -= data.len();
+            }
+        }
+        self.bytes += len;
+        self.order.push_back(offset);
+        self.objects.insert(offset, object);
+    }
+}
+
+/// the number of entries of a pack, after checking its signature and version
+fn pack_header(data: &[u8]) -> Result<u32, PackError> {
+    let header = |s| -> IResult<&[u8], (u32, u32)> {
+        let (s, _) = tag(PACK_SIGNATURE)(s)?;
+        let (s, version) = be_u32(s)?;
+        let (s, count) = be_u32(s)?;
+        Ok((s, (version, count)))
+    };
+    let (_, (version, count)) = header(data).map_err(|_| PackError::BadSignature)?;
+    if version != 2 && version != 3 {
+        return Err(PackError::UnsupportedVersion(version));
+    }
+    Ok(count)
+}
+
+/// a `.pack` file with its index
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+    data: Vec<u8>,
+    /// the start of each entry, sorted, to know where entries end
+    starts: Vec<u64>,
+    cache: Mutex<DeltaBaseCache>,
+}
+
+impl Pack {
+    /// opens the pack of the index at `idx_path`
+    pub fn open(idx_path: &Path) -> anyhow::Result<Self> {
+        let idx = std::fs::read(idx_path)
+            .with_context(|| format!("cannot read {}", idx_path.display()))?;
+        let index = PackIndex::try_from(idx.as_slice())
+            .with_context(|| format!("bad pack index {}", idx_path.display()))?;
+        let path = idx_path.with_extension("pack");
+        let data =
+            std::fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+        Self::new(path, index, data)
+    }
+
+    fn new(path: PathBuf, index: PackIndex, data: Vec<u8>) -> anyhow::Result<Self> {
+        let count = pack_header(&data)?;
+        let checksum = data.len().checked_sub(HASH_LEN).map(|at| &data[at..]);
+        if count as usize != index.hashes.len() || checksum != Some(index.pack_checksum.as_bytes())
+        {
+            Err(PackError::ChecksumMismatch)
+                .with_context(|| format!("{} does not match its index", path.display()))?;
+        }
+        let mut starts = index.offsets.clone();
+        starts.sort_unstable();
+        Ok(Self {
+            path,
+            index,
+            data,
+            starts,
+            cache: Mutex::default(),
+        })
+    }
+
+    /// opens all packs in `dir`, which is usually `objects/pack`
+    pub fn load_all(dir: &Path) -> anyhow::Result<Vec<Self>> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => Err(e)?,
+        };
+        let mut packs = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "idx") && path.with_extension("pack").exists()
+            {
+                packs.push(Self::open(&path)?);
+            }
+        }
+        // like git, prefer the newest pack, which is most likely to have what is looked up
+        packs.sort_by_key(|pack| {
+            std::cmp::Reverse(pack.path.metadata().and_then(|m| m.modified()).ok())
+        });
+        Ok(packs)
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.index.find(hash).is_some()
+    }
+
+    /// how many bytes the entry of `hash` takes up in the pack
+    pub fn disk_size(&self, hash: &Hash) -> Option<u64> {
+        let offset = self.index.find(hash)?;
+        let i = self.starts.binary_search(&offset).ok()?;
+        let end = match self.starts.get(i + 1) {
+            Some(&next) => next,
+            None => (self.data.len() - HASH_LEN) as u64,
+        };
+        Some(end - offset)
+    }
+
+    /// the object the entry of `hash` is a delta against, `None` if it is stored whole or is not
+    /// in this pack
+    pub fn delta_base(&self, hash: &Hash) -> Result<Option<Hash>, PackError> {
+        let Some(offset) = self.index.find(hash) else {
+            return Ok(None);
+        };
+        Ok(match self.entry(offset)?.kind {
+            EntryKind::Whole(_) => None,
+            EntryKind::OfsDelta(base) => {
+                let i = self
+                    .index
+                    .offsets
+                    .iter()
+                    .position(|&start| start == base)
+                    .ok_or(PackError::CorruptEntry(offset))?;
+                Some(self.index.hashes[i].clone())
+            }
+            EntryKind::RefDelta(base) => Some(base),
+        })
+    }
+
+    fn entry(&self, offset: u64) -> Result<Entry<'_>, PackError> {
+        Entry::parse(&self.data[..self.data.len() - HASH_LEN], offset)
+    }
+
+    /// reads the object with the given hash, if it is in this pack.
+    ///
+    /// `external` reads the bases of deltas against objects that are not in the pack
+    pub fn read(
+        &self,
+        hash: &Hash,
+        external: &dyn Fn(&Hash) -> anyhow::Result<RawObject>,
+    ) -> anyhow::Result<Option<RawObject>> {
+        match self.index.find(hash) {
+            Some(offset) => Ok(Some(self.read_at(offset, external)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// reads the object at `offset`, following delta chains iteratively so that long chains
+    /// do not exhaust the stack
+    fn read_at(
+        &self,
+        offset: u64,
+        external: &dyn Fn(&Hash) -> anyhow::Result<RawObject>,
+    ) -> anyhow::Result<RawObject> {
+        let mut deltas = vec![];
+        let mut at = offset;
+        // the base at the end of the chain, with its offset if it is in this pack
+        let (mut object, base) = loop {
+            if let Some(cached) = self.cache.lock().expect("not poisoned").get(at) {
+                break (cached, None);
+            }
+            let entry = self.entry(at)?;
+            let (data, _) = entry.inflate(at)?;
+            match entry.kind {
+                EntryKind::Whole(typ) => break ((typ, Arc::new(data)), Some(at)),
+                EntryKind::OfsDelta(base) => {
+                    deltas.push((at, data));
+                    at = base;
+                }
+                EntryKind::RefDelta(base) => {
+                    deltas.push((at, data));
+                    match self.index.find(&base) {
+                        Some(base) => at = base,
+                        None => break (external(&base)?, None),
+                    }
+                }
+            }
+            if deltas.len() > self.index.hashes.len() {
+                Err(PackError::CorruptEntry(offset)).context("delta chain loops")?;
+            }
+        };
+
+        let mut cache = self.cache.lock().expect("not poisoned");
+        // every base in a chain is likely to be the base of other objects too
+        if let (Some(base), false) = (base, deltas.is_empty()) {
+            cache.insert(base, object.clone());
+        }
+        for (at, delta) in deltas.into_iter().rev() {
+            let data = apply_delta(&object.1, &delta)
+                .with_context(|| format!("cannot apply the delta at offset {at}"))?;
+            object = (object.0, Arc::new(data));
+            if at != offset {
+                cache.insert(at, object.clone());
+            }
+        }
+        Ok(object)
+    }
+}
+
+/// the blocks of a delta base that are looked up in targets, like git's `RABIN_WINDOW`
+const DELTA_BLOCK: usize = 16;
+/// the most a single copy instruction copies, which older readers need
+const MAX_COPY: usize = 0x10000;
+/// the most positions remembered for a block of the base that occurs many times
+const MAX_BLOCK_POSITIONS: usize = 64;
+
+/// the positions of the blocks of a base, to find where targets match it
+pub struct DeltaIndex<'a> {
+    base: &'a [u8],
+    blocks: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> DeltaIndex<'a> {
+    pub fn new(base: &'a [u8]) -> Self {
+        let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        for (i, block) in base.chunks_exact(DELTA_BLOCK).enumerate() {
+            let positions = blocks.entry(block).or_default();
+            if positions.len() < MAX_BLOCK_POSITIONS {
+                positions.push(i * DELTA_BLOCK);
+            }
+        }
+        Self { base, blocks }
+    }
+
+    /// the longest match of the start of `target` in the base
+    fn longest_match(&self, target: &[u8]) -> Option<(usize, usize)> {
+        let positions = self.blocks.get(target.get(..DELTA_BLOCK)?)?;
+        positions
+            .iter()
+            .map(|&pos| {
+                let len = self.base[pos..]
+                    .iter()
+                    .zip(target)
+                    .take(MAX_COPY)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (pos, len)
+            })
+            .max_by_key(|&(_, len)| len)
+    }
+
+    /// a delta that turns the base into `target`, `None` if it would be larger than `max_size`
+    pub fn delta(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let mut out = vec![];
+        write_delta_size(&mut out, self.base.len());
+        write_delta_size(&mut out, target.len());
+
+        let mut insert_start = 0;
+        let mut i = 0;
+        while i < target.len() {
+            let Some((mut offset, mut len)) = self.longest_match(&target[i..]) else {
+                i += 1;
+                continue;
+            };
+            // the match may start in the bytes that were going to be inserted
+            while i > insert_start
+                && offset > 0
+                && len < MAX_COPY
+                && self.base[offset - 1] == target[i - 1]
+            {
+                offset -= 1;
+                i -= 1;
+                len += 1;
+            }
+            write_insert(&mut out, &target[insert_start..i]);
+            write_copy(&mut out, offset, len);
+            i += len;
+            insert_start = i;
+            if out.len() > max_size {
+                return None;
+            }
+        }
+        write_insert(&mut out, &target[insert_start..]);
+        (out.len() <= max_size).then_some(out)
+    }
+}
+
+fn write_delta_size(out: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        out.push(size as u8 | 0x80);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+fn write_insert(out: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(0x7f) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(out: &mut Vec<u8>, offset: usize, len: usize) {
+    let op = out.len();
+    out.push(0x80);
+    for i in 0..4 {
+        let byte = (offset >> (8 * i)) as u8;
+        if byte != 0 {
+            out[op] |= 1 << i;
+            out.push(byte);
+        }
+    }
+    // a size of 0x10000 is written as no size at all
+    for i in 0..3 {
+        let byte = (len >> (8 * i)) as u8;
+        if len != MAX_COPY && byte != 0 {
+            out[op] |= 0x10 << i;
+            out.push(byte);
+        }
+    }
+}
+
+/// git's hash of the path of an object, which sorts objects with similar names next to each
+/// other for finding deltas, favoring the last characters
+fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0, |hash: u32, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// how `pack-objects` looks for deltas and writes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackOptions {
+    /// how many of the preceding objects are tried as the base of a delta
+    pub window: usize,
+    /// the longest chain of deltas
+    pub depth: usize,
+    /// whether deltas refer to their base by offset instead of by hash
+    pub ofs_delta: bool,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            window: 10,
+            depth: 50,
+            ofs_delta: false,
+        }
+    }
+}
+
+/// an object to be written to a pack
+struct ToPack {
+    hash: Hash,
+    typ: BlobType,
+    data: Arc<Vec<u8>>,
+    name_hash: u32,
+    /// the object this is a delta against, and the delta
+    delta: Option<(usize, Vec<u8>)>,
+    depth: usize,
+}
+
+/// a pack and its index as written by [`write_pack`]
+pub struct PackFile {
+    pub pack: Vec<u8>,
+    pub index: Vec<u8>,
+    /// the trailer of the pack, which names it
+    pub checksum: Hash,
+}
+
+/// finds deltas between the objects like git does: sorted by type, name and size, each object
+/// is tried against a window of the ones before it
+fn find_deltas(objects: &mut [ToPack], options: PackOptions) {
+    let mut order: Vec<usize> = (0..objects.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&objects[a], &objects[b]);
+        (b.typ, b.name_hash, b.data.len()).cmp(&(a.typ, a.name_hash, a.data.len()))
+    });
+
+    let mut window: VecDeque<usize> = VecDeque::new();
+    for &target in &order {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for &base in window.iter().rev() {
+            let (base_obj, target_obj) = (&objects[base], &objects[target]);
+            if base_obj.typ != target_obj.typ || base_obj.depth >= options.depth {
+                continue;
+            }
+            let (base_size, target_size) = (base_obj.data.len(), target_obj.data.len());
+            // deltas only pay off when they are much smaller than the object, more so the
+            // longer the chain gets
+            let (max_size, ref_depth) = match &best {
+                Some((best, delta)) => (delta.len(), objects[*best].depth + 1),
+                None => ((target_size / 2).saturating_sub(HASH_LEN), 1),
+            };
+            let max_size =
+                max_size * (options.depth - base_obj.depth) / (options.depth - ref_depth + 1);
+            if max_size == 0
+                || target_size.saturating_sub(base_size) >= max_size
+                || target_size < base_size / 32
+            {
+                continue;
+            }
+            let index = DeltaIndex::new(&base_obj.data);
+            if let Some(delta) = index.delta(&target_obj.data, max_size) {
+                best = Some((base, delta));
+            }
+        }
+        if let Some((base, delta)) = best {
+            objects[target].depth = objects[base].depth + 1;
+            objects[target].delta = Some((base, delta));
+        }
+
+        window.push_back(target);
+        if window.len() > options.window {
+            window.pop_front();
+        }
+    }
+}
+
+fn compress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// the header of a pack entry: its type and the size of its data
+fn write_entry_header(out: &mut Vec<u8>, typ: u8, mut size: usize) {
+    let mut c = typ << 4 | (size & 0x0f) as u8;
+    size >>= 4;
+    while size != 0 {
+        out.push(c | 0x80);
+        c = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(c);
+}
+
+/// the distance back to the base of an `OFS_DELTA`, with an offset removed for every extra byte
+fn write_ofs_distance(out: &mut Vec<u8>, mut distance: u64) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// an index of version 2 for a pack, from the hash, offset and crc32 of each entry
+pub fn write_index(entries: &mut [(Hash, u64, u32)], pack_checksum: &Hash) -> Vec<u8> {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut out = vec![];
+    out.extend_from_slice(IDX_SIGNATURE);
+    out.extend_from_slice(&2u32.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (hash, _, _) in entries.iter() {
+        fanout[hash.as_bytes()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        out.extend_from_slice(&total.to_be_bytes());
+    }
+    for (hash, _, _) in entries.iter() {
+        out.extend_from_slice(hash.as_bytes());
+    }
+    for (_, _, crc) in entries.iter() {
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    let mut large = vec![];
+    for &(_, offset, _) in entries.iter() {
+        let offset = match u32::try_from(offset) {
+            Ok(offset) if offset & LARGE_OFFSET == 0 => offset,
+            _ => {
+                large.push(offset);
+                LARGE_OFFSET | (large.len() - 1) as u32
+            }
+        };
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+    for offset in large {
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+    out.extend_from_slice(pack_checksum.as_bytes());
+    let checksum = Hash::from_bytes(&out);
+    out.extend_from_slice(checksum.as_bytes());
+    out
+}
+
+/// writes the objects to a pack of version 2 in the given order, with deltas between them
+pub fn write_pack(
+    repo: &Repository,
+    objects: &[(Hash, String)],
+    options: PackOptions,
+) -> anyhow::Result<PackFile> {
+    let mut seen = HashSet::new();
+    let mut to_pack = vec![];
+    for (hash, name) in objects {
+        if !seen.insert(hash) {
+            continue;
+        }
+        let (typ, data) = repo.read_raw(hash)?;
+        to_pack.push(ToPack {
+            hash: hash.clone(),
+            typ,
+            data,
+            name_hash: name_hash(name),
+            delta: None,
+            depth: 0,
+        });
+    }
+    if options.window > 0 && options.depth > 0 {
+        find_deltas(&mut to_pack, options);
+    }
+
+    let mut pack = vec![];
+    pack.extend_from_slice(PACK_SIGNATURE);
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(to_pack.len() as u32).to_be_bytes());
+
+    // bases are written before their deltas so that those can refer back to them
+    let mut offsets: Vec<Option<u64>> = vec![None; to_pack.len()];
+    let mut entries = Vec::with_capacity(to_pack.len());
+    for i in 0..to_pack.len() {
+        let mut chain = vec![i];
+        while let Some((base, _)) = &to_pack[*chain.last().expect("not empty")].delta {
+            chain.push(*base);
+        }
+        for &j in chain.iter().rev() {
+            if offsets[j].is_some() {
+                continue;
+            }
+            let object = &to_pack[j];
+            let offset = pack.len() as u64;
+            let mut entry = vec![];
+            let data = match &object.delta {
+                Some((base, delta)) if options.ofs_delta => {
+                    write_entry_header(&mut entry, OBJ_OFS_DELTA, delta.len());
+                    let base = offsets[*base].expect("bases are written first");
+                    write_ofs_distance(&mut entry, offset - base);
+                    delta
+                }
+                Some((base, delta)) => {
+                    write_entry_header(&mut entry, OBJ_REF_DELTA, delta.len());
+                    entry.extend_from_slice(to_pack[*base].hash.as_bytes());
+                    delta
+                }
+                None => {
+                    write_entry_header(&mut entry, type_number(object.typ), object.data.len());
+                    object.data.as_slice()
+                }
+            };
+            entry.extend_from_slice(&compress(data)?);
+            let mut crc = Crc::new();
+            crc.update(&entry);
+            entries.push((object.hash.clone(), offset, crc.sum()));
+            offsets[j] = Some(offset);
+            pack.extend_from_slice(&entry);
+        }
+    }
+    let checksum = Hash::from_bytes(&pack);
+    pack.extend_from_slice(checksum.as_bytes());
+    let index = write_index(&mut entries, &checksum);
+    Ok(PackFile {
+        pack,
+        index,
+        checksum,
+    })
+}
+
+/// the hash of an object with the given type and content
+fn object_hash((typ, data): &RawObject) -> Hash {
+    let mut raw = format!("{typ} {}\0", data.len()).into_bytes();
+    raw.extend_from_slice(data);
+    Hash::from_bytes(&raw)
+}
+
+/// an entry found by reading a pack from its start
+struct Scanned {
+    offset: u64,
+    kind: EntryKind,
+    /// the decompressed data, taken once the entry is resolved
+    data: Vec<u8>,
+    crc: u32,
+}
+
+/// reads a pack received from elsewhere from start to end, checking it and resolving its
+/// deltas, and indexes it. `found` is called with every object of the pack.
+///
+/// the bases of deltas that are not in the pack are read from `thin_bases` and appended to it,
+/// which completes a thin pack
+pub fn read_pack(
+    mut pack: Vec<u8>,
+    thin_bases: Option<&Repository>,
+    mut found: impl FnMut(&Hash, &RawObject) -> anyhow::Result<()>,
+) -> anyhow::Result<PackFile> {
+    let count = pack_header(&pack)?;
+    let content_len = pack
+        .len()
+        .checked_sub(HASH_LEN)
+        .ok_or(PackError::BadSignature)?;
+    let (content, trailer) = pack.split_at(content_len);
+    if Hash::from_bytes(content).as_bytes() != trailer {
+        Err(PackError::ChecksumMismatch)?;
+    }
+
+    let mut scanned = vec![];
+    let mut offset = PACK_HEADER_LEN as u64;
+    for _ in 0..count {
+        let entry = Entry::parse(content, offset)?;
+        let (data, len) = entry.inflate(offset)?;
+        let mut crc = Crc::new();
+        crc.update(&content[offset as usize..offset as usize + len]);
+        scanned.push(Scanned {
+            offset,
+            kind: entry.kind,
+            data,
+            crc: crc.sum(),
+        });
+        offset += len as u64;
+    }
+    if offset != content_len as u64 {
+        bail!("pack has junk at the end");
+    }
+
+    // deltas are resolved once their base is, starting from the whole objects
+    let by_offset: HashMap<u64, usize> = scanned
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.offset, i))
+        .collect();
+    let mut ofs_children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut ref_children: HashMap<Hash, Vec<usize>> = HashMap::new();
+    let mut stack: Vec<(usize, RawObject)> = vec![];
+    for (i, entry) in scanned.iter_mut().enumerate() {
+        match &entry.kind {
+            EntryKind::Whole(typ) => {
+                stack.push((i, (*typ, Arc::new(std::mem::take(&mut entry.data)))));
+            }
+            EntryKind::OfsDelta(base) => {
+                let base = by_offset
+                    .get(base)
+                    .ok_or(PackError::CorruptEntry(entry.offset))?;
+                ofs_children.entry(*base).or_default().push(i);
+            }
+            EntryKind::RefDelta(base) => ref_children.entry(base.clone()).or_default().push(i),
+        }
+    }
+
+    let mut hashes: Vec<Option<Hash>> = vec![None; scanned.len()];
+    let mut appended = vec![];
+    loop {
+        while let Some((i, object)) = stack.pop() {
+            let hash = object_hash(&object);
+            found(&hash, &object)?;
+            let children = ofs_children.remove(&i).into_iter().flatten();
+            for child in children.chain(ref_children.remove(&hash).into_iter().flatten()) {
+                let delta = std::mem::take(&mut scanned[child].data);
+                let data = apply_delta(&object.1, &delta).with_context(|| {
+                    format!("cannot apply the delta at offset {}", scanned[child].offset)
+                })?;
+                stack.push((child, (object.0, Arc::new(data))));
+            }
+            hashes[i] = Some(hash);
+        }
+
+        // what is left are deltas against objects that are not in the pack
+        let Some(base) = ref_children.keys().min().cloned() else {
+            break;
+        };
+        let Some(repo) = thin_bases else {
+            let unresolved: usize = ref_children.values().map(Vec::len).sum();
+            bail!("pack has {unresolved} unresolved deltas");
+        };
+        let object = repo.read_raw(&base)?;
+        for child in ref_children.remove(&base).into_iter().flatten() {
+            let delta = std::mem::take(&mut scanned[child].data);
+            let data = apply_delta(&object.1, &delta)?;
+            stack.push((child, (object.0, Arc::new(data))));
+        }
+        appended.push((base, object));
+    }
+
+    let mut entries = vec![];
+    for (entry, hash) in scanned.iter().zip(hashes) {
+        let hash = hash.ok_or(PackError::CorruptEntry(entry.offset))?;
+        entries.push((hash, entry.offset, entry.crc));
+    }
+    if !appended.is_empty() {
+        pack.truncate(content_len);
+        for (hash, (typ, data)) in appended {
+            let offset = pack.len() as u64;
+            let mut entry = vec![];
+            write_entry_header(&mut entry, type_number(typ), data.len());
+            entry.extend_from_slice(&compress(&data)?);
+            let mut crc = Crc::new();
+            crc.update(&entry);
+            entries.push((hash, offset, crc.sum()));
+            pack.extend_from_slice(&entry);
+        }
+        pack[8..PACK_HEADER_LEN].copy_from_slice(&(entries.len() as u32).to_be_bytes());
+        let checksum = Hash::from_bytes(&pack);
+        pack.extend_from_slice(checksum.as_bytes());
+    }
+
+    let checksum =
+        Hash::from_raw(&pack[pack.len() - HASH_LEN..]).expect("has the length of a hash");
+    let index = write_index(&mut entries, &checksum);
+    Ok(PackFile {
+        pack,
+        index,
+        checksum,
+    })
+}
+
+/// writes a file that is never changed afterwards, replacing any earlier one
+fn write_read_only(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    std::fs::remove_file(path).ignore(std::io::ErrorKind::NotFound, ())?;
+    std::fs::write(path, data).with_context(|| format!("cannot write {}", path.display()))?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o444))?;
+    Ok(())
+}
+
+/// the `pack-objects` command
+#[derive(Debug)]
+pub struct PackObjects {
+    pub options: PackOptions,
+    /// whether the input is revisions, whose reachable objects are packed
+    pub revs: bool,
+    /// packs everything reachable from refs and HEAD, with `revs`
+    pub all: bool,
+}
+
+impl PackObjects {
+    /// the objects named by the input, one per line and optionally followed by a path
+    fn objects<R: BufRead>(&self, input: R) -> anyhow::Result<Vec<(Hash, String)>> {
+        let mut objects = vec![];
+        for line in input.lines() {
+            let line = line?;
+            let (hash, name) = line.split_once(' ').unwrap_or((&line, ""));
+            let hash = hash
+                .parse()
+                .with_context(|| format!("expected object ID, got garbage:\n {line}"))?;
+            objects.push((hash, name.to_owned()));
+        }
+        Ok(objects)
+    }
+
+    /// the objects reachable from the revisions of the input, `^` and `--not` exclude
+    fn reachable<R: BufRead>(
+        &self,
+        repo: &Repository,
+        input: R,
+    ) -> anyhow::Result<Vec<(Hash, String)>> {
+        let (mut include, mut exclude) = (vec![], vec![]);
+        if self.all {
+            include.extend(revision::all_tips(repo)?);
+        }
+        let mut not = false;
+        for line in input.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            if line == "--not" {
+                not = !not;
+                continue;
+            }
+            let (negated, rev) = match line.strip_prefix('^') {
+                Some(rev) => (!not, rev),
+                None => (not, line.as_str()),
+            };
+            let Some(hash) = refs::resolve_object(repo, rev)? else {
+                bail!("bad revision '{rev}'");
+            };
+            match negated {
+                true => exclude.push(hash),
+                false => include.push(hash),
+            }
+        }
+        let found = walk::objects(repo, &include, &exclude)?;
+        Ok(found
+            .into_iter()
+            .map(|found| (found.hash, found.path))
+            .collect())
+    }
+
+    /// packs the objects read from `input`, writing the pack to stdout without a `base_name`
+    /// and to `<base_name>-<checksum>.pack` with its index otherwise
+    pub fn run<R: BufRead>(
+        &self,
+        repo: &Repository,
+        input: R,
+        base_name: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let objects = match self.revs {
+            true => self.reachable(repo, input)?,
+            false => self.objects(input)?,
+        };
+        let pack = write_pack(repo, &objects, self.options)?;
+        let Some(base_name) = base_name else {
+            std::io::stdout().lock().write_all(&pack.pack)?;
+            return Ok(());
+        };
+        let base = format!("{}-{}", base_name.display(), pack.checksum);
+        for (extension, data) in [("pack", &pack.pack), ("idx", &pack.index)] {
+            write_read_only(Path::new(&format!("{base}.{extension}")), data)?;
+        }
+        println!("{}", pack.checksum);
+        Ok(())
+    }
+}
+
+/// the `index-pack` command
+#[derive(Debug)]
+pub struct IndexPack {
+    /// where the index is written instead of next to the pack
+    pub output: Option<PathBuf>,
+    /// whether bases missing from a pack read from stdin are appended to it
+    pub fix_thin: bool,
+}
+
+impl IndexPack {
+    /// indexes the pack file at `pack`
+    pub fn run(&self, pack: &Path) -> anyhow::Result<()> {
+        if pack.extension().is_none_or(|e| e != "pack") {
+            bail!(
+                "packfile name '{}' does not end with '.pack'",
+                pack.display()
+            );
+        }
+        let data = std::fs::read(pack)
+            .with_context(|| format!("cannot open packfile '{}'", pack.display()))?;
+        let indexed = read_pack(data, None, |_, _| Ok(()))?;
+        let index = self.output.clone().unwrap_or(pack.with_extension("idx"));
+        write_read_only(&index, &indexed.index)?;
+        println!("{}", indexed.checksum);
+        Ok(())
+    }
+
+    /// stores the pack read from `input` in the repository, or at `pack` if given, and indexes it
+    pub fn run_stdin<R: Read>(
+        &self,
+        repo: &Repository,
+        mut input: R,
+        pack: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+        let indexed = read_pack(data, self.fix_thin.then_some(repo), |_, _| Ok(()))?;
+        let pack = match pack {
+            Some(pack) => pack.to_owned(),
+            None => repo
+                .git_dir()
+                .join(format!("objects/pack/pack-{}.pack", indexed.checksum)),
+        };
+        let index = self.output.clone().unwrap_or(pack.with_extension("idx"));
+        write_read_only(&pack, &indexed.pack)?;
+        write_read_only(&index, &indexed.index)?;
+        println!("pack\t{}", indexed.checksum);
+        Ok(())
+    }
+}
+
+/// writes every object of the pack read from `input` as a loose object, except those that
+/// exist already, like `unpack-objects`
+pub fn unpack_objects<R: Read>(
+    repo: &Repository,
+    mut input: R,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+    read_pack(data, Some(repo), |hash, (typ, data)| {
+        if !dry_run && !repo.has_object(hash)? {
+            HashObject::literally(&typ.to_string(), data).write(repo)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas() {
+        let base = b"hello, world\n".repeat(10);
+        // base size 130, result size 17: copy 12 bytes from 0, insert "abcde"
+        let delta = [130, 1, 17, 0x90, 12, 5, b'a', b'b', b'c', b'd', b'e'];
+        assert_eq!(apply_delta(&base, &delta).unwrap(), b"hello, worldabcde");
+
+        // a copy size of 0 means 0x10000
+        let big = vec![7; 0x10000];
+        let delta = [0x80, 0x80, 0x04, 0x80, 0x80, 0x04, 0x80];
+        assert_eq!(apply_delta(&big, &delta).unwrap(), big);
+
+        assert!(apply_delta(&base, &[1, 1, 0x01]).is_err());
+        assert!(apply_delta(&base, &[130, 1, 5, 0x91, 200, 5]).is_err());
+        assert!(apply_delta(&base, &[130, 1, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn delta_roundtrip() {
+        let base: Vec<u8> = (0..5000u32)
+            .flat_map(|n| format!("line {n}\n").into_bytes())
+            .collect();
+        let mut target = base.clone();
+        target.splice(100..200, b"something else".iter().copied());
+        target.extend_from_slice(&base[..MAX_COPY.min(base.len())]);
+        let delta = DeltaIndex::new(&base).delta(&target, usize::MAX).unwrap();
+        assert!(delta.len() < 100);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        // nothing in common
+        let delta = DeltaIndex::new(b"abc").delta(b"xyz", usize::MAX).unwrap();
+        assert_eq!(apply_delta(b"abc", &delta).unwrap(), b"xyz");
+        assert_eq!(DeltaIndex::new(&base).delta(&target, 10), None);
+    }
+
+    #[test]
+    fn entry_headers() {
+        let mut out = vec![];
+        write_entry_header(&mut out, OBJ_BLOB, 1000);
+        write_ofs_distance(&mut out, 300);
+        assert_eq!(out, [0xb8, 0x3e, 0x81, 0x2c]);
+    }
+
+    #[test]
+    fn index_lookup() {
+        let hashes: Vec<Hash> = (0..3u8)
+            .map(|i| Hash::from_raw(&[i * 100; 20]).unwrap())
+            .collect();
+        let mut content = vec![];
+        let mut fanout = [0u32; 256];
+        for hash in &hashes {
+            for count in &mut fanout[hash.as_bytes()[0] as usize..] {
+                *count += 1;
+            }
+        }
+        // version 1 has no header
+        for count in fanout {
+            content.extend_from_slice(&count.to_be_bytes());
+        }
+        for (i, hash) in hashes.iter().enumerate() {
+            content.extend_from_slice(&(12 + i as u32 * 100).to_be_bytes());
+            content.extend_from_slice(hash.as_bytes());
+        }
+        content.extend_from_slice(&[0; HASH_LEN]);
+        content.extend_from_slice(Hash::from_bytes(&content).as_bytes());
+
+        let index = PackIndex::try_from(content.as_slice()).unwrap();
+        assert_eq!(index.find(&hashes[1]), Some(112));
+        assert_eq!(index.find(&Hash::from_raw(&[1; 20]).unwrap()), None);
+
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        assert!(PackIndex::try_from(content.as_slice()).is_err());
+    }
+}
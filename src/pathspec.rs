@@ -0,0 +1,159 @@
+/// matches `s` against a shell glob with `*`, `?`, `[...]` and `\` escapes.
+///
+/// with `pathname`, wildcards do not match `/` except for `**`, as in `.gitignore` files
+pub fn wildmatch(pattern: &[u8], s: &[u8], pathname: bool) -> bool {
+    match pattern.first() {
+        None => s.is_empty(),
+        Some(b'*') if pathname && pattern.starts_with(b"**") => {
+            let rest = &pattern[2..];
+            // `**/` also matches no directory at all
+            if let Some(after) = rest.strip_prefix(b"/") {
+                if wildmatch(after, s, pathname) {
+                    return true;
+                }
+            }
+            (0..=s.len()).any(|i| wildmatch(rest, &s[i..], pathname))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let limit = match pathname {
+                true => s.iter().position(|&c| c == b'/').unwrap_or(s.len()),
+                false => s.len(),
+            };
+            (0..=limit).any(|i| wildmatch(rest, &s[i..], pathname))
+        }
+        Some(b'?') => match s.first() {
+            Some(b'/') if pathname => false,
+            Some(_) => wildmatch(&pattern[1..], &s[1..], pathname),
+            None => false,
+        },
+        Some(b'[') => {
+            let Some((&c, s_rest)) = s.split_first() else {
+                return false;
+            };
+            match class(&pattern[1..], c) {
+                Some((rest, matched)) => {
+                    matched && !(pathname && c == b'/') && wildmatch(rest, s_rest, pathname)
+                }
+                // an unterminated class is a literal `[`
+                None => c == b'[' && wildmatch(&pattern[1..], s_rest, pathname),
+            }
+        }
+        Some(b'\\') if pattern.len() > 1 => {
+            s.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &s[1..], pathname)
+        }
+        Some(&p) => s.first() == Some(&p) && wildmatch(&pattern[1..], &s[1..], pathname),
+    }
+}
+
+/// matches `c` against the body of a `[...]` class, returning the rest of the pattern
+fn class(pattern: &[u8], c: u8) -> Option<(&[u8], bool)> {
+    let (negated, mut p) = match pattern.first() {
+        Some(b'!' | b'^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match p {
+            [] => return None,
+            [b']', rest @ ..] if !first => return Some((rest, matched != negated)),
+            [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+                matched |= (*lo..=*hi).contains(&c);
+                p = rest;
+            }
+            [ch, rest @ ..] => {
+                matched |= *ch == c;
+                p = rest;
+            }
+        }
+        first = false;
+    }
+}
+
+/// paths given on the command line, limiting a command to parts of the tree
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    /// normalized to have no leading `./` or trailing `/`, the whole tree is empty
+    specs: Vec<Vec<u8>>,
+}
+
+impl Pathspec {
+    pub fn new<S: AsRef<str>>(specs: &[S]) -> Self {
+        let specs = specs
+            .iter()
+            .map(|spec| {
+                let mut spec = spec.as_ref();
+                while let Some(rest) = spec.strip_prefix("./") {
+                    spec = rest;
+                }
+                let spec = spec.trim_end_matches('/');
+                let spec = if spec == "." { "" } else { spec };
+                spec.as_bytes().to_vec()
+            })
+            .collect();
+        Self { specs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    pub fn spec(&self, i: usize) -> &[u8] {
+        &self.specs[i]
+    }
+
+    /// whether the `i`th spec matches `path`, as the path itself, a leading directory or a glob
+    pub fn spec_matches(&self, i: usize, path: &[u8]) -> bool {
+        let spec = self.specs[i].as_slice();
+        spec.is_empty()
+            || path == spec
+            || (path.starts_with(spec) && path.get(spec.len()) == Some(&b'/'))
+            || wildmatch(spec, path, false)
+    }
+
+    /// the index of the first spec matching `path`
+    pub fn matching(&self, path: &[u8]) -> Option<usize> {
+        (0..self.specs.len()).find(|&i| self.spec_matches(i, path))
+    }
+
+    /// whether `path` is matched, an empty pathspec matches everything
+    pub fn matches(&self, path: &[u8]) -> bool {
+        self.is_empty() || self.matching(path).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(wildmatch(b"*.o", b"main.o", true));
+        assert!(!wildmatch(b"*.o", b"dir/main.o", true));
+        assert!(wildmatch(b"*.o", b"dir/main.o", false));
+        assert!(wildmatch(b"**/foo", b"foo", true));
+        assert!(wildmatch(b"**/foo", b"a/b/foo", true));
+        assert!(wildmatch(b"a/**/b", b"a/b", true));
+        assert!(wildmatch(b"a/**/b", b"a/x/y/b", true));
+        assert!(wildmatch(b"a/**", b"a/x/y", true));
+        assert!(wildmatch(b"[a-c]?[!x]", b"bzy", true));
+        assert!(!wildmatch(b"[a-c]?[!x]", b"bzx", true));
+        assert!(wildmatch(b"\\*", b"*", true));
+        assert!(!wildmatch(b"\\*", b"a", true));
+    }
+
+    #[test]
+    fn pathspecs() {
+        let spec = Pathspec::new(&["./dir/", "*.rs"]);
+        assert_eq!(spec.matching(b"dir"), Some(0));
+        assert_eq!(spec.matching(b"dir/file"), Some(0));
+        assert_eq!(spec.matching(b"directory"), None);
+        assert_eq!(spec.matching(b"src/main.rs"), Some(1));
+        assert!(Pathspec::new(&["."]).matches(b"anything"));
+    }
+}
@@ -0,0 +1,746 @@
+use std::{
+    fs::File,
+    io::{BufRead, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context};
+use walkdir::WalkDir;
+
+use crate::{
+    hash::Hash,
+    ident::{self, Role},
+    object::{Event, Object},
+    repository::Repository,
+    BlobType, IoErrorExt, Writeable,
+};
+
+/// symbolic refs are followed at most this many times
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// the order in which a short name is looked up, as in `git rev-parse`
+const DWIM_RULES: &[&str] = &[
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// the content of a ref file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    Direct(Hash),
+    Symbolic(String),
+}
+
+/// what a ref must point to for an update to go through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expect {
+    Any,
+    Missing,
+    Value(Hash),
+}
+
+impl Expect {
+    /// parses the `<old-value>` of `update-ref`, where an empty or all-zero value means missing
+    pub fn parse(repo: &Repository, s: &str) -> anyhow::Result<Self> {
+        Ok(match new_value(repo, s)? {
+            Some(hash) => Expect::Value(hash),
+            None => Expect::Missing,
+        })
+    }
+}
+
+/// parses the `<new-value>` of `update-ref`, where an empty or all-zero value means deletion
+pub fn new_value(repo: &Repository, s: &str) -> anyhow::Result<Option<Hash>> {
+    if s.is_empty() || (s.len() == 40 && s.bytes().all(|c| c == b'0')) {
+        return Ok(None);
+    }
+    let hash = resolve_object(repo, s)?.with_context(|| format!("{s}: not a valid SHA1"))?;
+    Ok(Some(hash))
+}
+
+/// checks a ref name against the rules of `git check-ref-format`
+pub fn valid_ref_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "@"
+        && !name.ends_with('/')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.chars().any(|c| {
+            c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+        })
+        && name
+            .split('/')
+            .all(|c| !c.is_empty() && !c.starts_with('.') && !c.ends_with(".lock"))
+}
+
+/// refs live under `refs/`, only all-caps names like `HEAD` may be outside of it
+fn check_name(name: &str) -> anyhow::Result<()> {
+    let pseudo = !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+    if !valid_ref_name(name) || !(name.starts_with("refs/") || pseudo) {
+        bail!("refusing to update ref with bad name '{name}'");
+    }
+    Ok(())
+}
+
+fn path(repo: &Repository, name: &str) -> PathBuf {
+    repo.git_dir().join(name)
+}
+
+/// reads a ref without following it, preferring the loose ref over a packed one
+pub fn read(repo: &Repository, name: &str) -> anyhow::Result<Option<RefValue>> {
+    match read_loose(repo, name)? {
+        Some(value) => Ok(Some(value)),
+        None => Ok(repo
+            .packed_refs()?
+            .get(name)
+            .map(|r| RefValue::Direct(r.hash.clone()))),
+    }
+}
+
+fn read_loose(repo: &Repository, name: &str) -> anyhow::Result<Option<RefValue>> {
+    let path = path(repo, name);
+    if path.is_dir() {
+        return Ok(None);
+    }
+    let Some(content) = std::fs::read_to_string(&path)
+        .map(Some)
+        .ignore(ErrorKind::NotFound, None)?
+    else {
+        return Ok(None);
+    };
+    let content = content.trim_end();
+    let value = match content.strip_prefix("ref: ") {
+        Some(target) => RefValue::Symbolic(target.trim_start().to_owned()),
+        None => RefValue::Direct(
+            content
+                .parse()
+                .with_context(|| format!("bad ref {name}: {content:?}"))?,
+        ),
+    };
+    Ok(Some(value))
+}
+
+/// follows symbolic refs from `name`, returning the name of the last one and its value
+pub fn follow(repo: &Repository, name: &str) -> anyhow::Result<(String, Option<Hash>)> {
+    let mut name = name.to_owned();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read(repo, &name)? {
+            Some(RefValue::Symbolic(target)) => name = target,
+            Some(RefValue::Direct(hash)) => return Ok((name, Some(hash))),
+            None => return Ok((name, None)),
+        }
+    }
+    bail!("symbolic ref loop at {name}")
+}
+
+/// the short form of a full ref name, like `main` for `refs/heads/main`
+pub fn shorten(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+/// the old and new values of a ref's updates from its reflog, oldest first
+pub fn reflog(repo: &Repository, name: &str) -> anyhow::Result<Vec<(Hash, Hash)>> {
+    let path = repo.git_dir().join("logs").join(name);
+    let Some(content) = std::fs::read_to_string(path)
+        .map(Some)
+        .ignore(ErrorKind::NotFound, None)?
+    else {
+        return Ok(vec![]);
+    };
+    let mut entries = vec![];
+    for line in content.lines() {
+        let mut values = line.splitn(3, ' ').map(str::parse);
+        if let (Some(Ok(old)), Some(Ok(new))) = (values.next(), values.next()) {
+            entries.push((old, new));
+        }
+    }
+    Ok(entries)
+}
+
+/// whether updates of `name` are added to its reflog, which they are if it exists or
+/// `core.logAllRefUpdates` asks for it. by default branches and `HEAD` are logged outside of
+/// bare repositories
+fn logs_updates(repo: &Repository, name: &str) -> anyhow::Result<bool> {
+    if repo.git_dir().join("logs").join(name).is_file() {
+        return Ok(true);
+    }
+    let config = repo.config()?;
+    let all = match config.get("core.logAllRefUpdates") {
+        Some(value) if value.eq_ignore_ascii_case("always") => return Ok(true),
+        Some(_) => config.get_bool("core.logAllRefUpdates")?.unwrap_or(false),
+        None => !repo.is_bare(),
+    };
+    Ok(all
+        && (name == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))))
+}
+
+/// adds `<old> <new> <committer>\t<message>` to the reflog of `name`
+fn append_reflog(
+    repo: &Repository,
+    name: &str,
+    old: Option<&Hash>,
+    new: &Hash,
+    committer: &Event,
+    message: &str,
+) -> anyhow::Result<()> {
+    let path = repo.git_dir().join("logs").join(name);
+    std::fs::create_dir_all(path.parent().expect("logs are inside the repository"))?;
+    let mut line = match old {
+        Some(old) => format!("{old} {new} ").into_bytes(),
+        None => format!("{} {new} ", "0".repeat(40)).into_bytes(),
+    };
+    committer.fmt(&mut line)?;
+    // the message is kept on one line, and left out with its tab if there is none
+    let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !message.is_empty() {
+        write!(line, "\t{message}")?;
+    }
+    writeln!(line)?;
+    File::options()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .with_context(|| format!("unable to append to '{}'", path.display()))?
+        .write_all(&line)?;
+    Ok(())
+}
+
+/// the object a ref points to, if it exists
+pub fn resolve(repo: &Repository, name: &str) -> anyhow::Result<Option<Hash>> {
+    Ok(follow(repo, name)?.1)
+}
+
+/// the full name a short name like `main` refers to, and its object
+pub fn dwim(repo: &Repository, short: &str) -> anyhow::Result<Option<(String, Hash)>> {
+    for rule in DWIM_RULES {
+        let name = rule.replace("{}", short);
+        if !valid_ref_name(&name) {
+            continue;
+        }
+        if let Some(hash) = resolve(repo, &name)? {
+            return Ok(Some((name, hash)));
+        }
+    }
+    Ok(None)
+}
+
+/// a full object name or a ref
+pub fn resolve_object(repo: &Repository, s: &str) -> anyhow::Result<Option<Hash>> {
+    if let Ok(hash) = s.parse() {
+        return Ok(Some(hash));
+    }
+    Ok(dwim(repo, s)?.map(|(_, hash)| hash))
+}
+
+/// the object behind any chain of tags
+pub fn peel(repo: &Repository, hash: &Hash) -> anyhow::Result<Hash> {
+    let mut hash = hash.clone();
+    while let Object::Tag(tag) = repo.read_object(&hash)? {
+        hash = tag.object().clone();
+    }
+    Ok(hash)
+}
+
+/// the ref `HEAD` points to
+pub fn head(repo: &Repository) -> anyhow::Result<Hash> {
+    let (name, hash) = follow(repo, "HEAD")?;
+    hash.with_context(|| format!("{name} does not point to a commit"))
+}
+
+/// the names of the loose refs under `refs/`, in no particular order
+fn loose_names(repo: &Repository) -> anyhow::Result<Vec<String>> {
+    let dir = repo.git_dir().join("refs");
+    let mut names = vec![];
+    for entry in WalkDir::new(&dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry.path().strip_prefix(repo.git_dir())?.to_string_lossy();
+        if valid_ref_name(&name) {
+            names.push(name.into_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// all refs under `refs/` starting with `prefix`, resolved and sorted by name
+pub fn list(repo: &Repository, prefix: &str) -> anyhow::Result<Vec<(String, Hash)>> {
+    let packed = repo.packed_refs()?;
+    let mut names = loose_names(repo)?;
+    names.extend(packed.refs.iter().map(|r| r.name.clone()));
+    names.sort();
+    names.dedup();
+
+    let mut refs = vec![];
+    for name in names {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        let hash = match read_loose(repo, &name)? {
+            Some(RefValue::Direct(hash)) => Some(hash),
+            Some(RefValue::Symbolic(_)) => resolve(repo, &name)?,
+            None => packed.get(&name).map(|r| r.hash.clone()),
+        };
+        if let Some(hash) = hash {
+            refs.push((name, hash));
+        }
+    }
+    Ok(refs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PackedRef {
+    name: String,
+    hash: Hash,
+    /// what an annotated tag points to in the end
+    peeled: Option<Hash>,
+}
+
+/// the refs in `.git/packed-refs`, sorted by name
+#[derive(Debug, Default)]
+pub struct PackedRefs {
+    refs: Vec<PackedRef>,
+}
+
+impl PackedRefs {
+    const HEADER: &'static str = "# pack-refs with: peeled fully-peeled sorted ";
+
+    /// reads `.git/packed-refs`, which is empty if it does not exist. most lookups should use
+    /// the copy kept by [`Repository::packed_refs`] instead
+    pub fn load(repo: &Repository) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path(repo, "packed-refs"))
+            .ignore(ErrorKind::NotFound, String::new())?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut refs: Vec<PackedRef> = vec![];
+        let mut sorted = false;
+        for line in content.lines() {
+            if let Some(traits) = line.strip_prefix("# pack-refs with:") {
+                sorted = traits.split_whitespace().any(|t| t == "sorted");
+            } else if let Some(peeled) = line.strip_prefix('^') {
+                let last = refs
+                    .last_mut()
+                    .context("unexpected line in packed-refs: peeled line without a ref")?;
+                last.peeled = Some(peeled.parse().context("bad peeled line in packed-refs")?);
+            } else if !line.starts_with('#') {
+                let (hash, name) = line
+                    .split_once(' ')
+                    .with_context(|| format!("unexpected line in packed-refs: {line}"))?;
+                refs.push(PackedRef {
+                    name: name.to_owned(),
+                    hash: hash
+                        .parse()
+                        .with_context(|| format!("unexpected line in packed-refs: {line}"))?,
+                    peeled: None,
+                });
+            }
+        }
+        if !sorted {
+            refs.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(Self { refs })
+    }
+
+    fn get(&self, name: &str) -> Option<&PackedRef> {
+        let i = self
+            .refs
+            .binary_search_by(|r| r.name.as_str().cmp(name))
+            .ok()?;
+        Some(&self.refs[i])
+    }
+
+    /// replaces `.git/packed-refs` while holding its lock
+    fn save(&self, repo: &Repository, mut lock: Lock) -> anyhow::Result<()> {
+        self.fmt(&mut lock.file)?;
+        lock.commit()?;
+        repo.forget_packed_refs();
+        Ok(())
+    }
+}
+
+impl Writeable for PackedRefs {
+    fn fmt<W: Write>(&self, f: &mut W) -> std::io::Result<()> {
+        writeln!(f, "{}", Self::HEADER)?;
+        for r in &self.refs {
+            writeln!(f, "{} {}", r.hash, r.name)?;
+            if let Some(peeled) = &r.peeled {
+                writeln!(f, "^{peeled}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// moves the loose refs into `.git/packed-refs`, all of them or only tags and already packed ones
+pub fn pack(repo: &Repository, all: bool, prune: bool) -> anyhow::Result<()> {
+    let lock = Lock::acquire(repo, "packed-refs")?;
+    let mut packed = PackedRefs::load(repo)?;
+
+    let mut pruned = vec![];
+    for name in loose_names(repo)? {
+        if !(all || name.starts_with("refs/tags/") || packed.get(&name).is_some()) {
+            continue;
+        }
+        let Some(RefValue::Direct(hash)) = read_loose(repo, &name)? else {
+            continue;
+        };
+        let peeled = Some(peel(repo, &hash)?).filter(|peeled| *peeled != hash);
+        let r = PackedRef {
+            name: name.clone(),
+            hash: hash.clone(),
+            peeled,
+        };
+        match packed.refs.binary_search_by(|r| r.name.cmp(&name)) {
+            Ok(i) => packed.refs[i] = r,
+            Err(i) => packed.refs.insert(i, r),
+        }
+        pruned.push((name, hash));
+    }
+    packed.save(repo, lock)?;
+
+    if prune {
+        for (name, hash) in pruned {
+            let ref_lock = Lock::acquire(repo, &name)?;
+            // the ref may have moved on since it was packed
+            if read_loose(repo, &name)? == Some(RefValue::Direct(hash)) {
+                ref_lock.delete()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// points the symbolic ref `name` at `target`
+pub fn set_symbolic(repo: &Repository, name: &str, target: &str) -> anyhow::Result<()> {
+    check_name(name)?;
+    let mut lock = Lock::acquire(repo, name)?;
+    writeln!(lock.file, "ref: {target}")?;
+    lock.commit()
+}
+
+/// deletes the symbolic ref `name` itself
+pub fn delete_symbolic(repo: &Repository, name: &str) -> anyhow::Result<()> {
+    let Some(RefValue::Symbolic(_)) = read(repo, name)? else {
+        bail!("Cannot delete {name}, not a symbolic ref");
+    };
+    let lock = Lock::acquire(repo, name)?;
+    lock.delete()
+}
+
+/// an exclusive `<ref>.lock` file, which replaces the ref on commit and is removed otherwise
+struct Lock {
+    name: String,
+    git_dir: PathBuf,
+    file: File,
+    done: bool,
+}
+
+impl Lock {
+    fn acquire(repo: &Repository, name: &str) -> anyhow::Result<Self> {
+        if path(repo, name).is_dir() {
+            bail!("cannot lock ref '{name}': there is a non-empty directory in the way");
+        }
+        let lock = path(repo, &format!("{name}.lock"));
+        let parent = lock.parent().expect("refs are inside the repository");
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("cannot lock ref '{name}': unable to create directory"))?;
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+            .with_context(|| {
+                format!(
+                    "cannot lock ref '{name}': Unable to create '{}'",
+                    lock.display()
+                )
+            })?;
+        Ok(Self {
+            name: name.to_owned(),
+            git_dir: repo.git_dir().to_owned(),
+            file,
+            done: false,
+        })
+    }
+
+    fn path(&self) -> PathBuf {
+        self.git_dir.join(&self.name)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        self.git_dir.join(format!("{}.lock", self.name))
+    }
+
+    fn commit(mut self) -> anyhow::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(self.lock_path(), self.path())?;
+        self.done = true;
+        Ok(())
+    }
+
+    /// removes the ref, and the directories it leaves empty below `refs/<kind>/`
+    fn delete(mut self) -> anyhow::Result<()> {
+        std::fs::remove_file(self.path()).ignore(ErrorKind::NotFound, ())?;
+        std::fs::remove_file(self.lock_path())?;
+        self.done = true;
+
+        let mut dir = self.name.as_str();
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            if parent.matches('/').count() < 2
+                || std::fs::remove_dir(self.git_dir.join(parent)).is_err()
+            {
+                break;
+            }
+            dir = parent;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = std::fs::remove_file(self.lock_path());
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Update {
+    name: String,
+    /// `None` deletes the ref
+    new: Option<Hash>,
+    old: Expect,
+    deref: bool,
+    /// only checks `old`
+    verify: bool,
+}
+
+/// ref updates which are all applied, or none of them
+#[derive(Debug, Default)]
+pub struct Transaction {
+    updates: Vec<Update>,
+    /// updates the symbolic refs themselves instead of what they point to
+    pub no_deref: bool,
+    /// why the refs are updated, for their reflogs
+    pub message: String,
+}
+
+impl Transaction {
+    /// sets `name` to `new`, or deletes it if `new` is `None`
+    pub fn update(&mut self, name: &str, new: Option<Hash>, old: Expect) {
+        self.push(name, new, old, false);
+    }
+
+    pub fn delete(&mut self, name: &str, old: Expect) {
+        self.push(name, None, old, false);
+    }
+
+    pub fn verify(&mut self, name: &str, old: Expect) {
+        self.push(name, None, old, true);
+    }
+
+    fn push(&mut self, name: &str, new: Option<Hash>, old: Expect, verify: bool) {
+        self.updates.push(Update {
+            name: name.to_owned(),
+            new,
+            old,
+            deref: !self.no_deref,
+            verify,
+        });
+    }
+
+    /// reads the commands of `update-ref --stdin`, one per line
+    pub fn parse<R: BufRead>(&mut self, repo: &Repository, input: R) -> anyhow::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+            let args: Vec<&str> = args.split(' ').collect();
+            let value = |s: &str| {
+                new_value(repo, s)
+                    .with_context(|| format!("{command} {}: invalid new value", args[0]))
+            };
+            match (command, args.as_slice()) {
+                ("update", [name, new]) => self.update(name, value(new)?, Expect::Any),
+                ("update", [name, new, old]) => {
+                    self.update(name, value(new)?, Expect::parse(repo, old)?)
+                }
+                ("create", [name, new]) => match value(new)? {
+                    Some(new) => self.update(name, Some(new), Expect::Missing),
+                    None => bail!("create {name}: zero <new-oid>"),
+                },
+                ("delete", [name]) => self.delete(name, Expect::Any),
+                ("delete", [name, old]) => self.delete(name, Expect::parse(repo, old)?),
+                ("verify", [name]) => self.verify(name, Expect::Missing),
+                ("verify", [name, old]) => self.verify(name, Expect::parse(repo, old)?),
+                ("option", ["no-deref"]) => self.no_deref = true,
+                ("update" | "create" | "delete" | "verify" | "option", _) => {
+                    bail!("{command}: wrong number of arguments in '{line}'")
+                }
+                _ => bail!("unknown command: {line}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn commit(self, repo: &Repository) -> anyhow::Result<()> {
+        let mut locked = vec![];
+        for update in self.updates {
+            check_name(&update.name)?;
+            let name = match update.deref {
+                true => follow(repo, &update.name)?.0,
+                false => update.name.clone(),
+            };
+            if locked.iter().any(|(lock, _): &(Lock, _)| lock.name == name) {
+                bail!("multiple updates for ref '{name}' not allowed");
+            }
+            locked.push((Lock::acquire(repo, &name)?, update));
+        }
+
+        // refs are only read once they are all locked, so that no one can change them after they
+        // were checked. what was read of `packed-refs` before may be out of date by then
+        repo.forget_packed_refs();
+        let mut olds = vec![];
+        for (lock, update) in &locked {
+            let name = &lock.name;
+            let current = match read(repo, name)? {
+                Some(RefValue::Direct(hash)) => Some(hash),
+                Some(RefValue::Symbolic(_)) => resolve(repo, name)?,
+                None => None,
+            };
+
+            match (&update.old, &current) {
+                (Expect::Any, _) => {}
+                (Expect::Missing, None) => {}
+                (Expect::Missing, Some(_)) => {
+                    bail!("cannot lock ref '{name}': reference already exists")
+                }
+                (Expect::Value(old), None) => {
+                    bail!("cannot lock ref '{name}': unable to resolve reference '{name}' (expected {old})")
+                }
+                (Expect::Value(old), Some(current)) if old != current => {
+                    bail!("cannot lock ref '{name}': is at {current} but expected {old}")
+                }
+                (Expect::Value(_), Some(_)) => {}
+            }
+            if let Some(new) = &update.new {
+                let typ = repo
+                    .read_object(new)
+                    .with_context(|| {
+                        format!("trying to write ref '{name}' with nonexistent object {new}")
+                    })?
+                    .typ();
+                if name.starts_with("refs/heads/") && typ != BlobType::Commit {
+                    bail!("trying to write non-commit object {new} to branch '{name}'");
+                }
+            }
+            olds.push(current);
+        }
+
+        // every new value is written before any ref changes, so that failing to write one leaves
+        // all refs as they were
+        for (lock, update) in &mut locked {
+            if let Some(new) = &update.new {
+                writeln!(lock.file, "{new}")?;
+            }
+        }
+
+        let deleted: Vec<&str> = locked
+            .iter()
+            .filter(|(_, update)| update.new.is_none() && !update.verify)
+            .map(|(lock, _)| lock.name.as_str())
+            .collect();
+        let packed = repo.packed_refs()?;
+        if deleted.iter().any(|name| packed.get(name).is_some()) {
+            let lock = Lock::acquire(repo, "packed-refs")?;
+            let mut packed = PackedRefs::load(repo)?;
+            packed.refs.retain(|r| !deleted.contains(&r.name.as_str()));
+            packed.save(repo, lock)?;
+        }
+
+        // like git, updates of the branch `HEAD` points at are logged for `HEAD` too
+        let head = follow(repo, "HEAD")?.0;
+        let mut committer = None;
+        for ((lock, update), old) in locked.into_iter().zip(olds) {
+            let Some(new) = &update.new else {
+                if !update.verify {
+                    let log = repo.git_dir().join("logs").join(&lock.name);
+                    std::fs::remove_file(log).ignore(ErrorKind::NotFound, ())?;
+                    lock.delete()?;
+                }
+                continue;
+            };
+            let also_head = lock.name != "HEAD" && lock.name == head;
+            for name in [Some(lock.name.as_str()), also_head.then_some("HEAD")]
+                .into_iter()
+                .flatten()
+            {
+                if !logs_updates(repo, name)? {
+                    continue;
+                }
+                if committer.is_none() {
+                    committer = Some(ident::event(repo, Role::Committer, None)?);
+                }
+                let committer = committer.as_ref().expect("just read");
+                append_reflog(repo, name, old.as_ref(), new, committer, &self.message)?;
+            }
+            lock.commit()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert!(check_name("refs/heads/main").is_ok());
+        assert!(check_name("HEAD").is_ok());
+        assert!(check_name("ORIG_HEAD").is_ok());
+        assert!(check_name("main").is_err());
+        assert!(check_name("refs/heads/a..b").is_err());
+        assert!(check_name("refs/heads/x.lock").is_err());
+        assert_eq!(shorten("refs/heads/main"), "main");
+        assert_eq!(shorten("refs/tags/v1"), "v1");
+        assert_eq!(shorten("HEAD"), "HEAD");
+    }
+
+    #[test]
+    fn packed_refs() {
+        let a = "c1a413174a0a819a1ff334895261bfe8b3b29623";
+        let b = "de152c0e40b317651ae279a437c3355a623e7091";
+        let content = format!(
+            "{}\n{a} refs/heads/main\n{b} refs/tags/v1\n^{a}\n",
+            PackedRefs::HEADER
+        );
+        let packed = PackedRefs::parse(&content).unwrap();
+        let tag = packed.get("refs/tags/v1").unwrap();
+        assert_eq!(tag.peeled, Some(a.parse().unwrap()));
+        assert_eq!(packed.get("refs/heads/main").unwrap().peeled, None);
+        assert!(packed.get("refs/heads/other").is_none());
+
+        let mut written = vec![];
+        packed.fmt(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), content);
+
+        // files without the sorted trait are sorted on load
+        let unsorted = format!("{b} refs/tags/v1\n{a} refs/heads/main\n");
+        let packed = PackedRefs::parse(&unsorted).unwrap();
+        assert_eq!(packed.refs[0].name, "refs/heads/main");
+    }
+}
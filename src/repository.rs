@@ -0,0 +1,444 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{bail, Context};
+use flate2::read::ZlibDecoder;
+
+use crate::{
+    commit_graph::CommitGraph,
+    config::Config,
+    hash::Hash,
+    object::{read_header, Commit, Object, ZlibReadExt},
+    pack::{Pack, RawObject},
+    refs::PackedRefs,
+    BlobType, IoErrorExt, PathBufExt,
+};
+
+/// a git directory and its worktree, as found by [`Repository::discover`]
+#[derive(Debug)]
+pub struct Repository {
+    /// relative to the current directory, which is the top of the worktree if there is one
+    git_dir: PathBuf,
+    /// `None` for a bare repository
+    work_tree: Option<PathBuf>,
+    /// where the command was started, relative to the top of the worktree, with a trailing `/`
+    prefix: String,
+    /// the packs in `objects/pack`, opened when an object is first looked up in them
+    packs: OnceLock<Vec<Pack>>,
+    /// `objects/info/commit-graph`, read when a generation is first looked up
+    commit_graph: OnceLock<Option<CommitGraph>>,
+    /// `packed-refs`, read when a ref is first looked up in it and dropped when it may have changed
+    packed_refs: RefCell<Option<Rc<PackedRefs>>>,
+}
+
+/// whether `dir` looks like a git directory, like git's `is_git_directory`
+fn is_git_dir(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// reads the `gitdir: <path>` of a `.git` file, relative paths are relative to the file
+fn read_git_file(path: &Path) -> anyhow::Result<PathBuf> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("error reading {}", path.display()))?;
+    let Some(dir) = content.strip_prefix("gitdir: ") else {
+        bail!("invalid gitfile format: {}", path.display());
+    };
+    let dir = Path::new(dir.trim_end());
+    let dir = match dir.is_relative() {
+        true => path.parent().unwrap_or(Path::new("")).join(dir),
+        false => dir.to_owned(),
+    };
+    if !is_git_dir(&dir) {
+        bail!("not a git repository: {}", dir.display());
+    }
+    Ok(dir)
+}
+
+/// the absolute directories of `GIT_CEILING_DIRECTORIES`, relative ones are ignored like in git
+fn ceiling_dirs() -> Vec<PathBuf> {
+    let Some(dirs) = std::env::var_os("GIT_CEILING_DIRECTORIES") else {
+        return vec![];
+    };
+    std::env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.canonicalize().unwrap_or(dir))
+        .collect()
+}
+
+/// `path` with `.` and `..` resolved without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+impl Repository {
+    /// finds the repository for the current directory, like `git rev-parse --git-dir` does:
+    /// `git_dir` (from `--git-dir`) or `GIT_DIR` if set, otherwise the first directory upwards
+    /// that has a `.git` or is a bare repository, stopping at `GIT_CEILING_DIRECTORIES`.
+    ///
+    /// the current directory is changed to the top of the worktree, or to the git directory of
+    /// a bare repository. `None` means there is no repository.
+    pub fn discover(git_dir: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        let cwd = std::env::current_dir().context("unable to get the current directory")?;
+        let env_work_tree = std::env::var_os("GIT_WORK_TREE").map(PathBuf::from);
+
+        let explicit = git_dir
+            .map(Path::to_owned)
+            .or_else(|| std::env::var_os("GIT_DIR").map(PathBuf::from));
+        let (git_dir, found_work_tree) = match explicit {
+            Some(dir) => {
+                let dir = match dir.is_file() {
+                    true => read_git_file(&dir)?,
+                    false => dir,
+                };
+                if !is_git_dir(&dir) {
+                    bail!("not a git repository: '{}'", dir.display());
+                }
+                // without anything else, git takes the current directory as the worktree
+                (cwd.join(dir), Some(cwd.clone()))
+            }
+            None => {
+                let ceilings = ceiling_dirs();
+                let start = cwd.canonicalize().unwrap_or(cwd.clone());
+                let mut found = None;
+                for dir in start.ancestors() {
+                    // the directory we start in is searched even if it is a ceiling
+                    if dir != start && ceilings.iter().any(|ceiling| ceiling == dir) {
+                        break;
+                    }
+                    let dot_git = dir.join(".git");
+                    if dot_git.is_file() {
+                        found = Some((read_git_file(&dot_git)?, Some(dir.to_owned())));
+                        break;
+                    }
+                    if is_git_dir(&dot_git) {
+                        found = Some((dot_git, Some(dir.to_owned())));
+                        break;
+                    }
+                    if is_git_dir(dir) {
+                        found = Some((dir.to_owned(), None));
+                        break;
+                    }
+                }
+                let Some(found) = found else {
+                    return Ok(None);
+                };
+                found
+            }
+        };
+        let git_dir = git_dir.canonicalize().unwrap_or(git_dir);
+
+        let config = Config::load_file(&git_dir.join("config"), Some(&git_dir))?;
+        let work_tree = if let Some(work_tree) = env_work_tree {
+            Some(cwd.join(work_tree))
+        } else if let Some(work_tree) = config.get_path("core.worktree") {
+            Some(git_dir.join(work_tree))
+        } else if config.get_bool("core.bare")? == Some(true) {
+            None
+        } else {
+            found_work_tree
+        };
+        let work_tree = work_tree.map(|dir| dir.canonicalize().unwrap_or(normalize(&dir)));
+
+        let start = cwd.canonicalize().unwrap_or(cwd);
+        let (top, prefix) = match &work_tree {
+            Some(work_tree) => {
+                let prefix = match start.strip_prefix(work_tree) {
+                    Ok(prefix) if prefix.as_os_str().is_empty() => String::new(),
+                    Ok(prefix) => format!("{}/", prefix.to_string_lossy()),
+                    Err(_) => String::new(),
+                };
+                (work_tree.clone(), prefix)
+            }
+            None => (git_dir.clone(), String::new()),
+        };
+        std::env::set_current_dir(&top)
+            .with_context(|| format!("cannot chdir to '{}'", top.display()))?;
+
+        // paths are kept relative where git shows them relative
+        let git_dir = match git_dir.strip_prefix(&top) {
+            Ok(rel) if rel.as_os_str().is_empty() => PathBuf::from("."),
+            Ok(rel) if rel == Path::new(".git") => rel.to_owned(),
+            _ => git_dir,
+        };
+        Ok(Some(Self {
+            git_dir,
+            work_tree,
+            prefix,
+            packs: OnceLock::new(),
+            commit_graph: OnceLock::new(),
+            packed_refs: RefCell::default(),
+        }))
+    }
+
+    /// the settings of all config files of the repository
+    pub fn config(&self) -> anyhow::Result<Config> {
+        Config::load(&self.git_dir)
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.work_tree.is_none()
+    }
+
+    /// turns a path given relative to where the command was started into one relative to the
+    /// top of the worktree, which is where commands run
+    pub fn prefixed(&self, path: &str) -> anyhow::Result<String> {
+        if self.prefix.is_empty() || Path::new(path).is_absolute() {
+            return Ok(path.to_owned());
+        }
+        let mut parts: Vec<&str> = self.prefix.split_terminator('/').collect();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    if parts.pop().is_none() {
+                        bail!("{path}: '{path}' is outside repository");
+                    }
+                }
+                part => parts.push(part),
+            }
+        }
+        let mut prefixed = parts.join("/");
+        if path.ends_with('/') && !prefixed.is_empty() {
+            prefixed.push('/');
+        }
+        // the top of the worktree itself
+        if prefixed.is_empty() {
+            prefixed.push('.');
+        }
+        Ok(prefixed)
+    }
+
+    /// a file given relative to where the command was started, as seen from the current directory
+    pub fn file(&self, path: &Path) -> PathBuf {
+        Path::new(&self.prefix).join(path)
+    }
+
+    /// the loose object file of `hash`
+    pub fn object_path(&self, hash: &Hash) -> PathBuf {
+        self.git_dir.join("objects").push_dir(hash.object_path())
+    }
+
+    /// the loose object file of `hash`, creating its directory so that it can be written
+    pub fn object_file(&self, hash: &Hash) -> anyhow::Result<PathBuf> {
+        let dir = self.git_dir.join("objects").push_dir(hash.dir());
+        std::fs::create_dir(dir).ignore(ErrorKind::AlreadyExists, ())?;
+        Ok(self.object_path(hash))
+    }
+
+    fn packs(&self) -> anyhow::Result<&[Pack]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let packs = Pack::load_all(&self.git_dir.join("objects/pack"))?;
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    /// the refs in `packed-refs`, read once until [`Repository::forget_packed_refs`]
+    pub fn packed_refs(&self) -> anyhow::Result<Rc<PackedRefs>> {
+        if let Some(packed) = self.packed_refs.borrow().as_ref() {
+            return Ok(packed.clone());
+        }
+        let packed = Rc::new(PackedRefs::load(self)?);
+        *self.packed_refs.borrow_mut() = Some(packed.clone());
+        Ok(packed)
+    }
+
+    /// makes the next lookup read `packed-refs` again, after it was or may have been changed
+    pub fn forget_packed_refs(&self) {
+        self.packed_refs.take();
+    }
+
+    /// the generation of a commit from the commit-graph, `None` if it is not in one
+    pub fn generation(&self, hash: &Hash) -> Option<u32> {
+        self.commit_graph
+            .get_or_init(|| CommitGraph::open(&self.git_dir))
+            .as_ref()
+            .and_then(|graph| graph.generation(hash))
+    }
+
+    /// the type and content of a loose object, `None` if there is no such loose object
+    fn read_loose(&self, hash: &Hash) -> anyhow::Result<Option<RawObject>> {
+        let data = match std::fs::read(self.object_path(hash)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => Err(e)?,
+        };
+        let (typ, _) = read_header(data.as_slice())?;
+        let mut raw = vec![];
+        ZlibDecoder::new(data.as_slice()).read_to_end(&mut raw)?;
+        let start = raw.iter().position(|&c| c == b'\0').map_or(0, |i| i + 1);
+        raw.drain(..start);
+        Ok(Some((typ, Arc::new(raw))))
+    }
+
+    /// the type and content of a packed object, `None` if no pack has it
+    fn read_packed(&self, hash: &Hash) -> anyhow::Result<Option<RawObject>> {
+        for pack in self.packs()? {
+            if let Some(object) = pack.read(hash, &|base| self.read_raw(base))? {
+                return Ok(Some(object));
+            }
+        }
+        Ok(None)
+    }
+
+    /// the type and content of an object, wherever it is stored
+    pub fn read_raw(&self, hash: &Hash) -> anyhow::Result<RawObject> {
+        match self.read_loose(hash)? {
+            Some(object) => Ok(object),
+            None => self
+                .read_packed(hash)?
+                .with_context(|| format!("not a valid object name {hash}")),
+        }
+    }
+
+    /// reads the object with the given hash from the object store
+    pub fn read_object(&self, hash: &Hash) -> anyhow::Result<Object> {
+        if let Ok(mut f) = File::open(self.object_path(hash)) {
+            return Ok(f.zlib_read()?);
+        }
+        let (typ, body) = self
+            .read_packed(hash)?
+            .with_context(|| format!("not a valid object name {hash}"))?;
+        let mut raw = format!("{typ} {}\0", body.len()).into_bytes();
+        raw.extend_from_slice(&body);
+        Ok(Object::try_from(raw.as_slice())?)
+    }
+
+    /// reads the commit with the given hash, failing for other objects
+    pub fn read_commit(&self, hash: &Hash) -> anyhow::Result<Commit> {
+        match self.read_object(hash)? {
+            Object::Commit(commit) => Ok(commit),
+            _ => bail!("{hash} is not a commit"),
+        }
+    }
+
+    /// reads only the type and size of an object
+    pub fn object_header(&self, hash: &Hash) -> anyhow::Result<(BlobType, usize)> {
+        if let Ok(f) = File::open(self.object_path(hash)) {
+            return Ok(read_header(f)?);
+        }
+        let (typ, body) = self
+            .read_packed(hash)?
+            .with_context(|| format!("not a valid object name {hash}"))?;
+        Ok((typ, body.len()))
+    }
+
+    pub fn has_object(&self, hash: &Hash) -> anyhow::Result<bool> {
+        Ok(self.object_path(hash).try_exists()?
+            || self.packs()?.iter().any(|pack| pack.contains(hash)))
+    }
+
+    /// how many bytes the object takes up on disk, compressed and possibly as a delta
+    pub fn object_disk_size(&self, hash: &Hash) -> anyhow::Result<u64> {
+        if let Ok(metadata) = self.object_path(hash).metadata() {
+            return Ok(metadata.len());
+        }
+        self.packs()?
+            .iter()
+            .find_map(|pack| pack.disk_size(hash))
+            .with_context(|| format!("not a valid object name {hash}"))
+    }
+
+    /// the object a packed object is stored as a delta against, `None` if it is stored whole.
+    ///
+    /// like git, the pack that has the object is asked even if there also is a loose copy
+    pub fn delta_base(&self, hash: &Hash) -> anyhow::Result<Option<Hash>> {
+        match self.packs()?.iter().find(|pack| pack.contains(hash)) {
+            Some(pack) => Ok(pack.delta_base(hash)?),
+            None => Ok(None),
+        }
+    }
+
+    /// the objects whose hash starts with the given lowercase hex digits, sorted
+    pub fn objects_with_prefix(&self, prefix: &str) -> anyhow::Result<Vec<Hash>> {
+        let mut hashes = vec![];
+        if let (Some(dir), Some(rest)) = (prefix.get(..2), prefix.get(2..)) {
+            let entries = std::fs::read_dir(self.git_dir.join("objects").join(dir))
+                .map(Some)
+                .ignore(ErrorKind::NotFound, None)?;
+            for file in entries.into_iter().flatten() {
+                let file = file?.file_name();
+                let Some(file) = file.to_str().filter(|file| file.starts_with(rest)) else {
+                    continue;
+                };
+                if let Ok(hash) = format!("{dir}{file}").parse() {
+                    hashes.push(hash);
+                }
+            }
+        }
+        for pack in self.packs()? {
+            hashes.extend(pack.index().with_prefix(prefix).cloned());
+        }
+        hashes.sort();
+        hashes.dedup();
+        Ok(hashes)
+    }
+
+    /// hashes of all objects, loose or packed, sorted
+    pub fn all_objects(&self) -> anyhow::Result<Vec<Hash>> {
+        let mut hashes = vec![];
+        for dir in std::fs::read_dir(self.git_dir.join("objects"))? {
+            let dir = dir?;
+            let prefix = dir.file_name();
+            let Some(prefix) = prefix.to_str().filter(|p| p.len() == 2) else {
+                continue;
+            };
+            for file in std::fs::read_dir(dir.path())? {
+                let file = file?.file_name();
+                if let Some(Ok(hash)) = file.to_str().map(|rest| format!("{prefix}{rest}").parse())
+                {
+                    hashes.push(hash);
+                }
+            }
+        }
+        for pack in self.packs()? {
+            hashes.extend_from_slice(pack.index().hashes());
+        }
+        hashes.sort();
+        hashes.dedup();
+        Ok(hashes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixed() {
+        let repo = Repository {
+            git_dir: ".git".into(),
+            work_tree: Some("/repo".into()),
+            prefix: "a/b/".to_owned(),
+            packs: OnceLock::new(),
+            commit_graph: OnceLock::new(),
+            packed_refs: RefCell::default(),
+        };
+        assert_eq!(repo.prefixed("file").unwrap(), "a/b/file");
+        assert_eq!(repo.prefixed(".").unwrap(), "a/b");
+        assert_eq!(repo.prefixed("../c/").unwrap(), "a/c/");
+        assert_eq!(repo.prefixed("../..").unwrap(), ".");
+        assert_eq!(repo.prefixed("/abs").unwrap(), "/abs");
+        assert!(repo.prefixed("../../..").is_err());
+    }
+}
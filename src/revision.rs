@@ -0,0 +1,461 @@
+use std::{collections::HashSet, path::Path, process::ExitCode};
+
+use anyhow::bail;
+use clap::ValueEnum;
+
+use crate::{
+    hash::Hash,
+    index::Index,
+    object::{Commit, Object},
+    refs,
+    repository::Repository,
+    walk::DateQueue,
+    BlobType,
+};
+
+/// the shortest abbreviated hash that is looked up
+const MIN_ABBREV: usize = 4;
+/// the length of abbreviated hashes unless `core.abbrev` says otherwise
+const DEFAULT_ABBREV: usize = 7;
+
+#[derive(Debug, derive_more::Display, Clone, thiserror::Error)]
+pub enum RevisionError {
+    #[display(fmt = "Not a valid object name {_0}")]
+    Unknown(String),
+    #[display(fmt = "short object ID {_0} is ambiguous")]
+    Ambiguous(String),
+    #[display(fmt = "path '{_0}' does not exist in '{_1}'")]
+    NoSuchPath(String, String),
+    #[display(fmt = "path '{_0}' is not in the index")]
+    NotInIndex(String),
+    #[display(fmt = "no upstream configured for branch '{_0}'")]
+    NoUpstream(String),
+    #[display(fmt = "HEAD does not point to a branch")]
+    DetachedHead,
+    #[display(fmt = "{_0}: expected {_1} type, but the object dereferences to {_2} type")]
+    WrongType(String, BlobType, BlobType),
+}
+
+/// the objects a revision names after peeling, from `^{type}` or how it is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Peel {
+    Any,
+    Type(BlobType),
+}
+
+/// the type `^{...}` peels to, `None` for `^{}`
+fn peel_type(name: &str) -> Option<Peel> {
+    match name {
+        "" => Some(Peel::Any),
+        "object" => Some(Peel::Any),
+        name => BlobType::from_str(name, false).ok().map(Peel::Type),
+    }
+}
+
+/// follows tags, and commits to their tree, until the object has type `typ`
+pub fn peel(repo: &Repository, spec: &str, hash: Hash, typ: BlobType) -> anyhow::Result<Hash> {
+    let mut hash = hash;
+    loop {
+        let object = repo.read_object(&hash)?;
+        if object.typ() == typ {
+            return Ok(hash);
+        }
+        hash = match object {
+            Object::Tag(tag) => tag.object().clone(),
+            Object::Commit(commit) if typ == BlobType::Tree => commit.tree().clone(),
+            object => Err(RevisionError::WrongType(spec.to_owned(), typ, object.typ()))?,
+        };
+    }
+}
+
+/// follows tags until the object is not a tag, for `^{}`
+fn peel_tags(repo: &Repository, hash: Hash) -> anyhow::Result<Hash> {
+    let mut hash = hash;
+    while let Object::Tag(tag) = repo.read_object(&hash)? {
+        hash = tag.object().clone();
+    }
+    Ok(hash)
+}
+
+/// peels `hash` to a commit, as `spec` names it in errors, and reads it
+fn peel_commit(repo: &Repository, spec: &str, hash: Hash) -> anyhow::Result<Commit> {
+    repo.read_commit(&peel(repo, spec, hash, BlobType::Commit)?)
+}
+
+/// the newest commit reachable from `tips` whose message contains `text`.
+///
+/// git takes `text` as a regular expression, here it is matched literally
+fn find_message(repo: &Repository, tips: &[Hash], text: &str) -> anyhow::Result<Option<Hash>> {
+    let mut queue = DateQueue::new();
+    let mut seen = HashSet::new();
+    for tip in tips {
+        let Ok(hash) = peel(repo, "", tip.clone(), BlobType::Commit) else {
+            continue;
+        };
+        if seen.insert(hash.clone()) {
+            queue.push(hash.clone(), &peel_commit(repo, "", hash)?);
+        }
+    }
+    while let Some(hash) = queue.pop() {
+        let commit = peel_commit(repo, "", hash.clone())?;
+        if String::from_utf8_lossy(commit.message()).contains(text) {
+            return Ok(Some(hash));
+        }
+        for parent in commit.parents() {
+            if seen.insert(parent.clone()) {
+                queue.push(parent.clone(), &peel_commit(repo, "", parent.clone())?);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// the objects of all refs and then `HEAD`, which `--all` starts from and `:/` searches
+pub fn all_tips(repo: &Repository) -> anyhow::Result<Vec<Hash>> {
+    let mut tips: Vec<Hash> = refs::list(repo, "refs/")?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect();
+    tips.extend(refs::resolve(repo, "HEAD")?);
+    Ok(tips)
+}
+
+/// the ref `<branch>@{upstream}` names, the current branch for an empty `branch`
+fn upstream(repo: &Repository, branch: &str) -> anyhow::Result<String> {
+    let branch = match branch {
+        "" | "HEAD" => {
+            let (name, _) = refs::follow(repo, "HEAD")?;
+            match name.strip_prefix("refs/heads/") {
+                Some(branch) => branch.to_owned(),
+                None => Err(RevisionError::DetachedHead)?,
+            }
+        }
+        branch => branch
+            .strip_prefix("refs/heads/")
+            .unwrap_or(branch)
+            .to_owned(),
+    };
+    let config = repo.config()?;
+    let remote = config.get(&format!("branch.{branch}.remote"));
+    let merge = config.get(&format!("branch.{branch}.merge"));
+    let (Some(remote), Some(merge)) = (remote, merge) else {
+        Err(RevisionError::NoUpstream(branch))?
+    };
+    Ok(match remote {
+        "." => merge.to_owned(),
+        remote => {
+            let merge = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+            format!("refs/remotes/{remote}/{merge}")
+        }
+    })
+}
+
+/// whether `name` could be an abbreviated hash
+fn is_hex(name: &str) -> bool {
+    (MIN_ABBREV..=40).contains(&name.len()) && name.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+/// resolves the name a revision starts with: a full or abbreviated hash, or a ref.
+/// ambiguous abbreviations are narrowed down to the objects that peel to `hint`
+fn resolve_name(repo: &Repository, name: &str, hint: Peel) -> anyhow::Result<Hash> {
+    if name.len() == 40 {
+        if let Ok(hash) = name.parse() {
+            return Ok(hash);
+        }
+    }
+    let name = match name {
+        "@" => "HEAD",
+        name => name,
+    };
+    if let Some((_, hash)) = refs::dwim(repo, name)? {
+        return Ok(hash);
+    }
+    if !is_hex(name) {
+        Err(RevisionError::Unknown(name.to_owned()))?;
+    }
+    let mut candidates = repo.objects_with_prefix(&name.to_ascii_lowercase())?;
+    if candidates.len() > 1 {
+        if let Peel::Type(typ) = hint {
+            candidates.retain(|hash| peel(repo, name, hash.clone(), typ).is_ok());
+        }
+    }
+    match candidates.as_slice() {
+        [] => Err(RevisionError::Unknown(name.to_owned()))?,
+        [hash] => Ok(hash.clone()),
+        _ => Err(RevisionError::Ambiguous(name.to_owned()))?,
+    }
+}
+
+/// where `:` separates a revision from a path, outside of `{...}`
+fn path_separator(spec: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// the number after `~` or `^` at the start of `s`, and the rest
+fn number(s: &str) -> (Option<usize>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    (s[..end].parse().ok(), &s[end..])
+}
+
+/// resolves a revision like `HEAD~2`, `main^{tree}`, `v1.0:src/main.rs`, `:/fix` or an
+/// abbreviated hash to the object it names
+pub fn resolve(repo: &Repository, spec: &str) -> anyhow::Result<Hash> {
+    resolve_peeled(repo, spec, Peel::Any)
+}
+
+/// like [`resolve`], but the object is peeled to `typ`, like `<spec>^{<typ>}`
+pub fn resolve_typed(repo: &Repository, spec: &str, typ: BlobType) -> anyhow::Result<Hash> {
+    let hash = resolve_peeled(repo, spec, Peel::Type(typ))?;
+    peel(repo, spec, hash, typ)
+}
+
+fn resolve_peeled(repo: &Repository, spec: &str, hint: Peel) -> anyhow::Result<Hash> {
+    if let Some(text) = spec.strip_prefix(":/") {
+        return find_message(repo, &all_tips(repo)?, text)?
+            .ok_or_else(|| RevisionError::Unknown(spec.to_owned()).into());
+    }
+    if let Some(path) = spec.strip_prefix(':') {
+        let (stage, path) = match path.as_bytes() {
+            [stage @ b'0'..=b'3', b':', ..] => ((stage - b'0') as u16, &path[2..]),
+            _ => (0, path),
+        };
+        let index = Index::load(repo)?;
+        let entry = index
+            .entries()
+            .iter()
+            .find(|entry| entry.path == path.as_bytes() && entry.stage() == stage)
+            .ok_or_else(|| RevisionError::NotInIndex(path.to_owned()))?;
+        return Ok(entry.hash.clone());
+    }
+    if let Some(i) = path_separator(spec) {
+        let (rev, path) = (&spec[..i], &spec[i + 1..]);
+        let tree = resolve_typed(repo, rev, BlobType::Tree)?;
+        if path.is_empty() {
+            return Ok(tree);
+        }
+        let Object::Tree(tree) = repo.read_object(&tree)? else {
+            unreachable!("peeled to a tree");
+        };
+        let found = tree.find(repo, path.trim_end_matches('/').as_bytes())?;
+        return match found {
+            Some((_, hash)) => Ok(hash),
+            None => Err(RevisionError::NoSuchPath(path.to_owned(), rev.to_owned()))?,
+        };
+    }
+
+    let end = spec.find(['~', '^']).unwrap_or(spec.len());
+    let (name, mut rest) = spec.split_at(end);
+    // navigating history needs a commit, which disambiguates abbreviated hashes
+    let hint = match rest.chars().next() {
+        Some(_) if rest.starts_with("^{") => {
+            let close = rest.find('}').unwrap_or(rest.len());
+            peel_type(&rest[2..close]).unwrap_or(Peel::Any)
+        }
+        Some(_) => Peel::Type(BlobType::Commit),
+        None => hint,
+    };
+    let mut hash = match name.split_once("@{") {
+        Some((branch, suffix)) => match suffix.strip_suffix('}') {
+            Some(u) if ["u", "upstream"].contains(&u.to_ascii_lowercase().as_str()) => {
+                let upstream = upstream(repo, branch)?;
+                refs::resolve(repo, &upstream)?
+                    .ok_or_else(|| RevisionError::Unknown(spec.to_owned()))?
+            }
+            _ => Err(RevisionError::Unknown(spec.to_owned()))?,
+        },
+        None if name.is_empty() => Err(RevisionError::Unknown(spec.to_owned()))?,
+        None => resolve_name(repo, name, hint)?,
+    };
+
+    while !rest.is_empty() {
+        let current = &spec[..spec.len() - rest.len()];
+        if let Some(after) = rest.strip_prefix("^{") {
+            let Some(close) = after.find('}') else {
+                Err(RevisionError::Unknown(spec.to_owned()))?
+            };
+            let inner = &after[..close];
+            rest = &after[close + 1..];
+            hash = match inner.strip_prefix('/') {
+                Some(text) => {
+                    let commit = peel(repo, current, hash, BlobType::Commit)?;
+                    find_message(repo, &[commit], text)?
+                        .ok_or_else(|| RevisionError::Unknown(spec.to_owned()))?
+                }
+                None => match peel_type(inner) {
+                    Some(Peel::Any) if inner.is_empty() => peel_tags(repo, hash)?,
+                    Some(Peel::Any) => hash,
+                    Some(Peel::Type(typ)) => peel(repo, current, hash, typ)?,
+                    None => Err(RevisionError::Unknown(spec.to_owned()))?,
+                },
+            };
+        } else if let Some(after) = rest.strip_prefix('^') {
+            let (n, after) = number(after);
+            rest = after;
+            let commit = peel_commit(repo, current, hash.clone())?;
+            hash = match n.unwrap_or(1) {
+                0 => peel(repo, current, hash, BlobType::Commit)?,
+                n => commit
+                    .parents()
+                    .get(n - 1)
+                    .cloned()
+                    .ok_or_else(|| RevisionError::Unknown(spec.to_owned()))?,
+            };
+        } else if let Some(after) = rest.strip_prefix('~') {
+            let (n, after) = number(after);
+            rest = after;
+            for _ in 0..n.unwrap_or(1) {
+                let commit = peel_commit(repo, current, hash)?;
+                hash = commit
+                    .parents()
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| RevisionError::Unknown(spec.to_owned()))?;
+            }
+        } else {
+            Err(RevisionError::Unknown(spec.to_owned()))?;
+        }
+    }
+    Ok(hash)
+}
+
+/// the shortest prefix of `hash` of at least `min_len` digits that no other object starts with
+pub fn abbreviate(repo: &Repository, hash: &Hash, min_len: usize) -> anyhow::Result<String> {
+    let hex = hash.to_string();
+    for len in min_len.clamp(MIN_ABBREV, 40)..40 {
+        let candidates = repo.objects_with_prefix(&hex[..len])?;
+        if candidates.iter().all(|candidate| candidate == hash) {
+            return Ok(hex[..len].to_owned());
+        }
+    }
+    Ok(hex)
+}
+
+/// the length of abbreviated hashes, from `core.abbrev`
+pub fn abbrev_len(repo: &Repository) -> anyhow::Result<usize> {
+    let config = repo.config()?;
+    Ok(config
+        .get("core.abbrev")
+        .and_then(crate::config::parse_int)
+        .and_then(|len| usize::try_from(len).ok())
+        .unwrap_or(DEFAULT_ABBREV))
+}
+
+/// the objects one argument includes and excludes: a revision, `^rev`, `A..B` for what is
+/// reachable from B but not A, or `A...B` for what is reachable from either but not both.
+/// an empty side of a range is `HEAD`
+pub fn range(repo: &Repository, arg: &str) -> anyhow::Result<(Vec<Hash>, Vec<Hash>)> {
+    let side = |rev: &str| match rev {
+        "" => resolve(repo, "HEAD"),
+        rev => resolve(repo, rev),
+    };
+    if arg.starts_with(':') {
+        return Ok((vec![resolve(repo, arg)?], vec![]));
+    }
+    if let Some((a, b)) = arg.split_once("...") {
+        let (a, b) = (side(a)?, side(b)?);
+        let bases = repo.merge_bases(&a, std::slice::from_ref(&b))?;
+        return Ok((vec![a, b], bases));
+    }
+    if let Some((a, b)) = arg.split_once("..") {
+        return Ok((vec![side(b)?], vec![side(a)?]));
+    }
+    match arg.strip_prefix('^') {
+        Some(rev) => Ok((vec![], vec![resolve(repo, rev)?])),
+        None => Ok((vec![resolve(repo, arg)?], vec![])),
+    }
+}
+
+/// the `rev-parse` command
+#[derive(Debug)]
+pub struct RevParse {
+    /// requires exactly one argument naming an object
+    pub verify: bool,
+    /// fails without a message when `verify` fails
+    pub quiet: bool,
+    /// abbreviates the printed hashes to at least this many digits
+    pub short: Option<usize>,
+}
+
+impl RevParse {
+    fn print(&self, repo: &Repository, prefix: &str, hash: &Hash) -> anyhow::Result<()> {
+        match self.short {
+            Some(len) => println!("{prefix}{}", abbreviate(repo, hash, len)?),
+            None => println!("{prefix}{hash}"),
+        }
+        Ok(())
+    }
+
+    /// prints the objects `arg` names, excluded ones with a leading `^`
+    fn arg(&self, repo: &Repository, arg: &str) -> anyhow::Result<()> {
+        let (include, exclude) = range(repo, arg)?;
+        for hash in include.iter().rev() {
+            self.print(repo, "", hash)?;
+        }
+        for hash in &exclude {
+            self.print(repo, "^", hash)?;
+        }
+        Ok(())
+    }
+
+    pub fn run(&self, repo: &Repository, args: &[String]) -> anyhow::Result<ExitCode> {
+        if self.verify {
+            let hash = match args {
+                [arg] => resolve(repo, arg).ok(),
+                _ => None,
+            };
+            return match hash {
+                Some(hash) => {
+                    self.print(repo, "", &hash)?;
+                    Ok(ExitCode::SUCCESS)
+                }
+                None if self.quiet => Ok(ExitCode::FAILURE),
+                None => bail!("Needed a single revision"),
+            };
+        }
+        for arg in args {
+            match self.arg(repo, arg) {
+                Ok(()) => {}
+                // what is not a revision may be a path
+                Err(_) if Path::new(arg).exists() => println!("{arg}"),
+                Err(err) => match err.downcast_ref() {
+                    Some(RevisionError::Unknown(_)) => bail!(
+                        "ambiguous argument '{arg}': unknown revision or path not in the working tree.
+Use '--' to separate paths from revisions, like this:
+'git <command> [<revision>...] -- [<file>...]'"
+                    ),
+                    _ => return Err(err),
+                },
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_parts() {
+        assert_eq!(path_separator("HEAD:src/main.rs"), Some(4));
+        assert_eq!(path_separator("HEAD^{/fix: typo}:a"), Some(17));
+        assert_eq!(path_separator("HEAD~2"), None);
+        assert_eq!(number("12^2"), (Some(12), "^2"));
+        assert_eq!(number("^{tree}"), (None, "^{tree}"));
+        assert!(is_hex("abcD"));
+        assert!(!is_hex("abc"));
+        assert!(!is_hex("main"));
+        assert_eq!(peel_type("tree"), Some(Peel::Type(BlobType::Tree)));
+        assert_eq!(peel_type(""), Some(Peel::Any));
+        assert_eq!(peel_type("bogus"), None);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{io::ErrorKind, process::ExitCode};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    hash::Hash,
+    ignore::Ignore,
+    index::{worktree_files, worktree_path, Index, IndexEntry},
+    object::{Object, Tree},
+    pathspec::Pathspec,
+    repository::Repository,
+    IoErrorExt,
+};
+
+/// the tree of the commit `HEAD` points to, if there is one
+fn head_tree(repo: &Repository) -> anyhow::Result<Option<Tree>> {
+    let Ok(head) = crate::refs::head(repo) else {
+        return Ok(None);
+    };
+    let Object::Commit(commit) = repo.read_object(&head)? else {
+        bail!("HEAD is not a commit");
+    };
+    let Object::Tree(tree) = repo.read_object(commit.tree())? else {
+        bail!("{} is not a tree", commit.tree());
+    };
+    Ok(Some(tree))
+}
+
+fn display(path: &[u8]) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(path)
+}
+
+/// stages the files matching `paths`, including their removal
+pub fn add(
+    repo: &Repository,
+    paths: &[String],
+    all: bool,
+    update: bool,
+    force: bool,
+    dry_run: bool,
+    verbose: bool,
+) -> anyhow::Result<()> {
+    let pathspec = Pathspec::new(paths);
+    if pathspec.is_empty() && !all && !update {
+        println!("Nothing specified, nothing added.");
+        return Ok(());
+    }
+    let verbose = verbose || dry_run;
+    let mut index = Index::load(repo)?;
+    let mut matched = vec![false; pathspec.len()];
+
+    for entry in index.entries().to_vec() {
+        if !pathspec.matches(&entry.path) {
+            continue;
+        }
+        if let Some(spec) = pathspec.matching(&entry.path) {
+            matched[spec] = true;
+        }
+        let metadata = match std::fs::symlink_metadata(worktree_path(&entry.path)) {
+            Ok(metadata) if !metadata.is_dir() => metadata,
+            Ok(_) => {
+                // replaced by a directory, whose files are added below
+                index.remove(&entry.path);
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                if verbose {
+                    println!("remove '{}'", display(&entry.path));
+                }
+                index.remove(&entry.path);
+                continue;
+            }
+            Err(e) => Err(e)?,
+        };
+        if entry.stage() != 0
+            || entry.intent_to_add()
+            || index.is_modified(repo, &entry, &metadata)?
+        {
+            if verbose {
+                println!("add '{}'", display(&entry.path));
+            }
+            index.add(IndexEntry::from_worktree(repo, &entry.path, !dry_run)?);
+        } else if let Some(entry) = index.get_mut(&entry.path) {
+            entry.update_stat(&metadata);
+        }
+    }
+
+    if !update {
+        for path in worktree_files(repo, &pathspec, !force)? {
+            if let Some(spec) = pathspec.matching(&path) {
+                matched[spec] = true;
+            }
+            if index.get(&path).is_some() {
+                continue;
+            }
+            if verbose {
+                println!("add '{}'", display(&path));
+            }
+            index.add(IndexEntry::from_worktree(repo, &path, !dry_run)?);
+        }
+    }
+
+    for (i, matched) in matched.into_iter().enumerate() {
+        if matched {
+            continue;
+        }
+        let spec = pathspec.spec(i);
+        let path = spec.strip_suffix(b"/").unwrap_or(spec);
+        let Ok(metadata) = std::fs::metadata(worktree_path(path)) else {
+            bail!("pathspec '{}' did not match any files", display(spec));
+        };
+        if !update && Ignore::load(repo).is_path_ignored(path, metadata.is_dir()) {
+            bail!(
+                "The following paths are ignored by one of your .gitignore files:\n{}\nhint: Use -f if you really want to add them.",
+                display(path)
+            );
+        }
+        // like git, untracked files with -u and directories without files to add are skipped
+        if update || metadata.is_dir() {
+            continue;
+        }
+        bail!("pathspec '{}' did not match any files", display(spec));
+    }
+
+    if !dry_run {
+        index.save(repo)?;
+    }
+    Ok(())
+}
+
+/// removes the files matching `paths` from the index and, unless `cached`, the worktree
+pub fn rm(
+    repo: &Repository,
+    paths: &[String],
+    cached: bool,
+    recursive: bool,
+    force: bool,
+    quiet: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let pathspec = Pathspec::new(paths);
+    if pathspec.is_empty() {
+        bail!("No pathspec was given. Which files should I remove?");
+    }
+    let mut index = Index::load(repo)?;
+
+    let mut removed = vec![];
+    for i in 0..pathspec.len() {
+        let spec = pathspec.spec(i);
+        let entries: Vec<_> = index
+            .entries()
+            .iter()
+            .filter(|e| pathspec.spec_matches(i, &e.path))
+            .cloned()
+            .collect();
+        if entries.is_empty() {
+            bail!("pathspec '{}' did not match any files", display(spec));
+        }
+        if !recursive
+            && entries
+                .iter()
+                .any(|e| e.path.starts_with(spec) && e.path != spec)
+        {
+            bail!("not removing '{}' recursively without -r", display(spec));
+        }
+        removed.extend(entries);
+    }
+    removed.sort_by(|a, b| a.path.cmp(&b.path));
+    removed.dedup_by(|a, b| a.path == b.path);
+
+    if !force {
+        let head = head_tree(repo)?;
+        for entry in &removed {
+            let in_head = match &head {
+                Some(tree) => tree.find(repo, &entry.path)?.map(|(_, hash)| hash),
+                None => None,
+            };
+            let staged = in_head.as_ref() != Some(&entry.hash);
+            let local = match std::fs::symlink_metadata(worktree_path(&entry.path)) {
+                Ok(metadata) => index.is_modified(repo, entry, &metadata)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => false,
+                Err(e) => Err(e)?,
+            };
+            let path = display(&entry.path);
+            if staged && local {
+                bail!("the following file has staged content different from both the\nfile and the HEAD:\n    {path}\n(use -f to force removal)");
+            }
+            if staged && !cached {
+                bail!("the following file has changes staged in the index:\n    {path}\n(use --cached to keep the file, or -f to force removal)");
+            }
+            if local && !cached {
+                bail!("the following file has local modifications:\n    {path}\n(use --cached to keep the file, or -f to force removal)");
+            }
+        }
+    }
+
+    for entry in &removed {
+        if !quiet {
+            println!("rm '{}'", display(&entry.path));
+        }
+        if dry_run {
+            continue;
+        }
+        index.remove(&entry.path);
+        if !cached {
+            let path = worktree_path(&entry.path);
+            std::fs::remove_file(&path)
+                .ignore(ErrorKind::NotFound, ())
+                .with_context(|| format!("git rm: '{}'", path.display()))?;
+            // like git, also remove the directories left empty
+            let mut dir = path.parent();
+            while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+                if std::fs::remove_dir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+    }
+
+    if !dry_run {
+        index.save(repo)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateIndex {
+    pub add: bool,
+    pub remove: bool,
+    pub force_remove: bool,
+    pub refresh: bool,
+    pub info_only: bool,
+    pub cacheinfo: Vec<String>,
+    /// `+x` or `-x`
+    pub chmod: Option<String>,
+    pub index_version: Option<u32>,
+}
+
+impl UpdateIndex {
+    pub fn run(&self, repo: &Repository, files: &[String]) -> anyhow::Result<ExitCode> {
+        let mut index = Index::load(repo)?;
+        let mut code = ExitCode::SUCCESS;
+
+        if let Some(version) = self.index_version {
+            index.set_version(version)?;
+        }
+
+        for info in &self.cacheinfo {
+            let mut parts = info.splitn(3, ',');
+            let (Some(mode), Some(hash), Some(path)) = (parts.next(), parts.next(), parts.next())
+            else {
+                bail!("option 'cacheinfo' expects <mode>,<sha1>,<path>");
+            };
+            let mode = u32::from_str_radix(mode, 8).context("invalid mode")?;
+            let hash: Hash = hash.parse().context("invalid object name")?;
+            let path = Pathspec::new(&[path]).spec(0).to_vec();
+            if index.get(&path).is_none() && !self.add {
+                bail!(
+                    "{}: cannot add to the index - missing --add option?",
+                    display(&path)
+                );
+            }
+            index.add(IndexEntry::from_cacheinfo(mode, hash, path));
+        }
+
+        let chmod = match self.chmod.as_deref() {
+            None => None,
+            Some("+x") => Some(0o100755),
+            Some("-x") => Some(0o100644),
+            Some(other) => bail!("option 'chmod' expects \"+x\" or \"-x\", not {other}"),
+        };
+
+        for file in files {
+            let path = Pathspec::new(&[file]).spec(0).to_vec();
+            if self.force_remove {
+                index.remove(&path);
+                continue;
+            }
+            match std::fs::symlink_metadata(worktree_path(&path)) {
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    if !self.remove {
+                        bail!("{file}: does not exist and --remove not passed");
+                    }
+                    index.remove(&path);
+                    continue;
+                }
+                Err(e) => Err(e)?,
+                Ok(metadata) if metadata.is_dir() => {
+                    bail!("{file}: is a directory - add files inside instead");
+                }
+                Ok(_) => {}
+            }
+            if index.get(&path).is_none() && !self.add {
+                bail!("{file}: cannot add to the index - missing --add option?");
+            }
+            index.add(IndexEntry::from_worktree(repo, &path, !self.info_only)?);
+
+            if let Some(mode) = chmod {
+                let entry = index.get_mut(&path).expect("was just added");
+                if entry.mode & 0o170000 == 0o100000 {
+                    entry.mode = mode;
+                } else {
+                    bail!(
+                        "git update-index: cannot chmod {} '{file}'",
+                        self.chmod.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+        }
+
+        if self.refresh {
+            for entry in index.entries().to_vec() {
+                if entry.stage() != 0 {
+                    println!("{}: needs merge", display(&entry.path));
+                    code = ExitCode::FAILURE;
+                    continue;
+                }
+                if entry.assume_valid() || entry.skip_worktree() {
+                    continue;
+                }
+                let metadata = match std::fs::symlink_metadata(worktree_path(&entry.path)) {
+                    Ok(metadata) => metadata,
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        println!("{}: needs update", display(&entry.path));
+                        code = ExitCode::FAILURE;
+                        continue;
+                    }
+                    Err(e) => Err(e)?,
+                };
+                if index.is_modified(repo, &entry, &metadata)? {
+                    println!("{}: needs update", display(&entry.path));
+                    code = ExitCode::FAILURE;
+                } else if !entry.stat_matches(&metadata) {
+                    index
+                        .get_mut(&entry.path)
+                        .expect("entry is in the index")
+                        .update_stat(&metadata);
+                }
+            }
+        }
+
+        index.save(repo)?;
+        Ok(code)
+    }
+}
@@ -0,0 +1,696 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    io::{stdout, BufWriter, Write},
+};
+
+use crate::{
+    hash::Hash,
+    object::{Commit, Object, DIRECTORY},
+    pathspec::Pathspec,
+    repository::Repository,
+    revision, BlobType,
+};
+
+/// how many more commits the walk goes through once only excluded ones are left, in case
+/// commits with skewed dates are still to be excluded, like git's `SLOP`
+const SLOP: usize = 5;
+
+/// commits by committer date, newest first, and in the order they were added among equals
+pub struct DateQueue {
+    heap: BinaryHeap<(i64, Reverse<usize>, Hash)>,
+    added: usize,
+}
+
+impl DateQueue {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            added: 0,
+        }
+    }
+
+    pub fn push(&mut self, hash: Hash, commit: &Commit) {
+        let time = commit.committer().timestamp();
+        self.heap.push((time, Reverse(self.added), hash));
+        self.added += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<Hash> {
+        self.heap.pop().map(|(_, _, hash)| hash)
+    }
+
+    /// the committer date of the commit popped next
+    fn peek_time(&self) -> Option<i64> {
+        self.heap.peek().map(|(time, _, _)| *time)
+    }
+
+    /// the queued commits, in no particular order
+    fn hashes(&self) -> impl Iterator<Item = &Hash> {
+        self.heap.iter().map(|(_, _, hash)| hash)
+    }
+}
+
+/// the order commits are listed in, children always come before their parents except by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// newest first, as they are found
+    #[default]
+    Default,
+    /// newest first among the commits whose children are all listed
+    Date,
+    /// the history of each line of development together
+    Topo,
+}
+
+/// what `rev-list` and friends walk and list
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub order: Order,
+    /// lists at most this many commits
+    pub max_count: Option<usize>,
+    /// lists the oldest commit first, after the others options are applied
+    pub reverse: bool,
+    /// leaves out commits older than this timestamp, and their history
+    pub since: Option<i64>,
+    /// leaves out commits newer than this timestamp
+    pub until: Option<i64>,
+    /// only follows the first parent of merges
+    pub first_parent: bool,
+    /// only lists merges for `Some(true)`, or no merges for `Some(false)`
+    pub merges: Option<bool>,
+    /// only lists commits that descend from an excluded commit
+    pub ancestry_path: bool,
+    /// only lists commits that change these paths, following only a parent that has the
+    /// same content at them where there is one
+    pub paths: Pathspec,
+}
+
+/// the commits a walk lists, and their parents among them
+#[derive(Debug, Default)]
+pub struct History {
+    pub commits: Vec<Hash>,
+    /// the parents of listed commits that are not excluded, or their nearest such ancestors
+    /// that change the paths in place of commits that do not
+    pub parents: HashMap<Hash, Vec<Hash>>,
+    /// the excluded parents of the commits the walk went through
+    pub boundary: Vec<Hash>,
+}
+
+/// the commits a commit-or-stack queue holds, depending on the order
+enum Queue {
+    Date(DateQueue),
+    Stack(Vec<Hash>),
+}
+
+impl Queue {
+    fn push(&mut self, hash: Hash, commit: &Commit) {
+        match self {
+            Queue::Date(queue) => queue.push(hash, commit),
+            Queue::Stack(stack) => stack.push(hash),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Hash> {
+        match self {
+            Queue::Date(queue) => queue.pop(),
+            Queue::Stack(stack) => stack.pop(),
+        }
+    }
+}
+
+/// reorders `list` so that commits come before their parents, like git's
+/// `sort_in_topological_order`
+fn sort(
+    list: Vec<Hash>,
+    commits: &HashMap<Hash, Commit>,
+    parents: &HashMap<Hash, Vec<Hash>>,
+    order: Order,
+) -> Vec<Hash> {
+    // one more than the number of listed children, zero once the commit is listed
+    let mut indegree: HashMap<Hash, usize> = list.iter().map(|hash| (hash.clone(), 1)).collect();
+    for hash in &list {
+        for parent in &parents[hash] {
+            if let Some(n) = indegree.get_mut(parent) {
+                *n += 1;
+            }
+        }
+    }
+    let mut queue = match order {
+        Order::Date => Queue::Date(DateQueue::new()),
+        _ => Queue::Stack(vec![]),
+    };
+    let tips = list.iter().filter(|&hash| indegree[hash] == 1);
+    match &mut queue {
+        // the tips are taken from the end, but should come in the order they were found
+        Queue::Stack(stack) => stack.extend(tips.rev().cloned()),
+        Queue::Date(_) => {
+            for hash in tips {
+                queue.push(hash.clone(), &commits[hash]);
+            }
+        }
+    }
+
+    let mut sorted = Vec::with_capacity(list.len());
+    while let Some(hash) = queue.pop() {
+        for parent in &parents[&hash] {
+            match indegree.get_mut(parent) {
+                Some(n) if *n > 1 => {
+                    *n -= 1;
+                    if *n == 1 {
+                        queue.push(parent.clone(), &commits[parent]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        indegree.insert(hash.clone(), 0);
+        sorted.push(hash);
+    }
+    sorted
+}
+
+/// the entries of `tree` that `paths` match, with whole subtrees where a spec names a
+/// directory, to tell whether two commits differ at the paths
+fn matched_entries(
+    repo: &Repository,
+    tree: &Hash,
+    paths: &Pathspec,
+) -> anyhow::Result<Vec<(Vec<u8>, u32, Hash)>> {
+    let mut matched = vec![];
+    let mut trees = vec![(tree.clone(), vec![])];
+    while let Some((hash, prefix)) = trees.pop() {
+        let Object::Tree(tree) = repo.read_object(&hash)? else {
+            anyhow::bail!("{hash} is not a tree");
+        };
+        for (name, mode, hash) in tree.entries() {
+            let path = match prefix.is_empty() {
+                true => name.to_vec(),
+                false => [prefix.as_slice(), name].join(&b'/'),
+            };
+            if paths.matches(&path) {
+                matched.push((path, mode, hash.clone()));
+                continue;
+            }
+            // a directory is only entered if a spec may match inside of it
+            let inside = (0..paths.len()).any(|i| {
+                let spec = paths.spec(i);
+                spec.starts_with(&path) && spec.get(path.len()) == Some(&b'/')
+                    || spec.iter().any(|c| b"*?[\\".contains(c))
+            });
+            if mode == DIRECTORY && inside {
+                trees.push((hash.clone(), path));
+            }
+        }
+    }
+    matched.sort();
+    Ok(matched)
+}
+
+/// marks commits as excluded, and the ancestors of them the walk already read, like git's
+/// `mark_parents_uninteresting`
+fn hide(uninteresting: &mut HashSet<Hash>, commits: &HashMap<Hash, Commit>, hashes: &[Hash]) {
+    let mut stack = hashes.to_vec();
+    while let Some(hash) = stack.pop() {
+        if !uninteresting.insert(hash.clone()) {
+            continue;
+        }
+        if let Some(commit) = commits.get(&hash) {
+            stack.extend(commit.parents().iter().cloned());
+        }
+    }
+}
+
+/// the commits reachable from `include` but not from `exclude`, in the order `options` ask for
+pub fn history(
+    repo: &Repository,
+    include: &[Hash],
+    exclude: &[Hash],
+    options: &WalkOptions,
+) -> anyhow::Result<History> {
+    let parents = |commit: &Commit| -> Vec<Hash> {
+        match options.first_parent {
+            true => commit.parents().iter().take(1).cloned().collect(),
+            false => commit.parents().to_vec(),
+        }
+    };
+
+    let mut entries = HashMap::new();
+    let mut matched = |hash: &Hash, commit: &Commit| -> anyhow::Result<Vec<(Vec<u8>, u32, Hash)>> {
+        if let Some(matched) = entries.get(hash) {
+            return Ok(Clone::clone(matched));
+        }
+        let matched = matched_entries(repo, commit.tree(), &options.paths)?;
+        entries.insert(hash.clone(), matched.clone());
+        Ok(matched)
+    };
+
+    // without anything that needs the whole history, the walk stops once enough is listed.
+    // excluded commits may still turn out to hide listed ones until the end
+    let limit = match options.order == Order::Default
+        && options.until.is_none()
+        && options.merges.is_none()
+        && !options.ancestry_path
+        && options.paths.is_empty()
+        && exclude.is_empty()
+    {
+        true => options.max_count,
+        false => None,
+    };
+
+    let mut commits: HashMap<Hash, Commit> = HashMap::new();
+    // excluded commits are walked along with the others, and all their history is left out,
+    // not only along first parents
+    let mut uninteresting: HashSet<Hash> = exclude.iter().cloned().collect();
+    let mut queue = DateQueue::new();
+    let mut next: Vec<Hash> = include.iter().chain(exclude).cloned().collect();
+    let mut slop = SLOP;
+    let mut list = vec![];
+    // the parents the walk went on to, for all commits it went through
+    let mut followed: HashMap<Hash, Vec<Hash>> = HashMap::new();
+    // the parents commits are sorted by, which are all of them unless the paths simplified them
+    let mut sort_parents: HashMap<Hash, Vec<Hash>> = HashMap::new();
+    // the commits left out because they do not change the paths
+    let mut same = HashSet::new();
+    loop {
+        for hash in next.drain(..) {
+            if commits.contains_key(&hash) {
+                continue;
+            }
+            let commit = repo.read_commit(&hash)?;
+            queue.push(hash.clone(), &commit);
+            commits.insert(hash, commit);
+        }
+        if limit.is_some_and(|limit| list.len() >= limit) {
+            break;
+        }
+        let Some(hash) = queue.pop() else {
+            break;
+        };
+        let commit = &commits[&hash];
+        if uninteresting.contains(&hash) {
+            hide(&mut uninteresting, &commits, commit.parents());
+            next = commit.parents().to_vec();
+            // like git's `still_interesting`
+            let time = commit.committer().timestamp();
+            if queue.hashes().any(|hash| !uninteresting.contains(hash))
+                || queue.peek_time().is_some_and(|next| time <= next)
+            {
+                slop = SLOP;
+            } else {
+                slop -= 1;
+                if slop == 0 {
+                    break;
+                }
+            }
+            continue;
+        }
+        if options
+            .since
+            .is_some_and(|since| commit.committer().timestamp() < since)
+        {
+            continue;
+        }
+        next = parents(commit);
+        let mut simplified = None;
+        if !options.paths.is_empty() {
+            let ours = matched(&hash, commit)?;
+            let mut treesame = None;
+            for parent in &next {
+                let theirs = matched(parent, &repo.read_commit(parent)?)?;
+                if ours == theirs {
+                    treesame = Some(parent.clone());
+                    break;
+                }
+            }
+            match treesame {
+                // the history of the other parents does not matter for the paths
+                Some(parent) => {
+                    next = vec![parent.clone()];
+                    simplified = Some(parent);
+                    same.insert(hash.clone());
+                }
+                None if next.is_empty() && ours.is_empty() => {
+                    same.insert(hash.clone());
+                }
+                None => {}
+            }
+        }
+        let all_parents = match simplified {
+            Some(parent) => vec![parent],
+            None => commit.parents().to_vec(),
+        };
+        sort_parents.insert(hash.clone(), all_parents);
+        followed.insert(hash.clone(), next.clone());
+        list.push(hash);
+    }
+    // commits whose dates made them come before a descendant of theirs that was excluded
+    list.retain(|hash| !uninteresting.contains(hash));
+    followed.retain(|hash, _| !uninteresting.contains(hash));
+
+    let mut boundary = vec![];
+    for hash in &list {
+        for parent in commits[hash].parents() {
+            if uninteresting.contains(parent) && !boundary.contains(parent) {
+                boundary.push(parent.clone());
+            }
+        }
+    }
+
+    // parents are rewritten past the commits that are left out
+    let mut rewritten: HashMap<Hash, Option<Hash>> = HashMap::new();
+    let mut rewrite = |parent: &Hash| -> Option<Hash> {
+        let mut chain = vec![];
+        let mut current = Some(parent.clone());
+        let found = loop {
+            let Some(hash) = current else {
+                break None;
+            };
+            if let Some(known) = rewritten.get(&hash) {
+                break known.clone();
+            }
+            if !followed.contains_key(&hash) {
+                break None;
+            }
+            if !same.contains(&hash) {
+                break Some(hash);
+            }
+            current = followed[&hash].first().cloned();
+            chain.push(hash);
+        };
+        for hash in chain {
+            rewritten.insert(hash, found.clone());
+        }
+        found
+    };
+    let mut history_parents = HashMap::new();
+    for hash in list.iter().filter(|hash| !same.contains(hash)) {
+        let mut rewritten: Vec<Hash> = vec![];
+        for parent in &followed[hash] {
+            if let Some(parent) = rewrite(parent) {
+                if !rewritten.contains(&parent) {
+                    rewritten.push(parent);
+                }
+            }
+        }
+        history_parents.insert(hash.clone(), rewritten);
+    }
+
+    // like git, commits newer than `until` are left out before sorting, so they do not hold
+    // back their parents
+    if let Some(until) = options.until {
+        list.retain(|hash| commits[hash].committer().timestamp() <= until);
+    }
+
+    if options.ancestry_path {
+        let mut on_path: HashSet<Hash> = exclude.iter().cloned().collect();
+        // parents usually come after their children, but not always
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for hash in list.iter().rev() {
+                if !on_path.contains(hash)
+                    && parents(&commits[hash]).iter().any(|p| on_path.contains(p))
+                {
+                    on_path.insert(hash.clone());
+                    changed = true;
+                }
+            }
+        }
+        list.retain(|hash| on_path.contains(hash));
+    }
+
+    // like git, the commits left out for the paths are sorted too, and only dropped after
+    if options.order != Order::Default {
+        list = sort(list, &commits, &sort_parents, options.order);
+    }
+    list.retain(|hash| !same.contains(hash));
+
+    let mut list: Vec<Hash> = list
+        .into_iter()
+        .filter(|hash| {
+            let commit = &commits[hash];
+            let merge = commit.parents().len() > 1;
+            options.merges.is_none_or(|merges| merges == merge)
+        })
+        .take(options.max_count.unwrap_or(usize::MAX))
+        .collect();
+    if options.reverse {
+        list.reverse();
+    }
+    let listed: HashSet<&Hash> = list.iter().collect();
+    history_parents.retain(|hash, _| listed.contains(hash));
+    Ok(History {
+        commits: list,
+        parents: history_parents,
+        boundary,
+    })
+}
+
+/// an object found while walking history, with the path it was found at for trees and blobs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    pub hash: Hash,
+    pub typ: BlobType,
+    /// empty for commits and the trees of commits, the name of tags
+    pub path: String,
+}
+
+/// walks the trees of commits and the objects named directly, recording each object only once
+struct Walk<'a> {
+    repo: &'a Repository,
+    seen: HashSet<Hash>,
+    found: Vec<Found>,
+    /// whether found objects are recorded or only marked as seen
+    record: bool,
+}
+
+impl Walk<'_> {
+    fn new(repo: &Repository) -> Walk<'_> {
+        Walk {
+            repo,
+            seen: HashSet::new(),
+            found: vec![],
+            record: true,
+        }
+    }
+
+    fn visit(&mut self, hash: &Hash, typ: BlobType, path: &str) -> bool {
+        if !self.seen.insert(hash.clone()) {
+            return false;
+        }
+        if self.record {
+            self.found.push(Found {
+                hash: hash.clone(),
+                typ,
+                path: path.to_owned(),
+            });
+        }
+        true
+    }
+
+    /// marks excluded trees and blobs as seen, and the trees of the excluded commits at the
+    /// boundary of the walk, like git's `mark_edges_uninteresting`. the rest of the excluded
+    /// history is not read
+    fn hide(&mut self, exclude: &[Hash], boundary: &[Hash]) -> anyhow::Result<()> {
+        self.record = false;
+        for hash in exclude {
+            let mut hash = hash.clone();
+            loop {
+                match self.repo.read_object(&hash)? {
+                    Object::Tag(tag) => {
+                        self.visit(&hash, BlobType::Tag, "");
+                        hash = tag.object().clone();
+                        continue;
+                    }
+                    Object::Tree(_) => self.tree(&hash, "")?,
+                    Object::Blob(_) => {
+                        self.visit(&hash, BlobType::Blob, "");
+                    }
+                    Object::Commit(_) => {}
+                }
+                break;
+            }
+        }
+        for hash in boundary {
+            let tree = self.repo.read_commit(hash)?.tree().clone();
+            self.tree(&tree, "")?;
+        }
+        self.record = true;
+        Ok(())
+    }
+
+    /// the objects named directly first, like `git rev-list --objects`, then the trees of the
+    /// commits
+    fn objects(&mut self, named: Vec<Named>, commits: &[Hash]) -> anyhow::Result<()> {
+        let mut trees = vec![];
+        for (hash, typ, name) in named {
+            match typ {
+                BlobType::Tree => trees.push((hash, name)),
+                typ => {
+                    self.visit(&hash, typ, &name);
+                }
+            }
+        }
+        for hash in commits {
+            trees.push((self.repo.read_commit(hash)?.tree().clone(), String::new()));
+        }
+        for (hash, path) in trees {
+            self.tree(&hash, &path)?;
+        }
+        Ok(())
+    }
+
+    /// depth first in the order of the entries, like git
+    fn tree(&mut self, hash: &Hash, path: &str) -> anyhow::Result<()> {
+        if !self.visit(hash, BlobType::Tree, path) {
+            return Ok(());
+        }
+        let Object::Tree(tree) = self.repo.read_object(hash)? else {
+            anyhow::bail!("{hash} is not a tree");
+        };
+        for (name, typ, child) in tree.children() {
+            let name = String::from_utf8_lossy(name);
+            let child_path = match path.is_empty() {
+                true => name.into_owned(),
+                false => format!("{path}/{name}"),
+            };
+            match typ {
+                BlobType::Tree => self.tree(child, &child_path)?,
+                typ => {
+                    self.visit(child, typ, &child_path);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a tag, tree or blob named directly, with the name of tags
+type Named = (Hash, BlobType, String);
+
+/// splits revisions into the commits they are or peel to, and the other objects on the way
+fn split_tips(repo: &Repository, include: &[Hash]) -> anyhow::Result<(Vec<Hash>, Vec<Named>)> {
+    let mut tips = vec![];
+    let mut named = vec![];
+    for hash in include {
+        let mut hash = hash.clone();
+        loop {
+            match repo.read_object(&hash)? {
+                Object::Commit(_) => tips.push(hash),
+                Object::Tag(tag) => {
+                    named.push((hash, BlobType::Tag, tag.name().to_owned()));
+                    hash = tag.object().clone();
+                    continue;
+                }
+                object => named.push((hash, object.typ(), String::new())),
+            }
+            break;
+        }
+    }
+    Ok((tips, named))
+}
+
+/// the commits excluded revisions peel to
+fn bottoms(repo: &Repository, exclude: &[Hash]) -> Vec<Hash> {
+    exclude
+        .iter()
+        .filter_map(|hash| revision::peel(repo, "", hash.clone(), BlobType::Commit).ok())
+        .collect()
+}
+
+/// the objects reachable from `include` but not from `exclude`, the commits first
+pub fn objects(
+    repo: &Repository,
+    include: &[Hash],
+    exclude: &[Hash],
+) -> anyhow::Result<Vec<Found>> {
+    let (tips, named) = split_tips(repo, include)?;
+    let history = history(
+        repo,
+        &tips,
+        &bottoms(repo, exclude),
+        &WalkOptions::default(),
+    )?;
+    let mut walk = Walk::new(repo);
+    walk.hide(exclude, &history.boundary)?;
+    for hash in &history.commits {
+        walk.visit(hash, BlobType::Commit, "");
+    }
+    walk.objects(named, &history.commits)?;
+    Ok(walk.found)
+}
+
+/// the `rev-list` command
+#[derive(Debug)]
+pub struct RevList {
+    pub options: WalkOptions,
+    /// also lists tags, trees and blobs, after the commits
+    pub objects: bool,
+    /// only prints the number of commits
+    pub count: bool,
+    /// starts from all refs and HEAD
+    pub all: bool,
+}
+
+impl RevList {
+    pub fn run(&self, repo: &Repository, revs: &[String]) -> anyhow::Result<()> {
+        let mut include = vec![];
+        let mut exclude = vec![];
+        if self.all {
+            include.extend(revision::all_tips(repo)?);
+        }
+        for rev in revs {
+            let (positive, negative) = revision::range(repo, rev)?;
+            include.extend(positive);
+            exclude.extend(negative);
+        }
+
+        // tags and trees named directly are listed with --objects, before the commits' trees
+        let (tips, named) = split_tips(repo, &include)?;
+        let history = history(repo, &tips, &bottoms(repo, &exclude), &self.options)?;
+        let commits = history.commits;
+        let mut out = BufWriter::new(stdout().lock());
+        if self.count {
+            writeln!(out, "{}", commits.len())?;
+            return Ok(out.flush()?);
+        }
+        for hash in &commits {
+            writeln!(out, "{hash}")?;
+        }
+        if self.objects {
+            let mut walk = Walk::new(repo);
+            walk.hide(&exclude, &history.boundary)?;
+            walk.objects(named, &commits)?;
+            for found in walk.found {
+                writeln!(out, "{} {}", found.hash, found.path)?;
+            }
+        }
+        Ok(out.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_queue() {
+        let commit = |time: i64| {
+            let raw = format!(
+                "tree {}\nauthor A <a@b> {time} +0000\ncommitter A <a@b> {time} +0000\n\nmessage\n",
+                "0".repeat(40)
+            );
+            let raw = format!("commit {}\0{raw}", raw.len());
+            Commit::try_from(raw.as_bytes()).unwrap()
+        };
+        let hash = |n: u32| format!("{n:040}").parse::<Hash>().unwrap();
+        let mut queue = DateQueue::new();
+        queue.push(hash(1), &commit(10));
+        queue.push(hash(2), &commit(20));
+        queue.push(hash(3), &commit(10));
+        queue.push(hash(4), &commit(30));
+        let order: Vec<Hash> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, [hash(4), hash(2), hash(1), hash(3)]);
+    }
+}
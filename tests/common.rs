@@ -43,9 +43,18 @@ impl Temp {
         self.inner.path().join(path.as_ref())
     }
 
+    /// our git with the identity and dates of [`Temp::real_git`]
     pub fn git(&self) -> Command {
         let mut cmd = Command::cargo_bin("git-rs").expect("failed to compile command");
         cmd.current_dir(self.path());
+        with_identity(&mut cmd);
+        cmd
+    }
+
+    /// real git with a fixed identity and dates, so that hashes are reproducible
+    pub fn real_git(&self) -> Command {
+        let mut cmd = self.cmd("git");
+        with_identity(&mut cmd);
         cmd
     }
 
@@ -56,6 +65,15 @@ impl Temp {
     }
 }
 
+fn with_identity(cmd: &mut Command) {
+    cmd.env("GIT_AUTHOR_NAME", "A U Thor")
+        .env("GIT_AUTHOR_EMAIL", "author@example.com")
+        .env("GIT_AUTHOR_DATE", "1112911993 +0530")
+        .env("GIT_COMMITTER_NAME", "C O Mitter")
+        .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+        .env("GIT_COMMITTER_DATE", "1112912053 -0700");
+}
+
 impl Default for Temp {
     fn default() -> Self {
         Self::new()
@@ -1,9 +1,12 @@
 use assert_cmd::prelude::*;
+use assert_cmd::Command as AssertCommand;
 use common::make_dir;
-use predicates::prelude::predicate;
+use predicates::prelude::{predicate, PredicateBooleanExt};
 use std::{
     fs::{create_dir, File},
     io::Write,
+    os::unix::fs::PermissionsExt,
+    path::Path,
     process::Command,
 };
 
@@ -174,6 +177,7 @@ fn write_tree() -> anyhow::Result<()> {
     File::create(dir.subpath("dir2/file2"))?;
     File::create(dir.subpath("dir2/other_file2"))?;
 
+    dir.git().args(["add", "."]).assert().success();
     dir.git().args(["write-tree"]).assert().success();
 
     for f in walkdir::WalkDir::new(dir.subpath(".git/objects")) {
@@ -207,3 +211,1696 @@ fn write_tree() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn write_tree_options() -> anyhow::Result<()> {
+    let dir = worktree()?;
+    dir.cmd("git").args(["add", "."]).status()?;
+    let expected = real_output(&dir, &["write-tree"])?;
+    let prefix = real_output(&dir, &["write-tree", "--prefix=src/"])?;
+
+    dir.git()
+        .args(["write-tree"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(expected.clone()));
+    dir.git()
+        .args(["write-tree", "--prefix=src"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(prefix));
+    dir.git()
+        .args(["write-tree", "--prefix=missing/"])
+        .assert()
+        .failure();
+
+    // the walk also picks up ignored files
+    dir.git()
+        .args(["write-tree", "--from-worktree"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(expected).not());
+
+    let blob = "2e65efe2a145dda7ee51d1741299f848e5bf752e";
+    dir.cmd("git")
+        .args(["update-index", "--add", "--cacheinfo"])
+        .arg(format!("100644,{blob},virtual"))
+        .status()?;
+    dir.git().args(["write-tree"]).assert().failure();
+    dir.git()
+        .args(["write-tree", "--missing-ok"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(real_output(
+            &dir,
+            &["write-tree", "--missing-ok"],
+        )?));
+
+    Ok(())
+}
+
+#[test]
+fn cat_file_pretty_tree_and_commit() -> anyhow::Result<()> {
+    let dir = make_dir();
+    dir.git().arg("init").assert().success();
+
+    let real = make_dir();
+    create_dir(real.subpath("dir1"))?;
+    writeln!(File::create(real.subpath("file0"))?, "hello")?;
+    writeln!(File::create(real.subpath("dir1/file1"))?, "world")?;
+    real.cmd("git").arg("init").silence().status()?;
+    real.cmd("git").args(["add", "."]).silence().status()?;
+    real.real_git()
+        .args(["commit", "-m", "initial\n\nwith a body"])
+        .silence()
+        .status()?;
+    real.cmd("cp")
+        .args(["-r", ".git/objects"])
+        .arg(dir.subpath(".git"))
+        .status()?;
+
+    for object in ["HEAD", "HEAD^{tree}"] {
+        let hash = real.cmd("git").args(["rev-parse", object]).output()?.stdout;
+        let hash = String::from_utf8(hash)?.trim_end().to_owned();
+        let expected = real.cmd("git").args(["cat-file", "-p", &hash]).output()?;
+
+        dir.git()
+            .args(["cat-file", "-p", &hash])
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(String::from_utf8(expected.stdout)?));
+    }
+
+    Ok(())
+}
+
+/// a repository created by real git, with a single commit of two files
+fn repo_with_commit() -> anyhow::Result<common::Temp> {
+    let dir = make_dir();
+    create_dir(dir.subpath("dir1"))?;
+    writeln!(File::create(dir.subpath("file0"))?, "hello")?;
+    writeln!(File::create(dir.subpath("dir1/file1"))?, "world")?;
+    dir.cmd("git").arg("init").silence().status()?;
+    dir.cmd("git").args(["add", "."]).silence().status()?;
+    dir.real_git()
+        .args(["commit", "-m", "initial"])
+        .silence()
+        .status()?;
+    Ok(dir)
+}
+
+/// a repository created by real git, with a file that grows over 20 more commits, so that
+/// packs of it have deltas
+fn repo_with_history() -> anyhow::Result<common::Temp> {
+    let dir = repo_with_commit()?;
+    for i in 0..20 {
+        let lines: String = (0..i * 40).map(|n| format!("line {n}\n")).collect();
+        std::fs::write(dir.subpath("file0"), lines)?;
+        dir.real_git()
+            .args(["commit", "-qam", &format!("change {i}")])
+            .status()?;
+    }
+    Ok(dir)
+}
+
+fn real_output(dir: &common::Temp, args: &[&str]) -> anyhow::Result<String> {
+    let output = dir.real_git().args(args).output()?;
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn mktag() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let tag = format!(
+        "object {head}type commit\ntag v1.0\ntagger C O Mitter <committer@example.com> 1112912053 -0700\n\nrelease\n",
+    );
+
+    let expected = AssertCommand::from_std(dir.cmd("git"))
+        .arg("mktag")
+        .write_stdin(tag.as_bytes())
+        .output()?
+        .stdout;
+
+    AssertCommand::from_std(dir.git())
+        .arg("mktag")
+        .write_stdin(tag.as_bytes())
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(String::from_utf8(expected)?));
+
+    let wrong_type = tag.replace("type commit", "type tree");
+    AssertCommand::from_std(dir.git())
+        .arg("mktag")
+        .write_stdin(wrong_type.as_bytes())
+        .assert()
+        .failure();
+
+    let no_tagger = format!("object {head}type commit\ntag v1.0\n\nrelease\n");
+    AssertCommand::from_std(dir.git())
+        .arg("mktag")
+        .write_stdin(no_tagger.as_bytes())
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn tag() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+
+    dir.git().args(["tag", "light"]).assert().success();
+    common::file_contents(
+        dir.subpath(".git/refs/tags/light"),
+        predicate::eq(head.as_str()),
+    );
+    dir.git().args(["tag", "light"]).assert().failure();
+
+    dir.git()
+        .args(["tag", "-a", "v1.0", "-m", "release"])
+        .assert()
+        .success();
+    assert_eq!(real_output(&dir, &["cat-file", "-t", "v1.0"])?, "tag\n");
+    assert_eq!(real_output(&dir, &["rev-parse", "v1.0^{commit}"])?, head);
+    assert_eq!(
+        real_output(&dir, &["tag", "-l", "--format=%(contents)", "v1.0"])?,
+        "release\n\n"
+    );
+
+    dir.git()
+        .args(["tag", "-l", "v*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("v1.0\n"));
+    dir.git().args(["tag", "-d", "light"]).assert().success();
+    dir.git()
+        .arg("tag")
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("v1.0\n"));
+
+    Ok(())
+}
+
+#[test]
+fn cat_file_modes() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    dir.real_git()
+        .args(["tag", "-a", "v1.0", "-m", "release"])
+        .status()?;
+    let tag = real_output(&dir, &["rev-parse", "v1.0"])?;
+    let tag = tag.trim_end();
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let head = head.trim_end();
+    let tree = real_output(&dir, &["rev-parse", "HEAD^{tree}"])?;
+    let tree = tree.trim_end();
+
+    for object in [tag, head, tree] {
+        for mode in ["-t", "-s", "-p"] {
+            dir.git()
+                .args(["cat-file", mode, object])
+                .assert()
+                .success()
+                .stdout(predicate::str::diff(real_output(
+                    &dir,
+                    &["cat-file", mode, object],
+                )?));
+        }
+    }
+
+    for (typ, object) in [
+        ("tag", tag),
+        ("commit", tag),
+        ("tree", head),
+        ("tree", tree),
+    ] {
+        let expected = dir.cmd("git").args(["cat-file", typ, object]).output()?;
+        dir.git()
+            .args(["cat-file", typ, object])
+            .assert()
+            .success()
+            .stdout(predicate::eq(expected.stdout));
+    }
+
+    dir.git()
+        .args(["cat-file", "blob", head])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn cat_file_batch() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let tree = real_output(&dir, &["rev-parse", "HEAD^{tree}"])?;
+    let input = format!(
+        "{head}{} with  rest\n{}\nnot-a-hash\n",
+        tree.trim_end(),
+        "1".repeat(40)
+    );
+
+    for args in [
+        vec!["--batch"],
+        vec!["--batch-check"],
+        vec!["--batch-check=%(objecttype) %(rest) %(objectname)"],
+        vec!["--batch=%(objectsize)"],
+        vec!["--batch-check", "--batch-all-objects"],
+        vec!["--batch", "--batch-all-objects"],
+    ] {
+        let expected = AssertCommand::from_std(dir.cmd("git"))
+            .arg("cat-file")
+            .args(&args)
+            .write_stdin(input.as_bytes())
+            .output()?;
+        AssertCommand::from_std(dir.git())
+            .arg("cat-file")
+            .args(&args)
+            .write_stdin(input.as_bytes())
+            .assert()
+            .success()
+            .stdout(predicate::eq(expected.stdout));
+    }
+
+    let commands = format!("info {head}contents {tree}");
+    let expected = AssertCommand::from_std(dir.cmd("git"))
+        .args(["cat-file", "--batch-command"])
+        .write_stdin(commands.as_bytes())
+        .output()?;
+    AssertCommand::from_std(dir.git())
+        .args(["cat-file", "--batch-command"])
+        .write_stdin(commands.as_bytes())
+        .assert()
+        .success()
+        .stdout(predicate::eq(expected.stdout));
+
+    Ok(())
+}
+
+#[test]
+fn hash_object_types() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let commit = dir
+        .cmd("git")
+        .args(["cat-file", "commit", "HEAD"])
+        .output()?
+        .stdout;
+    let tree = dir
+        .cmd("git")
+        .args(["cat-file", "tree", "HEAD"])
+        .output()?
+        .stdout;
+
+    AssertCommand::from_std(dir.git())
+        .args(["hash-object", "-t", "commit", "--stdin"])
+        .write_stdin(commit.clone())
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(head));
+    AssertCommand::from_std(dir.git())
+        .args(["hash-object", "-t", "tree", "--stdin"])
+        .write_stdin(commit.clone())
+        .assert()
+        .failure();
+    AssertCommand::from_std(dir.git())
+        .args(["hash-object", "-t", "bogus", "--stdin"])
+        .write_stdin(commit.clone())
+        .assert()
+        .failure();
+
+    for (typ, content) in [("tree", commit.clone()), ("bogus", tree)] {
+        let expected = AssertCommand::from_std(dir.cmd("git"))
+            .args(["hash-object", "--literally", "-t", typ, "--stdin"])
+            .write_stdin(content.clone())
+            .output()?
+            .stdout;
+        AssertCommand::from_std(dir.git())
+            .args(["hash-object", "--literally", "-t", typ, "--stdin"])
+            .write_stdin(content)
+            .assert()
+            .success()
+            .stdout(predicate::eq(expected));
+    }
+
+    let paths = "file0\ndir1/file1\n";
+    let expected = AssertCommand::from_std(dir.cmd("git"))
+        .args(["hash-object", "--stdin-paths"])
+        .write_stdin(paths)
+        .output()?
+        .stdout;
+    AssertCommand::from_std(dir.git())
+        .args(["hash-object", "--stdin-paths"])
+        .write_stdin(paths)
+        .assert()
+        .success()
+        .stdout(predicate::eq(expected));
+
+    Ok(())
+}
+
+/// a worktree with nested files, an executable and ignored build output
+fn worktree() -> anyhow::Result<common::Temp> {
+    let dir = make_dir();
+    dir.cmd("git").arg("init").silence().status()?;
+    create_dir(dir.subpath("src"))?;
+    create_dir(dir.subpath("target"))?;
+    writeln!(File::create(dir.subpath("README"))?, "readme")?;
+    writeln!(File::create(dir.subpath("src/main.rs"))?, "fn main() {{}}")?;
+    File::create(dir.subpath("src/lib.rs"))?;
+    writeln!(File::create(dir.subpath("src/main.o"))?, "binary")?;
+    writeln!(File::create(dir.subpath("target/out"))?, "build output")?;
+    writeln!(File::create(dir.subpath(".gitignore"))?, "/target/\n*.o")?;
+    let run = dir.subpath("run.sh");
+    writeln!(File::create(&run)?, "#!/bin/sh")?;
+    std::fs::set_permissions(run, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+    Ok(dir)
+}
+
+#[test]
+fn add() -> anyhow::Result<()> {
+    let real = worktree()?;
+    real.cmd("git").args(["add", "."]).status()?;
+    let expected = real_output(&real, &["ls-files", "--stage"])?;
+
+    let dir = worktree()?;
+    dir.git().args(["add", "."]).assert().success();
+    assert_eq!(real_output(&dir, &["ls-files", "--stage"])?, expected);
+    dir.real_git()
+        .args(["commit", "-m", "initial"])
+        .silence()
+        .status()?;
+
+    std::fs::remove_file(dir.subpath("README"))?;
+    writeln!(File::create(dir.subpath("src/lib.rs"))?, "pub mod x;")?;
+    writeln!(File::create(dir.subpath("new"))?, "new")?;
+    dir.git()
+        .args(["add", "-u", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("remove 'README'\nadd 'src/lib.rs'\n"));
+    dir.git().args(["add", "-u"]).assert().success();
+    assert_eq!(
+        real_output(&dir, &["status", "--porcelain"])?,
+        "D  README\nM  src/lib.rs\n?? new\n"
+    );
+
+    dir.git()
+        .args(["add", "src/main.o"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("ignored"));
+    dir.git().args(["add", "missing"]).assert().failure();
+    // like git, untracked files with -u and empty directories are skipped
+    create_dir(dir.subpath("empty"))?;
+    dir.git().args(["add", "-u", "new"]).assert().success();
+    dir.git().args(["add", "empty/"]).assert().success();
+    dir.git().args(["add", "-A"]).assert().success();
+    assert_eq!(
+        real_output(&dir, &["status", "--porcelain"])?,
+        "D  README\nA  new\nM  src/lib.rs\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rm() -> anyhow::Result<()> {
+    let dir = worktree()?;
+    dir.cmd("git").args(["add", "."]).status()?;
+    dir.real_git()
+        .args(["commit", "-m", "initial"])
+        .silence()
+        .status()?;
+
+    dir.git()
+        .args(["rm", "--cached", "README"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("rm 'README'\n"));
+    assert!(dir.subpath("README").exists());
+
+    dir.git().args(["rm", "src"]).assert().failure();
+    writeln!(File::create(dir.subpath("src/lib.rs"))?, "changed")?;
+    dir.git().args(["rm", "-r", "src"]).assert().failure();
+    dir.git()
+        .args(["rm", "-r", "-f", "src"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("rm 'src/lib.rs'\nrm 'src/main.rs'\n"));
+    assert!(!dir.subpath("src/lib.rs").exists());
+
+    assert_eq!(
+        real_output(&dir, &["status", "--porcelain"])?,
+        "D  README\nD  src/lib.rs\nD  src/main.rs\n?? README\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn update_index() -> anyhow::Result<()> {
+    let dir = worktree()?;
+    let blob = "2e65efe2a145dda7ee51d1741299f848e5bf752e";
+
+    dir.git()
+        .args(["update-index", "README"])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["update-index", "--add", "README", "src/main.rs"])
+        .assert()
+        .success();
+    dir.git()
+        .args(["update-index", "--chmod=+x", "README"])
+        .assert()
+        .success();
+    dir.git()
+        .args([
+            "update-index",
+            "--cacheinfo",
+            &format!("100644,{blob},virtual"),
+        ])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["update-index", "--add", "--cacheinfo"])
+        .arg(format!("100644,{blob},virtual"))
+        .assert()
+        .success();
+
+    let readme = real_output(&dir, &["hash-object", "README"])?;
+    let main = real_output(&dir, &["hash-object", "src/main.rs"])?;
+    assert_eq!(
+        real_output(&dir, &["ls-files", "--stage"])?,
+        format!(
+            "100755 {} 0\tREADME\n100644 {} 0\tsrc/main.rs\n100644 {blob} 0\tvirtual\n",
+            readme.trim_end(),
+            main.trim_end()
+        )
+    );
+
+    std::fs::remove_file(dir.subpath("src/main.rs"))?;
+    dir.git()
+        .args(["update-index", "--refresh"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::diff(
+            "README: needs update\nsrc/main.rs: needs update\nvirtual: needs update\n",
+        ));
+    dir.git()
+        .args(["update-index", "--remove", "src/main.rs"])
+        .assert()
+        .success();
+    dir.git()
+        .args(["update-index", "--force-remove", "virtual"])
+        .assert()
+        .success();
+    assert_eq!(real_output(&dir, &["ls-files"])?, "README\n");
+
+    Ok(())
+}
+
+#[test]
+fn refs() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let head = head.trim_end();
+    let tree = real_output(&dir, &["rev-parse", "HEAD^{tree}"])?;
+    let zero = "0".repeat(40);
+
+    dir.git()
+        .args(["update-ref", "refs/heads/feature", head, &zero])
+        .assert()
+        .success();
+    dir.git()
+        .args(["update-ref", "refs/heads/feature", head, &zero])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["update-ref", "refs/heads/tree", tree.trim_end()])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["symbolic-ref", "HEAD", "refs/heads/feature"])
+        .assert()
+        .success();
+    dir.git()
+        .args(["symbolic-ref", "--short", "HEAD"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("feature\n"));
+
+    // one failing command leaves every ref untouched
+    AssertCommand::from_std(dir.git())
+        .args(["update-ref", "--stdin"])
+        .write_stdin(format!(
+            "create refs/heads/new {head}\ndelete refs/heads/feature {zero}1\n"
+        ))
+        .assert()
+        .failure();
+    AssertCommand::from_std(dir.git())
+        .args(["update-ref", "--stdin"])
+        .write_stdin(format!(
+            "create refs/heads/new {head}\ndelete refs/heads/feature {}\n",
+            tree.trim_end()
+        ))
+        .assert()
+        .failure();
+    assert!(!dir.subpath(".git/refs/heads/new").exists());
+    assert!(!dir.subpath(".git/refs/heads/feature.lock").exists());
+
+    AssertCommand::from_std(dir.git())
+        .args(["update-ref", "--stdin"])
+        .write_stdin(format!(
+            "create refs/heads/a/b {head}\nverify refs/heads/feature {head}\n"
+        ))
+        .assert()
+        .success();
+    dir.git()
+        .args(["update-ref", "-d", "refs/heads/a/b", head])
+        .assert()
+        .success();
+    assert!(!dir.subpath(".git/refs/heads/a").exists());
+
+    dir.real_git()
+        .args(["tag", "-a", "-m", "annotated", "v1"])
+        .silence()
+        .status()?;
+    let expected = dir
+        .cmd("git")
+        .args(["show-ref", "--head", "-d"])
+        .output()?
+        .stdout;
+    dir.git()
+        .args(["show-ref", "--head", "-d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(String::from_utf8(expected)?));
+    dir.git()
+        .args(["show-ref", "--verify", "refs/heads/missing"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn packed_refs() -> anyhow::Result<()> {
+    let setup = || -> anyhow::Result<common::Temp> {
+        let dir = repo_with_commit()?;
+        dir.real_git()
+            .args(["tag", "-a", "-m", "annotated", "v1"])
+            .silence()
+            .status()?;
+        dir.real_git().args(["tag", "light"]).silence().status()?;
+        dir.real_git()
+            .args(["branch", "feature"])
+            .silence()
+            .status()?;
+        Ok(dir)
+    };
+
+    let real = setup()?;
+    real.cmd("git").args(["pack-refs", "--all"]).status()?;
+    let expected = std::fs::read_to_string(real.subpath(".git/packed-refs"))?;
+
+    let dir = setup()?;
+    let refs = real_output(&dir, &["show-ref", "-d"])?;
+    dir.git()
+        .args(["pack-refs", "--all", "--prune"])
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(dir.subpath(".git/packed-refs"))?,
+        expected
+    );
+    assert!(!dir.subpath(".git/refs/tags/v1").exists());
+    dir.git()
+        .args(["show-ref", "-d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(refs));
+
+    // loose refs shadow packed ones, deleting removes both
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let tree = real_output(&dir, &["rev-parse", "HEAD^{tree}"])?;
+    dir.git()
+        .args([
+            "update-ref",
+            "refs/tags/light",
+            tree.trim_end(),
+            head.trim_end(),
+        ])
+        .assert()
+        .success();
+    assert_eq!(real_output(&dir, &["rev-parse", "light"])?, tree);
+    dir.git()
+        .args(["update-ref", "-d", "refs/tags/light"])
+        .assert()
+        .success();
+    dir.git().args(["show-ref", "light"]).assert().failure();
+    assert!(!std::fs::read_to_string(dir.subpath(".git/packed-refs"))?.contains("light"));
+
+    Ok(())
+}
+
+#[test]
+fn commit() -> anyhow::Result<()> {
+    let dir = worktree()?;
+    dir.git().args(["add", "."]).assert().success();
+    dir.git()
+        .args(["commit", "-m", "initial"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(
+            r"^\[(main|master) \(root-commit\) [0-9a-f]{7}\] initial\n$",
+        )?);
+    let first = real_output(&dir, &["rev-parse", "HEAD"])?;
+    assert_eq!(
+        real_output(&dir, &["log", "--format=%s%x00%P"])?,
+        "initial\0\n"
+    );
+    assert_eq!(
+        real_output(&dir, &["rev-parse", "HEAD^{tree}"])?,
+        real_output(&dir, &["write-tree"])?
+    );
+
+    dir.git().args(["commit", "-m", "again"]).assert().failure();
+    dir.git()
+        .args(["commit", "--allow-empty", "-m", "empty"])
+        .assert()
+        .success();
+
+    writeln!(
+        File::create(dir.subpath("message"))?,
+        "from a file\n\nbody  "
+    )?;
+    dir.git().args(["add", "message"]).assert().success();
+    dir.git()
+        .args(["commit", "-F", "message"])
+        .assert()
+        .success();
+    assert_eq!(
+        real_output(&dir, &["log", "-1", "--format=%B"])?,
+        "from a file\n\nbody\n\n"
+    );
+
+    // the editor sees the old message when amending
+    dir.git()
+        .args(["commit", "--amend"])
+        .env("GIT_EDITOR", "sed -i 's/from a file/edited/'")
+        .assert()
+        .success();
+    assert_eq!(
+        real_output(&dir, &["log", "--format=%s"])?,
+        "edited\nempty\ninitial\n"
+    );
+    dir.git()
+        .args(["commit", "--allow-empty"])
+        .env("GIT_EDITOR", "true")
+        .assert()
+        .failure();
+
+    dir.cmd("git")
+        .args(["checkout", "--detach"])
+        .silence()
+        .status()?;
+    dir.git()
+        .args(["commit", "--allow-empty", "-m", "detached"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[detached HEAD "));
+    assert_eq!(
+        real_output(&dir, &["log", "--format=%s", "HEAD~3"])?,
+        "initial\n"
+    );
+    assert_eq!(real_output(&dir, &["rev-parse", "HEAD~3"])?, first);
+
+    Ok(())
+}
+
+#[test]
+fn commit_tree_identity() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    let tree = real_output(&dir, &["rev-parse", "HEAD^{tree}"])?;
+    let tree = tree.trim_end();
+
+    let args = ["commit-tree", "-m", "subject", "-m", "body", tree];
+    dir.git()
+        .args(args)
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(real_output(&dir, &args)?));
+
+    // identity from the config, dates in other formats
+    let config = "[user]\n\tname = Config User\n\temail = config@example.com\n";
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(dir.subpath(".git/config"))?
+        .write_all(config.as_bytes())?;
+    let run = |cmd: &mut Command| {
+        cmd.args(["commit-tree", "-m", "config", tree])
+            .env_remove("GIT_AUTHOR_NAME")
+            .env_remove("GIT_AUTHOR_EMAIL")
+            .env("GIT_AUTHOR_DATE", "Thu, 07 Apr 2005 22:13:13 +0200")
+            .env("GIT_COMMITTER_DATE", "2005-04-07T22:13:13.5-03:30")
+            .output()
+    };
+    let expected = run(&mut dir.real_git())?;
+    let got = run(&mut dir.git())?;
+    assert!(got.status.success());
+    assert_eq!(got.stdout, expected.stdout);
+
+    let expected = dir
+        .real_git()
+        .args(["commit-tree", "-m", "override", tree])
+        .env("GIT_AUTHOR_NAME", "Someone Else")
+        .env("GIT_AUTHOR_EMAIL", "else@example.com")
+        .output()?;
+    dir.git()
+        .args(["commit-tree", "--author", "Someone Else <else@example.com>"])
+        .args(["-m", "override", tree])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(String::from_utf8(expected.stdout)?));
+
+    dir.git()
+        .args(["commit-tree", "-m", "bad date", tree])
+        .env("GIT_AUTHOR_DATE", "not a date")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn config() -> anyhow::Result<()> {
+    let dir = make_dir();
+    dir.real_git().arg("init").silence().status()?;
+    let home = dir.path().to_str().unwrap();
+    let extra = "[include]\n\tpath = ~/extra\n[includeIf \"gitdir:nowhere/\"]\n\tpath = missing\n";
+    std::fs::write(dir.subpath(".gitconfig"), extra)?;
+    std::fs::write(dir.subpath("extra"), "[x]\n\tsize = 1k\n\tflag\n")?;
+
+    // each change is made by both, starting from the same file
+    let steps: &[&[&str]] = &[
+        &["config", "user.name", "A U Thor"],
+        &["config", "Foo.Bar", "v"],
+        &["config", "new.sub.key", "  spaced # x"],
+        &["config", "--add", "a.m", "1"],
+        &["config", "--add", "a.m", "2"],
+        &["config", "a.m", "3"],
+        &["config", "--unset", "a.m"],
+        &["config", "--get", "missing.key"],
+        &["config", "nosection"],
+        &["config", "a.1x"],
+        &["config", "--get-all", "a.m"],
+        &["config", "--type=int", "--get", "x.size"],
+        &["config", "--type=bool", "--get", "x.flag"],
+        &["config", "--show-origin", "--get", "user.name"],
+        &["config", "--unset-all", "a.m"],
+        &["config", "--unset", "user.name"],
+        &["config", "--list", "--show-origin"],
+    ];
+    let config = dir.subpath(".git/config");
+    for args in steps {
+        let before = std::fs::read(&config)?;
+        let run = |mut cmd: Command| {
+            cmd.args(*args)
+                .env("HOME", home)
+                .env("GIT_CONFIG_NOSYSTEM", "1")
+                .output()
+        };
+        // there is no `--add` yet, so real git makes the multivar
+        let got = match args[1] {
+            "--add" => run(dir.real_git())?,
+            _ => run(dir.git())?,
+        };
+        let ours = std::fs::read(&config)?;
+        std::fs::write(&config, &before)?;
+        let expected = run(dir.real_git())?;
+        assert_eq!(got.status.code(), expected.status.code(), "{args:?}");
+        assert_eq!(
+            String::from_utf8(got.stdout)?,
+            String::from_utf8(expected.stdout)?,
+            "{args:?}"
+        );
+        assert_eq!(ours, std::fs::read(&config)?, "{args:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn discovery() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    std::fs::create_dir_all(dir.subpath("sub/deep"))?;
+    std::fs::write(dir.subpath("sub/deep/f"), "deep\n")?;
+    std::fs::write(dir.subpath("sub/g"), "g\n")?;
+
+    // paths are relative to where the command is started
+    dir.git()
+        .current_dir(dir.subpath("sub/deep"))
+        .args(["add", "f", "../g"])
+        .assert()
+        .success();
+    dir.git()
+        .args(["-C", "sub", "-C", "deep", "hash-object", "-w", "f"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(real_output(
+            &dir,
+            &["hash-object", "sub/deep/f"],
+        )?));
+    let files = real_output(&dir, &["ls-files", "sub"])?;
+    assert_eq!(files, "sub/deep/f\nsub/g\n");
+
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    let show_head = |cmd: &mut Command| {
+        cmd.args(["show-ref", "--head", "-s", "HEAD"])
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(head.clone()));
+    };
+    show_head(dir.git().current_dir(dir.subpath("sub/deep")));
+    show_head(
+        dir.git()
+            .current_dir(dir.subpath("sub"))
+            .arg("--git-dir")
+            .arg(dir.subpath(".git")),
+    );
+    show_head(
+        dir.git()
+            .current_dir("/")
+            .env("GIT_DIR", dir.subpath(".git")),
+    );
+
+    // a .git file pointing elsewhere
+    std::fs::create_dir(dir.subpath("linked"))?;
+    std::fs::write(dir.subpath("linked/.git"), "gitdir: ../.git\n")?;
+    show_head(dir.git().current_dir(dir.subpath("linked")));
+
+    // a bare repository, which has no worktree
+    dir.real_git()
+        .args(["clone", "-q", "--bare", ".", "bare.git"])
+        .status()?;
+    show_head(dir.git().current_dir(dir.subpath("bare.git/refs")));
+    dir.git()
+        .current_dir(dir.subpath("bare.git"))
+        .args(["add", "."])
+        .assert()
+        .failure();
+
+    // the search stops below a ceiling directory
+    dir.git()
+        .current_dir(dir.subpath("sub"))
+        .env("GIT_CEILING_DIRECTORIES", dir.path())
+        .args(["show-ref", "--head"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn init_options() -> anyhow::Result<()> {
+    let dir = make_dir();
+    std::fs::write(
+        dir.subpath(".gitconfig"),
+        "[init]\n\tdefaultBranch = trunk\n",
+    )?;
+    let home = dir.path().to_str().unwrap();
+    let cases: &[&[&str]] = &[
+        &["init", "plain"],
+        &["init", "--bare", "bare.git"],
+        &["init", "-b", "topic", "--shared=group", "shared"],
+        &["init", "--template=", "--shared=0640", "no-template"],
+        &["init", "--object-format=sha1", "sha1"],
+    ];
+    // the same listing of files and their permissions, config and HEAD
+    let listing = |root: &Path| -> anyhow::Result<String> {
+        let mut out = String::new();
+        for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+            let entry = entry?;
+            let mode = entry.metadata()?.permissions().mode();
+            let path = entry.path().strip_prefix(root)?;
+            out.push_str(&format!("{mode:o} {}\n", path.display()));
+            if matches!(
+                path.file_name().and_then(|f| f.to_str()),
+                Some("config" | "HEAD")
+            ) {
+                out.push_str(&std::fs::read_to_string(entry.path())?);
+            }
+        }
+        Ok(out)
+    };
+    for (i, args) in cases.iter().enumerate() {
+        let ours = dir.subpath(format!("ours{i}"));
+        let theirs = dir.subpath(format!("theirs{i}"));
+        for (mut cmd, path) in [(dir.git(), &ours), (dir.real_git(), &theirs)] {
+            std::fs::create_dir(path)?;
+            cmd.current_dir(path)
+                .args(*args)
+                .arg("-q")
+                .env("HOME", home)
+                .env("GIT_CONFIG_NOSYSTEM", "1")
+                .assert()
+                .success();
+        }
+        assert_eq!(listing(&ours)?, listing(&theirs)?, "{args:?}");
+    }
+
+    // reinitializing keeps HEAD and says so
+    let plain = dir.subpath("ours0/plain");
+    dir.git()
+        .current_dir(&plain)
+        .args(["init", "-b", "other"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(format!(
+            "Reinitialized existing Git repository in {}/\n",
+            plain.join(".git").canonicalize()?.display()
+        )))
+        .stderr(predicate::str::contains("ignored --initial-branch=other"));
+    file_contents(
+        plain.join(".git/HEAD"),
+        predicate::str::diff("ref: refs/heads/trunk\n"),
+    );
+
+    dir.git()
+        .args(["init", "-b", "a..b", "bad"])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["init", "--shared=bogus", "bad"])
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn packed_objects() -> anyhow::Result<()> {
+    let dir = repo_with_history()?;
+    let tree = real_output(&dir, &["rev-parse", "HEAD~3^{tree}"])?;
+    dir.real_git().args(["repack", "-adq"]).status()?;
+
+    for index_version in ["2", "1", "2,0x100"] {
+        let pack = walkdir::WalkDir::new(dir.subpath(".git/objects/pack"))
+            .into_iter()
+            .filter_map(Result::ok)
+            .find(|entry| entry.path().extension().is_some_and(|e| e == "pack"))
+            .unwrap();
+        dir.real_git()
+            .args(["index-pack", "--index-version", index_version])
+            .arg(pack.path())
+            .silence()
+            .status()?;
+
+        for args in [
+            vec!["--batch", "--batch-all-objects"],
+            vec![
+                "--batch-check=%(objectname) %(objecttype) %(objectsize) %(objectsize:disk) %(deltabase)",
+                "--batch-all-objects",
+            ],
+        ] {
+            let expected = dir.real_git().arg("cat-file").args(&args).output()?;
+            dir.git()
+                .arg("cat-file")
+                .args(&args)
+                .assert()
+                .success()
+                .stdout(predicate::eq(expected.stdout));
+        }
+        dir.git()
+            .args(["ls-tree", tree.trim_end()])
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(
+                &dir,
+                &["ls-tree", tree.trim_end()],
+            )?));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn pack_objects() -> anyhow::Result<()> {
+    let dir = repo_with_history()?;
+    let objects = real_output(&dir, &["rev-list", "--objects", "--all"])?;
+
+    for args in [vec![], vec!["--delta-base-offset", "--depth=2"]] {
+        let output = AssertCommand::from_std(dir.git())
+            .arg("pack-objects")
+            .args(&args)
+            .arg("out")
+            .write_stdin(objects.as_bytes())
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let checksum = String::from_utf8(output)?;
+        let base = format!("out-{}", checksum.trim_end());
+
+        let verify = dir
+            .real_git()
+            .args(["verify-pack", "-v", &format!("{base}.idx")])
+            .output()?;
+        assert!(verify.status.success());
+        let verify = String::from_utf8(verify.stdout)?;
+        assert!(verify.contains("chain length = 1:"), "{verify}");
+        assert!(!verify.contains("chain length = 3:") || args.is_empty());
+
+        // the index is the same as the one git builds for the pack
+        dir.real_git()
+            .args(["index-pack", "-o", "check.idx", &format!("{base}.pack")])
+            .silence()
+            .status()?;
+        assert_eq!(
+            std::fs::read(dir.subpath("check.idx"))?,
+            std::fs::read(dir.subpath(format!("{base}.idx")))?
+        );
+    }
+
+    // only what is reachable from the revisions and not from the excluded ones
+    let old = real_output(&dir, &["rev-parse", "HEAD~5"])?;
+    let input = format!("HEAD\n^{old}");
+    let pack = AssertCommand::from_std(dir.git())
+        .args(["pack-objects", "--revs", "--stdout"])
+        .write_stdin(input.as_bytes())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    std::fs::write(dir.subpath("revs.pack"), pack)?;
+    let status = dir
+        .real_git()
+        .args(["index-pack", "revs.pack"])
+        .silence()
+        .status()?;
+    assert!(status.success());
+    let listed = real_output(&dir, &["verify-pack", "-v", "revs.idx"])?;
+    let mut packed: Vec<&str> = listed
+        .lines()
+        .filter(|line| line.len() > 40 && line.as_bytes()[40] == b' ')
+        .map(|line| &line[..40])
+        .collect();
+    packed.sort();
+    let expected = real_output(
+        &dir,
+        &["rev-list", "--objects", "HEAD", "--not", old.trim_end()],
+    )?;
+    let mut expected: Vec<&str> = expected.lines().map(|line| &line[..40]).collect();
+    expected.sort();
+    assert_eq!(packed, expected);
+
+    Ok(())
+}
+
+#[test]
+fn index_pack() -> anyhow::Result<()> {
+    let dir = repo_with_history()?;
+    let pack = |revs: &str| -> anyhow::Result<Vec<u8>> {
+        let output = AssertCommand::from_std(dir.real_git())
+            .args(["pack-objects", "--revs", "--thin", "--stdout"])
+            .write_stdin(revs)
+            .output()?;
+        Ok(output.stdout)
+    };
+    std::fs::write(dir.subpath("all.pack"), pack("HEAD\n")?)?;
+
+    dir.git()
+        .args(["index-pack", "all.pack"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(real_output(
+            &dir,
+            &["index-pack", "-o", "check.idx", "all.pack"],
+        )?));
+    assert_eq!(
+        std::fs::read(dir.subpath("all.idx"))?,
+        std::fs::read(dir.subpath("check.idx"))?
+    );
+    let mut corrupt = std::fs::read(dir.subpath("all.pack"))?;
+    corrupt[100] ^= 1;
+    std::fs::write(dir.subpath("corrupt.pack"), corrupt)?;
+    dir.git()
+        .args(["index-pack", "corrupt.pack"])
+        .assert()
+        .failure();
+
+    // the history up to HEAD~1 as loose objects, then a thin pack of the last commit
+    let other = make_dir();
+    other.real_git().arg("init").silence().status()?;
+    AssertCommand::from_std(other.git())
+        .arg("unpack-objects")
+        .write_stdin(pack("HEAD~1\n")?)
+        .assert()
+        .success();
+    let objects = real_output(
+        &other,
+        &["cat-file", "--batch-all-objects", "--batch-check"],
+    )?;
+    let expected = real_output(&dir, &["rev-list", "--objects", "HEAD~1"])?;
+    assert_eq!(objects.lines().count(), expected.lines().count());
+
+    let thin = pack("HEAD\n^HEAD~1\n")?;
+    AssertCommand::from_std(other.git())
+        .args(["index-pack", "--stdin"])
+        .write_stdin(thin.clone())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unresolved deltas"));
+    AssertCommand::from_std(other.git())
+        .args(["index-pack", "--stdin", "--fix-thin"])
+        .write_stdin(thin)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("pack\t"));
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    other
+        .real_git()
+        .args(["update-ref", "HEAD", head.trim_end()])
+        .status()?;
+    let fsck = other.real_git().args(["fsck", "--strict"]).output()?;
+    assert!(fsck.status.success(), "{fsck:?}");
+    let packs = std::fs::read_dir(other.subpath(".git/objects/pack"))?;
+    for entry in packs {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "idx") {
+            let status = other.real_git().arg("verify-pack").arg(&path).status()?;
+            assert!(status.success());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn rev_parse() -> anyhow::Result<()> {
+    let dir = repo_with_history()?;
+    for args in [
+        &["branch", "side", "HEAD~5"][..],
+        &["tag", "-a", "v1", "-m", "release", "HEAD~2"],
+        &["config", "branch.master.remote", "."],
+        &["config", "branch.master.merge", "refs/heads/side"],
+    ] {
+        dir.real_git().args(args).silence().status()?;
+    }
+    let head = real_output(&dir, &["rev-parse", "HEAD"])?;
+    for spec in [
+        "HEAD",
+        "@",
+        "master",
+        "HEAD~3",
+        "HEAD^",
+        "HEAD^0",
+        "HEAD^{tree}",
+        "v1",
+        "v1^{}",
+        "v1^{commit}",
+        "v1~2^{tree}",
+        "HEAD:file0",
+        "HEAD~4:dir1/file1",
+        "HEAD:",
+        ":file0",
+        "@{upstream}",
+        ":/change 7",
+        "HEAD^{/change 1}",
+        "side..HEAD",
+        "..side",
+        "side...HEAD",
+        "^side",
+        &head[..8],
+        &format!("{}~2", &head[..8]),
+    ] {
+        dir.git()
+            .args(["rev-parse", spec])
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(
+                &dir,
+                &["rev-parse", spec],
+            )?));
+    }
+    dir.git().args(["rev-parse", "nope"]).assert().failure();
+    dir.git()
+        .args(["rev-parse", "HEAD:nope"])
+        .assert()
+        .failure();
+    dir.git()
+        .args(["rev-parse", "--verify", "-q", "nope"])
+        .assert()
+        .failure()
+        .stdout("");
+
+    // abbreviations are long enough to not be ambiguous
+    let objects = real_output(&dir, &["rev-list", "--objects", "--all"])?;
+    for line in objects.lines() {
+        let args = ["rev-parse", "--short=4", &line[..40]];
+        dir.git()
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(&dir, &args)?));
+    }
+
+    // other commands take revisions
+    for args in [
+        &["cat-file", "-p", "HEAD~2:file0"][..],
+        &["cat-file", "-t", "v1"],
+        &["ls-tree", "HEAD~3"],
+        &["ls-tree", "v1"],
+    ] {
+        dir.git()
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(&dir, args)?));
+    }
+    let commit = dir
+        .git()
+        .args(["commit-tree", "-m", "merge", "-p", "HEAD", "-p", "side"])
+        .arg("HEAD^{tree}")
+        .output()?;
+    let commit = String::from_utf8(commit.stdout)?;
+    let parents = real_output(&dir, &["rev-parse", &format!("{}^@", commit.trim_end())])?;
+    assert_eq!(parents, real_output(&dir, &["rev-parse", "HEAD", "side"])?);
+
+    Ok(())
+}
+
+/// a repository created by real git, where `topic` branches off master and is merged back,
+/// with commit dates that interleave the two lines of history
+fn repo_with_branches() -> anyhow::Result<common::Temp> {
+    let dir = repo_with_commit()?;
+    let mut time = 1112912053;
+    let mut commit = |branch: &str, name: &str, step: i64| -> anyhow::Result<()> {
+        time += step;
+        dir.real_git().args(["checkout", "-q", branch]).status()?;
+        std::fs::write(dir.subpath(name), name)?;
+        dir.real_git().args(["add", name]).status()?;
+        dir.real_git()
+            .args(["commit", "-qm", name])
+            .env("GIT_COMMITTER_DATE", format!("{time} +0000"))
+            .status()?;
+        Ok(())
+    };
+    commit("master", "base", 100)?;
+    dir.real_git().args(["branch", "topic"]).status()?;
+    commit("topic", "t1", 100)?;
+    commit("topic", "t2", 100)?;
+    commit("master", "m1", 100)?;
+    commit("master", "m2", 0)?;
+    commit("topic", "t3", -50)?;
+    dir.real_git().args(["checkout", "-q", "master"]).status()?;
+    dir.real_git()
+        .args(["merge", "-q", "--no-edit", "topic"])
+        .env("GIT_COMMITTER_DATE", "1112912503 +0000")
+        .status()?;
+    commit("master", "m3", 200)?;
+    commit("master", "m4", 0)?;
+    dir.real_git()
+        .args(["tag", "-a", "v1", "-m", "release", "HEAD~1"])
+        .status()?;
+    Ok(dir)
+}
+
+#[test]
+fn rev_list() -> anyhow::Result<()> {
+    let dir = repo_with_branches()?;
+    for args in [
+        &["HEAD"][..],
+        &["--all"],
+        &["v1"],
+        &["--topo-order", "HEAD"],
+        &["--date-order", "HEAD"],
+        &["--topo-order", "--reverse", "HEAD", "topic"],
+        &["--reverse", "-n", "3", "HEAD"],
+        &["--max-count=2", "HEAD"],
+        &["--first-parent", "HEAD"],
+        &["--first-parent", "--topo-order", "--all"],
+        &["--merges", "HEAD"],
+        &["--no-merges", "HEAD"],
+        &["topic..master"],
+        &["master", "^topic"],
+        &["topic...master"],
+        &["--ancestry-path", "topic~1..HEAD"],
+        &["--since=1112912600", "HEAD"],
+        &["--until=1112912400", "HEAD"],
+        &["--topo-order", "--until=1112912460", "--all"],
+        &["--date-order", "--until=1112912460", "--all"],
+        &["--count", "topic..HEAD"],
+        &["--objects", "HEAD"],
+        &["--objects", "v1", "^HEAD~3"],
+        &["--objects", "topic..master"],
+        &["--objects", "master", "^topic^"],
+        &["--objects", "--all"],
+    ] {
+        dir.git()
+            .arg("rev-list")
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(
+                &dir,
+                &[&["rev-list"], args].concat(),
+            )?));
+    }
+    dir.git().args(["rev-list", "nope"]).assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn log() -> anyhow::Result<()> {
+    let dir = repo_with_branches()?;
+    dir.real_git()
+        .args([
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "subject\nline\n\n\tbody\n\nmore",
+        ])
+        .status()?;
+    dir.real_git().args(["tag", "v0", "HEAD~3"]).status()?;
+    for args in [
+        &[][..],
+        &["--oneline"],
+        &["--pretty=short", "topic"],
+        &["--pretty=full", "-n", "2"],
+        &["--pretty=fuller", "--date=iso"],
+        &["--pretty=raw", "-n", "3"],
+        &["--pretty=oneline", "--abbrev-commit", "--reverse"],
+        &["--format=%H %h %T %t %P %p%n%an <%ae> %ad %at %ai %aI %as%n%cn %ce %cd%n%s"],
+        &["--format=[%b] [%B] %%%x41 %q %+s%-b% e", "-n", "3"],
+        &["--pretty=format:%h %d %D", "--all"],
+        &["--decorate", "--oneline", "--all"],
+        &["--decorate=full", "-n", "2"],
+        &["--no-decorate", "--oneline"],
+        &["--date=rfc", "-n", "1"],
+        &["--date=unix", "-n", "1"],
+        &["--date=short", "-n", "1"],
+        &["--date=raw", "-n", "1"],
+        &["--date=iso-strict", "-n", "1"],
+        &["--date=format:%Y/%m/%d %H.%M", "-n", "1"],
+        &["--graph"],
+        &["--graph", "--oneline", "--all"],
+        &["--graph", "--oneline", "-n", "4"],
+        &["--graph", "--format=%s"],
+        &["--graph", "--pretty=format:%s%n%b"],
+        &["--graph", "--date-order", "--oneline"],
+        &["--graph", "--first-parent", "--oneline"],
+        &["--oneline", "topic..master"],
+        &["--oneline", "--", "t1", "m2"],
+        &["--oneline", "--graph", "--", "t1", "m2"],
+        &["--oneline", "--graph", "--", "base"],
+        &["--stat"],
+    ] {
+        let real = real_output(&dir, &[&["log"], args].concat())?;
+        let assert = dir.git().arg("log").args(args).assert();
+        match args.contains(&"--stat") {
+            true => assert.failure(),
+            false => assert.success().stdout(predicate::str::diff(real)),
+        };
+    }
+    dir.git()
+        .args(["log", "--oneline", "t1", "base"])
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(real_output(
+            &dir,
+            &["log", "--oneline", "t1", "base"],
+        )?));
+    dir.git().args(["log", "nope"]).assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn merge_base() -> anyhow::Result<()> {
+    let dir = repo_with_branches()?;
+    // a criss-cross merge has two best common ancestors
+    for (branch, start, other) in [
+        ("cross1", "master~3", "topic~1"),
+        ("cross2", "topic~1", "master~3"),
+    ] {
+        dir.real_git()
+            .args(["checkout", "-q", "-b", branch, start])
+            .status()?;
+        dir.real_git()
+            .args(["merge", "-q", "--no-edit", other])
+            .status()?;
+    }
+    dir.real_git()
+        .args(["checkout", "-q", "--orphan", "lonely"])
+        .status()?;
+    dir.real_git()
+        .args(["commit", "-q", "--allow-empty", "-m", "lonely"])
+        .status()?;
+    dir.real_git()
+        .args(["checkout", "-q", "-f", "master"])
+        .status()?;
+
+    let cases = [
+        &["master", "topic"][..],
+        &["topic", "master"],
+        &["--all", "cross1", "cross2"],
+        &["cross1", "cross2"],
+        &["master~4", "master"],
+        &["--all", "master", "cross1", "cross2"],
+        &["--octopus", "master", "topic", "cross1"],
+        &["--octopus", "--all", "cross1", "cross2"],
+        &[
+            "--independent",
+            "master",
+            "topic",
+            "cross1",
+            "master~2",
+            "topic",
+        ],
+        &["--independent", "v1", "master"],
+    ];
+    let check = || -> anyhow::Result<()> {
+        for args in cases {
+            dir.git()
+                .arg("merge-base")
+                .args(args)
+                .assert()
+                .success()
+                .stdout(predicate::str::diff(real_output(
+                    &dir,
+                    &[&["merge-base"], args].concat(),
+                )?));
+        }
+        for (a, b, ancestor) in [
+            ("topic", "master", true),
+            ("master", "topic", false),
+            ("master", "master", true),
+            ("cross1", "cross2", false),
+        ] {
+            let assert = dir
+                .git()
+                .args(["merge-base", "--is-ancestor", a, b])
+                .assert();
+            match ancestor {
+                true => assert.success(),
+                false => assert.failure(),
+            };
+        }
+        Ok(())
+    };
+    check()?;
+    // with generation numbers from a commit-graph
+    dir.real_git()
+        .args(["commit-graph", "write", "--reachable"])
+        .status()?;
+    check()?;
+    // a corrupt commit-graph is ignored
+    let graph = dir.subpath(".git/objects/info/commit-graph");
+    let mut data = std::fs::read(&graph)?;
+    data[..4].copy_from_slice(b"XXXX");
+    // git writes the graph read-only
+    std::fs::remove_file(&graph)?;
+    std::fs::write(&graph, data)?;
+    check()?;
+    for args in [
+        &["rev-parse", "master...topic"][..],
+        &["rev-list", "master...topic"],
+    ] {
+        dir.git()
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(&dir, args)?));
+    }
+
+    dir.git()
+        .args(["merge-base", "lonely", "master"])
+        .assert()
+        .failure()
+        .stdout("");
+
+    // the fork point is found in the reflog after the upstream history was rewritten
+    dir.real_git()
+        .args(["checkout", "-q", "-b", "feature"])
+        .status()?;
+    dir.real_git()
+        .args(["commit", "-q", "--allow-empty", "-m", "feature"])
+        .status()?;
+    dir.real_git().args(["checkout", "-q", "master"]).status()?;
+    dir.real_git()
+        .args(["reset", "-q", "--hard", "HEAD~2"])
+        .status()?;
+    for args in [
+        &["master", "feature"][..],
+        &["topic", "feature"],
+        &["master"],
+    ] {
+        let args = [&["merge-base", "--fork-point"], args].concat();
+        let expected = dir.real_git().args(&args).output()?;
+        let assert = dir.git().args(&args).assert();
+        let assert = match expected.status.success() {
+            true => assert.success(),
+            false => assert.failure(),
+        };
+        assert.stdout(predicate::str::diff(String::from_utf8(expected.stdout)?));
+    }
+
+    // the same when the upstream is committed to and rewound by git-rs, which writes the reflog
+    dir.real_git()
+        .args(["checkout", "-q", "-b", "upstream"])
+        .status()?;
+    for message in ["u1", "u2"] {
+        dir.git()
+            .args(["commit", "--allow-empty", "-m", message])
+            .assert()
+            .success();
+    }
+    dir.real_git()
+        .args(["checkout", "-q", "-b", "downstream"])
+        .status()?;
+    dir.git()
+        .args(["commit", "--allow-empty", "-m", "d1"])
+        .assert()
+        .success();
+    dir.real_git()
+        .args(["checkout", "-q", "upstream"])
+        .status()?;
+    let rewound = real_output(&dir, &["rev-parse", "upstream~2"])?;
+    dir.git()
+        .args([
+            "update-ref",
+            "-m",
+            "rewind",
+            "refs/heads/upstream",
+            rewound.trim(),
+        ])
+        .assert()
+        .success();
+    dir.git()
+        .args(["commit", "--allow-empty", "-m", "u3"])
+        .assert()
+        .success();
+    let fork_point = real_output(&dir, &["rev-parse", "downstream~1"])?;
+    let args = ["merge-base", "--fork-point", "upstream", "downstream"];
+    assert_eq!(real_output(&dir, &args)?, fork_point);
+    dir.git()
+        .args(args)
+        .assert()
+        .success()
+        .stdout(predicate::str::diff(fork_point));
+
+    Ok(())
+}
+
+#[test]
+fn diff_tree() -> anyhow::Result<()> {
+    let dir = repo_with_commit()?;
+    // a file becomes a symlink, and files are added inside and next to a directory
+    std::fs::remove_file(dir.subpath("file0"))?;
+    std::os::unix::fs::symlink("dir1/file1", dir.subpath("file0"))?;
+    writeln!(File::create(dir.subpath("dir1/file1"))?, "changed")?;
+    create_dir(dir.subpath("dir1/sub"))?;
+    writeln!(File::create(dir.subpath("dir1/sub/deep"))?, "deep")?;
+    writeln!(File::create(dir.subpath("dir1.txt"))?, "next to dir1")?;
+    writeln!(File::create(dir.subpath("file2"))?, "two")?;
+    dir.real_git().args(["add", "-A"]).status()?;
+    dir.real_git().args(["commit", "-qm", "second"]).status()?;
+    // a directory becomes a file, and a file only changes its mode
+    std::fs::remove_dir_all(dir.subpath("dir1"))?;
+    writeln!(File::create(dir.subpath("dir1"))?, "a file now")?;
+    std::fs::set_permissions(
+        dir.subpath("file2"),
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )?;
+    dir.real_git().args(["add", "-A"]).status()?;
+    dir.real_git().args(["commit", "-qm", "third"]).status()?;
+    dir.real_git()
+        .args(["commit", "-q", "--allow-empty", "-m", "empty"])
+        .status()?;
+
+    for commit in ["HEAD", "HEAD~1", "HEAD~2", "HEAD~3"] {
+        for args in [
+            &[][..],
+            &["-r"],
+            &["--name-only"],
+            &["--name-status", "-r"],
+            &["-z"],
+            &["-z", "-r", "--name-status"],
+            &["--root", "-r"],
+        ] {
+            let args = [&["diff-tree"], args, &[commit]].concat();
+            dir.git()
+                .args(&args)
+                .assert()
+                .success()
+                .stdout(predicate::str::diff(real_output(&dir, &args)?));
+        }
+    }
+    for args in [
+        &["diff-tree", "HEAD~3", "HEAD"][..],
+        &["diff-tree", "-r", "HEAD~3^{tree}", "HEAD~1"],
+        &["diff-tree", "-r", "--name-only", "HEAD", "HEAD~3"],
+    ] {
+        dir.git()
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::diff(real_output(&dir, args)?));
+    }
+
+    // commits are read with their parents, or compared to other commits or trees given after them
+    let mut lines = real_output(&dir, &["rev-list", "--parents", "HEAD"])?;
+    lines.push_str(&real_output(&dir, &["rev-parse", "HEAD", "HEAD~3"])?.replace('\n', " "));
+    lines.push_str("\nnot an object\n");
+    let trees = real_output(&dir, &["rev-parse", "HEAD~3^{tree}", "HEAD^{tree}"])?;
+    lines.push_str(trees.trim_end().replace('\n', " ").as_str());
+    lines.push('\n');
+    for args in [
+        &["diff-tree", "--stdin"][..],
+        &["diff-tree", "--stdin", "-r", "--root"],
+        &["diff-tree", "--stdin", "-z", "--name-status"],
+    ] {
+        let expected = AssertCommand::from_std(dir.real_git())
+            .args(args)
+            .write_stdin(lines.as_bytes())
+            .output()?;
+        AssertCommand::from_std(dir.git())
+            .args(args)
+            .write_stdin(lines.as_bytes())
+            .assert()
+            .success()
+            .stdout(predicate::eq(expected.stdout));
+    }
+
+    Ok(())
+}